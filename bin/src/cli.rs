@@ -0,0 +1,66 @@
+use std::path::{Path, PathBuf};
+
+use hap_metadata::hap_metadata;
+use hap_metadata::metadata::HapMetadata;
+use lib::template::checker::{check_template, has_error, TemplateDiagnostic};
+use lib::template::hl_template::{HlDeviceTemplate, TemplateFormat};
+
+/// 命令行子命令,返回 None 时启动服务
+/// homelink check-templates [dir]  检查模板目录,有错误时退出码为1
+pub async fn run(args: &[String]) -> Option<anyhow::Result<i32>> {
+    match args.first().map(|s| s.as_str()) {
+        Some("check-templates") => {
+            let dir = args.get(1).map(|s| s.as_str()).unwrap_or("templates");
+            Some(check_templates(PathBuf::from(dir)).await)
+        }
+        _ => None,
+    }
+}
+
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> anyhow::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_files(path.as_path(), files)?;
+        } else if TemplateFormat::from_path(path.as_path()).is_some() {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn check_file(meta: &HapMetadata, path: &Path) -> anyhow::Result<Vec<TemplateDiagnostic>> {
+    let format = TemplateFormat::from_path(path)
+        .ok_or(anyhow::anyhow!("不支持的模板格式"))?;
+    let content = std::fs::read_to_string(path)?;
+    let template: HlDeviceTemplate = format.parse(content.as_str())?;
+    Ok(check_template(meta, &template))
+}
+
+async fn check_templates(dir: PathBuf) -> anyhow::Result<i32> {
+    // 注册模型扩展
+    crate::init_integration().await?;
+    let meta = hap_metadata()?;
+    let mut files = vec![];
+    collect_files(dir.as_path(), &mut files)?;
+    files.sort();
+    let mut failed = 0;
+    for file in files.iter() {
+        match check_file(&meta, file.as_path()) {
+            Ok(diagnostics) => {
+                if has_error(&diagnostics) {
+                    failed += 1;
+                }
+                for d in diagnostics {
+                    println!("{}: {:?} {}: {}", file.display(), d.level, d.path, d.message);
+                }
+            }
+            Err(e) => {
+                failed += 1;
+                println!("{}: Error 解析失败: {:?}", file.display(), e);
+            }
+        }
+    }
+    println!("检查模板:{}个,失败:{}个", files.len(), failed);
+    Ok(if failed > 0 { 1 } else { 0 })
+}
@@ -6,11 +6,14 @@ use log::{error, info};
 use tokio::sync::{Mutex, oneshot};
 use tower_http::services::ServeDir;
 use hl_integration::integration::HlSourceIntegrator;
+use lib::api::auth::TokenKeys;
 use lib::api::router;
 use lib::api::state::{AppState, ServerShutdownSignal};
 use lib::config::cfgs::Configs;
 use lib::config::context::{APP_CONTEXT, ApplicationContext, get_app_context};
+use lib::config::secret::init_master_key;
 use lib::db::init::{db_conn, migrator_up};
+use lib::hap::network;
 use lib::init::manager::device_manager::IotDeviceManager;
 use lib::init::manager::mi_account_manager::MiAccountManager;
 use lib::init::manager::template_manager::TemplateManager;
@@ -18,11 +21,18 @@ use lib::init::{logger_init, Managers};
 // use lib::js_engine::context::EnvContext;
 // use lib::js_engine::init_js_engine::init_js_engine;
 use hap_metadata::hap_metadata;
-use lib::init::hap_init::init_hap_list;
+use lib::init::hap_init::{init_hap_list, sync_device_health};
+use lib::init::manager::mqtt_export_manager::MqttExportManager;
+use lib::service::bind_key_service::load_bind_keys;
+use lib::service::sys_user_service::init_admin_user;
 use lib::init::manager::ble_manager::BleManager;
+use lib::init::manager::rule_manager::RuleManager;
 use lib::socketio::socket_io_layer;
 use target_hap::hap_manager::HapManage;
 use xiaomi_integration::integration::XiaomiIntegration;
+use mqtt_integration::integration::MqttIntegration;
+
+mod cli;
 
 
 /// 先创建http服务
@@ -33,27 +43,50 @@ use xiaomi_integration::integration::XiaomiIntegration;
 async fn main() -> anyhow::Result<()> {
     // 初始化默认日志
     logger_init::init_logger();
+    // 子命令
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some(code) = cli::run(&args).await {
+        std::process::exit(code?);
+    }
 
     //读取配置
     let config = Configs::init();
     let addr = config.server.address.clone();
+    // 主密钥,迁移时需要用到
+    init_master_key(&config)?;
+    // 校验 hap 网卡配置
+    network::validate(&config.hap)?;
 
     // let addr = SocketAddr::new([0, 0, 0, 0].into(), 5514);
     let conn = db_conn(&config.server).await;
     //数据库版本迁移
     migrator_up(&conn).await;
+    // 初始化管理员
+    init_admin_user(&conn, &config.auth).await?;
     let ble_manager = BleManager::new();
     ble_manager.init().await;
+    // 加载加密蓝牙设备的 bindkey
+    if let Err(e) = load_bind_keys(&conn, &ble_manager).await {
+        error!("加载蓝牙bindkey失败:{:?}", e);
+    }
     let hap_metadata = Arc::new(hap_metadata()?);
     let mi_account_manager = MiAccountManager::new(conn.clone());
     let hap_manager = HapManage::new();
-    let template_manager = TemplateManager::new(conn.clone(), hap_manager.clone());
     // 初始化hap 服务器
     let device_manager = IotDeviceManager::new(conn.clone(),
                                                mi_account_manager.clone(),
                                                ble_manager.clone());
     // 初始化iot设备
     device_manager.init().await?;
+    device_manager.start_health_check();
+    let mqtt_export_manager = MqttExportManager::new(conn.clone(), hap_manager.clone(), device_manager.clone());
+    let template_manager = TemplateManager::new(conn.clone(), hap_manager.clone(), device_manager.clone(),
+                                                mqtt_export_manager.clone(), ble_manager.clone(),
+                                                hap_metadata.clone());
+    let rule_manager = RuleManager::new(conn.clone(),
+                                        device_manager.clone(),
+                                        ble_manager.clone(),
+                                        config.location.clone());
 
 
     let app_state = AppState::new(conn.clone(), Managers {
@@ -63,7 +96,9 @@ async fn main() -> anyhow::Result<()> {
         mi_account_manager: mi_account_manager.clone(),
         template_manager: template_manager.clone(),
         ble_manager: ble_manager.clone(),
-    });
+        rule_manager: rule_manager.clone(),
+        mqtt_export_manager: mqtt_export_manager.clone(),
+    }, TokenKeys::new(&config.auth));
     // let schema = schema(conn.clone(), None, None)?;
 
 
@@ -71,7 +106,8 @@ async fn main() -> anyhow::Result<()> {
         // .route("/playground", get(graphql_playground))
         // .route("/graphql", post(graphql_handler))
         .nest_service("/", ServeDir::new("dist/"))
-        .nest("/api", router::api())
+        .nest("/api", router::api(app_state.clone()))
+        .merge(router::metrics())
         .with_state(app_state.clone())
         .layer(socket_io_layer(app_state.clone()));
 
@@ -92,9 +128,18 @@ async fn main() -> anyhow::Result<()> {
     //初始化集成
     init_integration().await?;
     init_hap_list(&conn, hap_manager.clone(), device_manager.clone()).await?;
+    sync_device_health(device_manager.clone(), hap_manager.clone());
+    // mqtt 导出
+    if let Some(mqtt_config) = context.config.mqtt_export.clone() {
+        if let Err(e) = mqtt_export_manager.init(mqtt_config).await {
+            error!("mqtt 导出启动失败:{:?}", e);
+        }
+    }
     // 初始化hap设备
     // 初始化模板
     template_manager.init().await?;
+    // 自动化规则
+    rule_manager.init().await?;
     info!("api_server start at :{:?}", addr);
     let (api_server_ch_send, api_server_ch) = oneshot::channel::<()>();
     let shutdown_signal = ServerShutdownSignal(Arc::new(Mutex::new(Some(api_server_ch_send))));
@@ -122,6 +167,9 @@ async fn main() -> anyhow::Result<()> {
 async fn init_integration() -> anyhow::Result<()> {
     let integration = XiaomiIntegration {};
     integration.init()?;
+    let integration = MqttIntegration {};
+    integration.init()?;
+    lib::hap::scene_switch::register_model_ext()?;
     Ok(())
 }
 
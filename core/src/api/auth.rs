@@ -0,0 +1,112 @@
+use std::time::Duration;
+
+use axum::extract::{Request, State};
+use axum::http::header;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use jsonwebtoken::{DecodingKey, EncodingKey, Header, Validation};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::api::errors::{ApiError, ApiErrorInner};
+use crate::api::state::AppState;
+use crate::config::cfgs::Auth;
+use crate::db::entity::prelude::SysUserModel;
+use crate::service::sys_user_service::{check_token_user, random_string, token_version};
+
+/// 未登入的响应码
+pub const UNAUTHORIZED_CODE: i32 = 401;
+
+/// token 中的用户信息
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Claims {
+    /// 用户id
+    pub sub: i64,
+    pub username: String,
+    /// 过期时间(秒)
+    pub exp: u64,
+    /// 签发时的密码版本,修改密码后旧 token 失效
+    #[serde(default)]
+    pub ver: String,
+}
+
+/// token 签名密钥
+pub struct TokenKeys {
+    encoding: EncodingKey,
+    decoding: DecodingKey,
+    expire: Duration,
+}
+
+impl TokenKeys {
+    pub fn new(auth: &Auth) -> Self {
+        let secret = match auth.token_secret() {
+            Some(s) => s,
+            None => {
+                warn!("未配置token_secret,使用随机密钥,重启后需要重新登入");
+                random_string(32)
+            }
+        };
+        Self {
+            encoding: EncodingKey::from_secret(secret.as_bytes()),
+            decoding: DecodingKey::from_secret(secret.as_bytes()),
+            expire: Duration::from_secs(auth.token_expire_hours * 3600),
+        }
+    }
+
+    pub fn encode(&self, user: &SysUserModel) -> anyhow::Result<String> {
+        let exp = chrono::Utc::now().timestamp() as u64 + self.expire.as_secs();
+        let claims = Claims {
+            sub: user.user_id,
+            username: user.username.clone(),
+            exp,
+            ver: token_version(user),
+        };
+        Ok(jsonwebtoken::encode(&Header::default(), &claims, &self.encoding)?)
+    }
+
+    /// 校验token 签名与有效期
+    pub fn decode(&self, token: &str) -> anyhow::Result<Claims> {
+        let data = jsonwebtoken::decode::<Claims>(token, &self.decoding, &Validation::default())?;
+        Ok(data.claims)
+    }
+}
+
+fn get_token(req: &Request) -> Option<&str> {
+    req.headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.strip_prefix("Bearer ").unwrap_or(v).trim())
+        .filter(|v| !v.is_empty())
+}
+
+/// 校验 token, 并检查用户存在,未禁用且未修改密码
+pub async fn verify_token(state: &AppState, token: &str) -> Option<Claims> {
+    let claims = state.token_keys.decode(token)
+        .map_err(|e| debug!("token 校验失败:{}", e))
+        .ok()?;
+    match check_token_user(state.conn(), &claims).await {
+        Ok(_) => Some(claims),
+        Err(e) => {
+            debug!("token 用户校验失败:{}", e);
+            None
+        }
+    }
+}
+
+/// 接口鉴权中间件,校验通过后将 Claims 放入 extensions
+pub async fn auth(state: State<AppState>, mut req: Request, next: Next) -> Response {
+    let claims = match get_token(&req) {
+        Some(token) => verify_token(&state, token).await,
+        None => None,
+    };
+    match claims {
+        Some(claims) => {
+            req.extensions_mut().insert(claims);
+            next.run(req).await
+        }
+        None => {
+            ApiError::ApiErrorInner(ApiErrorInner::new(UNAUTHORIZED_CODE, "未登入或登入已过期".to_string()))
+                .into_response()
+        }
+    }
+}
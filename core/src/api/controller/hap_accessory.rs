@@ -18,6 +18,7 @@ use crate::api::params::power::power_add_param::PowerAddParam;
 use crate::api::params::power::power_query_param::PowerQueryParam;
 use crate::api::params::power::power_update_param::PowerUpdateParam;
 use crate::db::entity::hap_accessory::ModelDelegateParamVec;
+use crate::init::hap_init::reload_accessory;
 use crate::template::hap::accessory::AccessoryTemplate;
 use crate::template::hap::service::ServiceTemplate;
 use crate::template::hl_template::{TemplateFormat};
@@ -56,7 +57,9 @@ pub async fn add(state: State<AppState>, Json(param): Json<PowerAddParam>) -> Ap
     model.hap_model_delegates = Set(ModelDelegateParamVec(vec![]));
     model.create_at = Set(chrono::Local::now().naive_local());
     model.update_at = Set(chrono::Local::now().naive_local());
-    model.insert(state.conn()).await?;
+    let model = model.insert(state.conn()).await?;
+    reload_accessory(state.conn(), state.hap_manager.clone(), state.device_manager.clone(), model.aid).await?;
+    state.mqtt_export_manager.sync_accessory(model.aid).await;
     Ok(ApiResp::with_data(()))
 }
 
@@ -116,7 +119,9 @@ pub async fn update_by_template(state: State<AppState>, Json(param): Json<Templa
 pub async fn update(state: State<AppState>,  Json(param): Json<PowerUpdateParam>) -> ApiResult<()> {
     let mut model = param.to_active_model::<HapAccessoryEntity, HapAccessoryActiveModel>()?;
     model.update_at = Set(chrono::Local::now().naive_local());
-    model.update(state.conn()).await?;
+    let model = model.update(state.conn()).await?;
+    reload_accessory(state.conn(), state.hap_manager.clone(), state.device_manager.clone(), model.aid).await?;
+    state.mqtt_export_manager.sync_accessory(model.aid).await;
     Ok(ApiResp::with_data(()))
 }
 
@@ -147,6 +152,8 @@ pub async fn delete(state: State<AppState>, Path(id): Path<i64>) -> ApiResult<()
     };
     model.delete(&txn).await?;
     txn.commit().await?;
+    state.hap_manager.remove_accessory(id as u64).await?;
+    state.mqtt_export_manager.remove_accessory(id).await;
     ok_data(())
 }
 
@@ -157,10 +164,9 @@ pub async fn disable(state: State<AppState>, Path(id): Path<i64>, Query(param):
         ..Default::default()
     };
     model.update(state.conn()).await?;
-    //移除
-    if param.disabled {
-        state.hap_manager.remove_accessory(id as u64).await?;
-    };
+    // 禁用时移除,启用时添加到运行中的桥接器
+    reload_accessory(state.conn(), state.hap_manager.clone(), state.device_manager.clone(), id).await?;
+    state.mqtt_export_manager.sync_accessory(id).await;
 
     Ok(ApiResp::with_data(()))
 }
\ No newline at end of file
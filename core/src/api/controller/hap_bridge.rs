@@ -1,5 +1,5 @@
 use axum::extract::{Path, Query, State};
-use axum::Json;
+use axum::{Extension, Json};
 use sea_orm::{ActiveEnum, ConnectionTrait, EntityTrait, IntoActiveModel, ModelTrait};
 use sea_orm::ActiveValue::Set;
 use sea_orm::prelude::Expr;
@@ -7,22 +7,29 @@ use hap::BonjourStatusFlag;
 use sea_orm::PaginatorTrait;
 use sea_orm::*;
 use crate::{api_err, err_msg};
+use crate::api::auth::Claims;
 use crate::api::output::{ApiResp, ApiResult, ok_data};
-use crate::api::params::{AddHapBridgeParam, DisableParam, GetTemplateParam};
-use crate::api::results::{HapBridgeResult, TemplateResult};
+use crate::api::params::{AddHapBridgeParam, DisableParam, GetTemplateParam, SetupCodeParam};
+use crate::api::results::{HapBridgeResult, SetupCodeResult, TemplateResult};
 use crate::api::state::AppState;
 use crate::db::entity::prelude::{HapAccessoryColumn, HapAccessoryEntity, HapBridgeActiveModel, HapBridgeColumn, HapBridgeEntity, HapBridgeModel};
 use sea_orm::QueryFilter;
-use tokio::time;
 use crate::api::params::power::power_query_param::PowerQueryParam;
 use crate::api::params::power::power_update_param::PowerUpdateParam;
 use crate::db::entity::hap_bridge::{BonjourStatusFlagWrapper, BridgeInfo, Model, PairingsWrapper};
+use crate::db::entity::pairing_log::PairingAction;
+use crate::db::entity::prelude::PairingLogModel;
 use crate::db::service::hap_bridge_service::create_hap_bridge;
 use crate::db::SNOWFLAKE;
-use crate::init::hap_init::add_hap_bridge;
+use crate::hap::db_bridge_storage::DbBridgesStorage;
+use crate::hap::setup_code::{render_qr_code, SetupCode};
+use crate::init::hap_init::{add_hap_bridge, restart_hap_bridge};
 use crate::service::hap_bridge_service;
 use crate::service::hap_bridge_service::to_model_result;
+use crate::service::pairing_service;
+use crate::service::pairing_service::PairingInfo;
 use crate::template::hap::bridge::HapBridgeTemplate;
+use target_hap::hap_manager::HapSession;
 
 pub async fn update_by_template(state: State<AppState>, Json(param): Json<TemplateResult>) -> ApiResult<()> {
     let template: HapBridgeTemplate = param.format.parse(param.text.as_str())?;
@@ -110,7 +117,7 @@ pub async fn delete(state: State<AppState>, Path(id): Path<i64>) -> ApiResult<()
 }
 
 ///重置
-pub async fn reset(state: State<AppState>, Path(id): Path<i64>) -> ApiResult<()> {
+pub async fn reset(state: State<AppState>, Extension(claims): Extension<Claims>, Path(id): Path<i64>) -> ApiResult<()> {
     let model = HapBridgeActiveModel {
         bridge_id: Set(id),
         pairings: Set(PairingsWrapper::default()),
@@ -118,18 +125,49 @@ pub async fn reset(state: State<AppState>, Path(id): Path<i64>) -> ApiResult<()>
         ..Default::default()
     };
     HapBridgeEntity::update(model).exec(state.conn()).await?;
+    DbBridgesStorage::clear_cache(id).await;
+    pairing_service::add_log(state.conn(), id, None, PairingAction::Reset, Some(claims.username)).await?;
     restart(state, Path(id)).await?;
     ok_data(())
 }
 
+/// 已配对的控制器
+pub async fn pairings(state: State<AppState>, Path(id): Path<i64>) -> ApiResult<Vec<PairingInfo>> {
+    let list = pairing_service::list_pairings(state.conn(), id).await?;
+    ok_data(list)
+}
+
+/// 撤销单个控制器,运行中的服务器配对缓存同时失效
+/// hap-rs 不能按控制器断开连接,重启桥接器断开会话,被撤销的控制器无法再通过配对验证
+pub async fn revoke_pairing(state: State<AppState>, Extension(claims): Extension<Claims>,
+                            Path((id, pairing_id)): Path<(i64, uuid::Uuid)>) -> ApiResult<()> {
+    pairing_service::revoke_pairing(state.conn(), id, pairing_id, claims.username).await?;
+    if state.hap_manager.is_server_running(id) {
+        restart_hap_bridge(state.conn(), state.hap_manager.clone(), state.device_manager.clone(), id)
+            .await
+            .map_err(|e| api_err!("撤销成功,重启失败{e}"))?;
+    }
+    ok_data(())
+}
+
+/// 当前连接的控制器会话
+pub async fn pairing_sessions(state: State<AppState>, Path(id): Path<i64>) -> ApiResult<Vec<HapSession>> {
+    ok_data(pairing_service::sessions(&state.hap_manager, id).await)
+}
+
+/// 配对审计日志
+pub async fn pairing_logs(state: State<AppState>, Path(id): Path<i64>) -> ApiResult<Vec<PairingLogModel>> {
+    let list = pairing_service::list_logs(state.conn(), id).await?;
+    ok_data(list)
+}
+
 /// 重启桥接器
 pub async fn restart(state: State<AppState>, Path(id): Path<i64>) -> ApiResult<()> {
-    state.hap_manager.stop_server(id).await?;
-    // 等mdns 注销成功
-    time::sleep(time::Duration::from_millis(200)).await;
-    let model = HapBridgeEntity::find_by_id(id).one(state.conn()).await?;
-    let hap_bridge = model.ok_or(api_err!("桥接器不存在"))?;
-    add_hap_bridge(state.conn(), hap_bridge, state.hap_manager.clone(), state.device_manager.clone())
+    HapBridgeEntity::find_by_id(id)
+        .one(state.conn())
+        .await?
+        .ok_or(api_err!("桥接器不存在"))?;
+    restart_hap_bridge(state.conn(), state.hap_manager.clone(), state.device_manager.clone(), id)
         .await
         .map_err(|e| api_err!("停止成功,启动失败{e}"))?;
     ok_data(())
@@ -154,6 +192,22 @@ pub async fn get_detail(state: State<AppState>, Path(id): Path<i64>) -> ApiResul
     ok_data(result)
 }
 
+/// 配对链接和二维码
+pub async fn setup_code(state: State<AppState>, Path(id): Path<i64>, Query(param): Query<SetupCodeParam>) -> ApiResult<SetupCodeResult> {
+    let model = HapBridgeEntity::find_by_id(id)
+        .one(state.conn())
+        .await?
+        .ok_or(api_err!("桥接器不存在"))?;
+    let format = param.format.unwrap_or_default();
+    let code = SetupCode::from_bridge(&model);
+    let qr_code = render_qr_code(code.setup_uri.as_str(), format)?;
+    ok_data(SetupCodeResult {
+        code,
+        format,
+        qr_code,
+    })
+}
+
 pub async fn list(state: State<AppState>, Query(param): Query<PowerQueryParam>) -> ApiResult<Vec<HapBridgeResult>> {
     let condition = param.get_condition::<HapBridgeEntity>()?;
 
@@ -8,20 +8,25 @@ use crate::db::entity::prelude::{IotDeviceEntity, IotDeviceActiveModel, IotDevic
 use sea_orm::QueryFilter;
 use sea_orm::ColumnTrait;
 use miot_proto::device::miot_spec_device::{AsMiotDevice, MiotDeviceArc};
-use miot_proto::proto::miio_proto::MiotSpecId;
-use crate::api::params::{AddServiceParam, DisableParam, EditDeviceParam, QueryIotDeviceParam, TestPropParam};
+use miot_proto::proto::miio_proto::{MiotActionResult, MiotSpecId};
+use crate::api::params::{ActionParam, AddServiceParam, DisableParam, EditDeviceParam, QueryIotDeviceParam, TestPropParam};
 use crate::api::params::power::power_query_param::PowerQueryParam;
 use crate::api::params::power::power_update_param::PowerUpdateParam;
 use crate::api::results::{IotDeviceResult, MiotDeviceResult};
 use crate::api_err;
 use crate::db::entity::iot_device::SourcePlatform;
+use crate::service::bind_key_service;
 
 pub async fn edit_device(state: State<AppState>, Json(param): Json<PowerUpdateParam>) -> ApiResult<()> {
     println!("{:?}", param);
     let model = param.to_active_model::<IotDeviceEntity, IotDeviceActiveModel>()?;
     println!("{:?}", model);
-    model.update(state.conn()).await?;
-
+    let old = IotDeviceEntity::find_by_id(model.device_id.clone().unwrap())
+        .one(state.conn())
+        .await?
+        .ok_or(api_err!("设备不存在"))?;
+    let new = model.update(state.conn()).await?;
+    bind_key_service::sync_device_bind_key(state.conn(), &state.ble_manager, &[&old.params, &new.params]).await;
     ok_data(())
 }
 
@@ -58,9 +63,11 @@ pub async fn list(state: State<AppState>, Query(param): Query<PowerQueryParam>)
                 Some(serde_json::from_value::<MiotDeviceResult>(s.full)?)
             }
         };
+        let health = dev_manager.health.get(i.device_id);
         result_list.push(IotDeviceResult {
             model: i,
             running,
+            health,
             source: dev,
         })
     }
@@ -91,6 +98,18 @@ pub async fn set_property(state: State<AppState>, Path(id): Path<i64>, Json(para
     ok_data(())
 }
 
+/// 调用设备动作
+pub async fn action(state: State<AppState>, Path(id): Path<i64>, Json(params): Json<ActionParam>) -> ApiResult<MiotActionResult> {
+    let dev = state.device_manager
+        .get_device(id)
+        .ok_or(api_err!("设备不存在"))?;
+    let dev = MiotDeviceArc(dev);
+    let result = dev.as_miot_device()?
+        .call_action(params.siid, params.aiid, params.ins)
+        .await?;
+    ok_data(result)
+}
+
 pub async fn delete(state: State<AppState>, Path(id): Path<i64>) -> ApiResult<()> {
     //查询配件
     let count = HapAccessoryEntity::find()
@@ -100,8 +119,13 @@ pub async fn delete(state: State<AppState>, Path(id): Path<i64>) -> ApiResult<()
     if count > 0 {
         return Err(api_err!("设备下有配件,请先删除配件"));
     }
+    let device = IotDeviceEntity::find_by_id(id)
+        .one(state.conn())
+        .await?
+        .ok_or(api_err!("设备不存在"))?;
     IotDeviceEntity::delete_by_id(id).exec(state.conn()).await?;
     let _ = state.device_manager.remove_device(id).await;
+    bind_key_service::sync_device_bind_key(state.conn(), &state.ble_manager, &[&device.params]).await;
     Ok(ApiResp::with_data(()))
 }
 
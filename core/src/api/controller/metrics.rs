@@ -0,0 +1,12 @@
+use axum::extract::State;
+use axum::http::header;
+use axum::response::IntoResponse;
+use crate::api::errors::ApiError;
+use crate::api::state::AppState;
+use crate::service::metrics_service;
+
+/// prometheus 指标
+pub async fn metrics(state: State<AppState>) -> Result<impl IntoResponse, ApiError> {
+    let text = metrics_service::gather(&state).await?;
+    Ok(([(header::CONTENT_TYPE, prometheus::TEXT_FORMAT)], text))
+}
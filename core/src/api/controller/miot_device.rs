@@ -6,28 +6,33 @@ use anyhow::anyhow;
 use axum::body::HttpBody;
 use axum::extract::{Path, Query, State};
 use axum::Json;
-use log::{error, warn};
-use sea_orm::{ActiveModelTrait, Condition, EntityTrait, JsonValue, PaginatorTrait, QueryFilter};
+use log::{error, info, warn};
+use sea_orm::{ActiveModelTrait, ColumnTrait, Condition, EntityTrait, JsonValue, PaginatorTrait, QueryFilter};
+use sea_orm::ActiveValue;
 use sea_orm::ActiveValue::Set;
 use sea_orm::prelude::DateTimeUtc;
 use tap::TapFallible;
 use tokio::net::UdpSocket;
+use hex::FromHex;
 use miot_proto::device::miot_spec_device::MiotDeviceType;
 use miot_proto::proto::transport::udp_iot_spec_proto::UdpMiotSpecProtocol;
 
 
 use crate::api::output::{ApiResult, err_msg, ok_data};
-use crate::api::params::{AccountParam, DidParam, MiConvertByTemplateParam, MiConvertToIotParam};
+use crate::api::params::{AccountParam, DidParam, MiConvertByTemplateParam, MiConvertToIotParam, MiManualAddParam};
 use crate::api::params::power::power_query_param::PowerQueryParam;
 use crate::api::params::power::power_update_param::PowerUpdateParam;
-use crate::api::results::{MiotDeviceModelResult, MiotDeviceResult, TemplateResult};
+use crate::api::results::{MiotDeviceModelResult, MiotDeviceResult, MiotDiscoverResult, TemplateResult};
 use crate::api::state::AppState;
 use crate::api_err;
+use crate::config::secret::encrypt_secret;
+use crate::service::bind_key_service;
 use crate::db::entity::iot_device::{DeviceParam, DeviceType, IotDeviceType, SourcePlatform};
 use crate::db::entity::iot_device::DeviceParam::{MiGatewayParam, WifiDeviceParam};
 use crate::db::entity::mi_account::MiAccountStatus;
-use crate::db::entity::prelude::{IotDeviceActiveModel, IotDeviceEntity, MiAccountActiveModel, MiAccountColumn, MiAccountEntity, MiAccountModel, MiotDeviceActiveModel, MiotDeviceEntity, MiotDeviceModel};
+use crate::db::entity::prelude::{IotDeviceActiveModel, IotDeviceColumn, IotDeviceEntity, MiAccountActiveModel, MiAccountColumn, MiAccountEntity, MiAccountModel, MiotDeviceActiveModel, MiotDeviceEntity, MiotDeviceModel};
 use crate::db::SNOWFLAKE;
+use crate::init::hap_init;
 use crate::init::manager::template_manager::{ApplyTemplateOptions, SourcePlatformModel};
 use crate::template::hl_template::HlDeviceTemplate;
 // use crate::template::miot_template::HlDeviceTemplate;
@@ -36,6 +41,9 @@ use crate::template::hl_template::HlDeviceTemplate;
 // accounts
 pub async fn update_account(state: State<AppState>, Json(param): Json<PowerUpdateParam>) -> ApiResult<()> {
     let mut model = param.to_active_model::<MiAccountEntity, MiAccountActiveModel>()?;
+    if let ActiveValue::Set(password) = model.password.clone() {
+        model.password = Set(encrypt_secret(password.as_str())?);
+    }
     model.update_at = Set(DateTimeUtc::from(chrono::Local::now()));
     MiAccountEntity::update(model)
         .exec(state.conn())
@@ -56,7 +64,7 @@ pub async fn delete_account(state: State<AppState>, Query(param): Query<AccountP
 pub async fn change_password(state: State<AppState>, Json(param): Json<AccountParam>) -> ApiResult<()> {
     let account = MiAccountActiveModel {
         account: Set(param.account),
-        password: Set(param.password.ok_or(api_err!("密码不能为空"))?),
+        password: Set(encrypt_secret(param.password.ok_or(api_err!("密码不能为空"))?.as_str())?),
         ..Default::default()
     };
     MiAccountEntity::update(account)
@@ -74,7 +82,7 @@ pub async fn add_account(state: State<AppState>, Json(param): Json<AccountParam>
     };
     let account = MiAccountActiveModel {
         account: Set(param.account),
-        password: Set(param.password.ok_or(api_err!("密码不能为空"))?),
+        password: Set(encrypt_secret(param.password.ok_or(api_err!("密码不能为空"))?.as_str())?),
         status: Set(MiAccountStatus::NotLogin),
         ..Default::default()
     };
@@ -117,6 +125,11 @@ pub async fn sync_mi_devices(state: State<AppState>, Query(param): Query<Account
             Ok(dev_result) => {
                 count = count + 1;
                 let a = MiotDeviceEntity::find_by_id(dev_result.did.clone()).one(state.conn()).await?;
+                // mac 变化时 beacon_key 跟随新的 mac
+                let key_macs: Vec<String> = a.as_ref()
+                    .filter(|d| d.beacon_key.is_some() && d.mac != dev_result.mac)
+                    .map(|d| d.mac.iter().chain(dev_result.mac.iter()).cloned().collect())
+                    .unwrap_or_default();
                 // let text = serde_json::to_string(&dev_result).map_err(|e| anyhow!("parse error"))?;
                 let module = MiotDeviceActiveModel {
                     did: Set(dev_result.did),
@@ -135,6 +148,7 @@ pub async fn sync_mi_devices(state: State<AppState>, Query(param): Query<Account
                 } else {
                     MiotDeviceEntity::update(module).exec(state.conn()).await?;
                 }
+                bind_key_service::reload_bind_keys(state.conn(), &state.ble_manager, key_macs.as_slice()).await;
             }
             Err(e) => {
                 warn!("设备数据解析错误:{:?}", e);
@@ -145,6 +159,15 @@ pub async fn sync_mi_devices(state: State<AppState>, Query(param): Query<Account
 }
 
 
+/// 从米家云获取蓝牙设备的 beaconkey
+pub async fn fetch_beacon_key(state: State<AppState>, Json(param): Json<DidParam>) -> ApiResult<String> {
+    let key = bind_key_service::fetch_beacon_key(state.conn(),
+                                                 &state.mi_account_manager,
+                                                 &state.ble_manager,
+                                                 param.did.as_str()).await?;
+    ok_data(key)
+}
+
 /// 读取米家设备
 pub async fn handshake(state: State<AppState>, Json(param): Json<DidParam>) -> ApiResult<()> {
     let model = MiotDeviceEntity::find_by_id(param.did)
@@ -164,6 +187,67 @@ pub async fn handshake(state: State<AppState>, Json(param): Json<DidParam>) -> A
     ok_data(())
 }
 
+/// 局域网扫描设备,更新变化的ip
+pub async fn discover(state: State<AppState>) -> ApiResult<Vec<MiotDiscoverResult>> {
+    let devices = UdpMiotSpecProtocol::scan(Duration::from_secs(3))
+        .await
+        .map_err(|e| api_err!("扫描失败:{}", e))?;
+    let mut results = vec![];
+    for dev in devices {
+        let did = dev.did.to_string();
+        let model = MiotDeviceEntity::find_by_id(did.clone())
+            .one(state.conn())
+            .await?;
+        let mut result = MiotDiscoverResult {
+            did,
+            ip: dev.ip.clone(),
+            name: None,
+            model: None,
+            old_ip: None,
+            updated: false,
+        };
+        if let Some(model) = model {
+            result.name = Some(model.name.clone());
+            result.model = Some(model.model.clone());
+            result.old_ip = model.localip.clone();
+            if model.localip.as_deref() != Some(dev.ip.as_str()) {
+                info!("设备:{} ip变化:{:?} -> {}", model.did, model.localip, dev.ip);
+                let mut active: MiotDeviceActiveModel = model.into();
+                active.localip = Set(Some(dev.ip.clone()));
+                active.update_at = Set(chrono::Utc::now());
+                active.update(state.conn()).await?;
+                result.updated = true;
+                if let Err(e) = restart_by_did(&state, result.did.as_str()).await {
+                    error!("设备:{} ip变化后重启失败:{:?}", result.did, e);
+                }
+            }
+        }
+        results.push(result);
+    }
+    ok_data(results)
+}
+
+/// 重启使用该米家设备的运行中的设备,并重新加载其配件
+async fn restart_by_did(state: &AppState, did: &str) -> anyhow::Result<()> {
+    let devices = IotDeviceEntity::find()
+        .filter(IotDeviceColumn::SourceId.eq(did))
+        .all(state.conn())
+        .await?;
+    for dev in devices {
+        let id = dev.device_id;
+        if !state.device_manager.is_running(id) {
+            continue;
+        }
+        state.device_manager.stop_device(id)?;
+        state.device_manager.start_devices(Some(vec![id])).await?;
+        let aids = hap_init::reload_device_accessories(state.conn(), state.hap_manager.clone(), state.device_manager.clone(), id).await?;
+        for aid in aids {
+            state.mqtt_export_manager.sync_accessory(aid).await;
+        }
+    }
+    Ok(())
+}
+
 pub async fn list(state: State<AppState>, Query(param): Query<PowerQueryParam>) -> ApiResult<Vec<MiotDeviceModelResult>> {
     let condition = param.get_condition::<MiotDeviceEntity>()?;
 
@@ -214,14 +298,96 @@ pub async fn convert_by_template(state: State<AppState>, Json(param): Json<MiCon
     ok_data(())
 }
 
-/// 转换
-pub async fn access(state: State<AppState>, Json(param): Json<MiConvertToIotParam>) -> ApiResult<()> {
-    let mi_dev = MiotDeviceEntity::find_by_id(param.did.as_str())
-        .one(state.conn())
-        .await?
-        .ok_or(api_err!("设备不存在"))?;
+/// 手动添加的设备所属账号
+const LOCAL_ACCOUNT: &str = "local";
 
-    //创建iot设备
+/// 手动添加wifi设备,不依赖米家账号
+/// 握手并调用 miIO.info 校验后,创建 miot_device 和 iot_device
+pub async fn manual_add(state: State<AppState>, Json(param): Json<MiManualAddParam>) -> ApiResult<String> {
+    let token = <[u8; 16]>::from_hex(param.token.as_bytes())
+        .map_err(|_| api_err!("token格式错误,需要32位16进制字符串"))?;
+    let ip = param.ip.trim();
+    let _: std::net::IpAddr = ip.parse()
+        .map_err(|_| api_err!("ip格式错误"))?;
+    let (device_id, info) = UdpMiotSpecProtocol::probe(ip, token, Duration::from_secs(5))
+        .await
+        .map_err(|e| api_err!("设备校验失败:{}", e))?;
+    let model = info.get("model").and_then(|m| m.as_str());
+    if model.is_some_and(|m| m != param.model.as_str()) {
+        return Err(api_err!("设备型号不匹配,设备返回:{}", model.unwrap_or_default()));
+    }
+    let did = device_id.to_string();
+    let name = param.name.clone().unwrap_or(param.model.clone());
+    let mac = info.get("mac").and_then(|m| m.as_str()).map(|m| m.to_string());
+    let old = MiotDeviceEntity::find_by_id(did.clone()).one(state.conn()).await?;
+    let mi_dev = match old {
+        None => MiotDeviceActiveModel {
+            did: Set(did.clone()),
+            token: Set(param.token.to_lowercase()),
+            name: Set(name.clone()),
+            model: Set(param.model.clone()),
+            localip: Set(Some(ip.to_string())),
+            beacon_key: Set(None),
+            mac: Set(mac),
+            is_online: Set(true),
+            user_id: Set(LOCAL_ACCOUNT.to_string()),
+            update_at: Set(chrono::Utc::now()),
+            full: Set(info),
+        }.insert(state.conn()).await?,
+        // 已存在时只更新网络信息,保留账号和 beacon_key
+        Some(old) => {
+            let mut active: MiotDeviceActiveModel = old.into();
+            active.token = Set(param.token.to_lowercase());
+            active.localip = Set(Some(ip.to_string()));
+            if mac.is_some() {
+                active.mac = Set(mac);
+            }
+            active.is_online = Set(true);
+            active.full = Set(info);
+            active.update_at = Set(chrono::Utc::now());
+            active.update(state.conn()).await?
+        }
+    };
+
+    let exists = IotDeviceEntity::find()
+        .filter(IotDeviceColumn::SourceId.eq(did.as_str()))
+        .count(state.conn())
+        .await? > 0;
+    if exists {
+        // 已转换过的设备只需要用新的网络信息重启
+        if let Err(e) = restart_by_did(&state, did.as_str()).await {
+            error!("设备:{} 重启失败:{:?}", did, e);
+        }
+        return ok_data(did);
+    }
+
+    // 有桥接器和模板时直接应用模板,否则创建待配置的设备
+    let template = state.template_manager.templates
+        .iter()
+        .find(|t| t.model.as_str() == param.model.as_str())
+        .map(|t| t.clone());
+    match (param.bridge_id, template) {
+        (Some(bridge_id), Some(template)) => {
+            let dev_ids = state.template_manager.apply_template(ApplyTemplateOptions {
+                template,
+                bridge_id: Some(bridge_id),
+                platform: SourcePlatformModel::MiHome(mi_dev),
+            }).await?;
+            let _ = state.template_manager.start_devices(dev_ids).await;
+        }
+        _ => {
+            create_iot_device(&state, MiConvertToIotParam {
+                did: did.clone(),
+                name,
+                integration: "xiaomi_wifi".to_string(),
+                memo: Some("手动添加".to_string()),
+            }).await?;
+        }
+    }
+    ok_data(did)
+}
+
+async fn create_iot_device(state: &AppState, param: MiConvertToIotParam) -> anyhow::Result<()> {
     let model = IotDeviceActiveModel {
         device_id: Set(SNOWFLAKE.next_id()),
         integration: Set(param.integration),
@@ -235,10 +401,21 @@ pub async fn access(state: State<AppState>, Json(param): Json<MiConvertToIotPara
         update_at: Set(DateTimeUtc::from(chrono::Local::now())),
         ..Default::default()
     };
-
-    let dev = IotDeviceEntity::insert(model)
+    IotDeviceEntity::insert(model)
         .exec(state.conn())
         .await?;
+    Ok(())
+}
+
+/// 转换
+pub async fn access(state: State<AppState>, Json(param): Json<MiConvertToIotParam>) -> ApiResult<()> {
+    let mi_dev = MiotDeviceEntity::find_by_id(param.did.as_str())
+        .one(state.conn())
+        .await?
+        .ok_or(api_err!("设备不存在"))?;
+
+    //创建iot设备
+    create_iot_device(&state, param).await?;
     ok_data(())
 }
 
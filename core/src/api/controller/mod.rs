@@ -10,4 +10,7 @@ pub(crate) mod test;
 pub(crate) mod native_ble_device;
 pub(crate) mod template;
 pub(crate) mod source_device;
-pub(crate) mod system;
\ No newline at end of file
+pub(crate) mod system;
+pub(crate) mod metrics;
+pub(crate) mod rule;
+pub(crate) mod scene;
@@ -0,0 +1,108 @@
+use axum::extract::{Path, Query, State};
+use axum::Json;
+use sea_orm::{ActiveModelBehavior, ActiveModelTrait, EntityTrait, QueryOrder};
+use sea_orm::ActiveValue::Set;
+
+use crate::api::output::{ApiResult, ok_data};
+use crate::api::params::{DisableParam, RuleParam};
+use crate::api::state::AppState;
+use crate::api_err;
+use crate::db::entity::prelude::{RuleActiveModel, RuleColumn, RuleEntity, RuleModel};
+use crate::db::entity::rule::{RuleActionVec, RuleConditionVec, RuleTriggerVec};
+use crate::db::SNOWFLAKE;
+use crate::init::manager::rule_manager::RuleDryRunResult;
+
+pub async fn list(state: State<AppState>) -> ApiResult<Vec<RuleModel>> {
+    let list = RuleEntity::find()
+        .order_by_asc(RuleColumn::CreateAt)
+        .all(state.conn())
+        .await?;
+    ok_data(list)
+}
+
+pub async fn get(state: State<AppState>, Path(id): Path<i64>) -> ApiResult<RuleModel> {
+    let rule = RuleEntity::find_by_id(id)
+        .one(state.conn())
+        .await?
+        .ok_or(api_err!("规则不存在"))?;
+    ok_data(rule)
+}
+
+pub async fn add(state: State<AppState>, Json(param): Json<RuleParam>) -> ApiResult<i64> {
+    state.rule_manager.validate(&param.triggers, &param.conditions, &param.actions)?;
+    let rule_id = SNOWFLAKE.next_id();
+    let model = RuleActiveModel {
+        rule_id: Set(rule_id),
+        name: Set(param.name),
+        disabled: Set(param.disabled),
+        triggers: Set(RuleTriggerVec(param.triggers)),
+        conditions: Set(RuleConditionVec(param.conditions)),
+        actions: Set(RuleActionVec(param.actions)),
+        remark: Set(param.remark),
+        ..RuleActiveModel::new()
+    };
+    model.insert(state.conn()).await?;
+    state.rule_manager.reload_rule(rule_id).await?;
+    ok_data(rule_id)
+}
+
+pub async fn update(state: State<AppState>, Json(param): Json<RuleParam>) -> ApiResult<()> {
+    let rule_id = param.rule_id.ok_or(api_err!("规则id不能为空"))?;
+    state.rule_manager.validate(&param.triggers, &param.conditions, &param.actions)?;
+    RuleEntity::find_by_id(rule_id)
+        .one(state.conn())
+        .await?
+        .ok_or(api_err!("规则不存在"))?;
+    let model = RuleActiveModel {
+        rule_id: Set(rule_id),
+        name: Set(param.name),
+        disabled: Set(param.disabled),
+        triggers: Set(RuleTriggerVec(param.triggers)),
+        conditions: Set(RuleConditionVec(param.conditions)),
+        actions: Set(RuleActionVec(param.actions)),
+        remark: Set(param.remark),
+        update_at: Set(chrono::Utc::now()),
+        ..Default::default()
+    };
+    model.update(state.conn()).await?;
+    state.rule_manager.reload_rule(rule_id).await?;
+    ok_data(())
+}
+
+pub async fn delete(state: State<AppState>, Path(id): Path<i64>) -> ApiResult<()> {
+    RuleEntity::delete_by_id(id).exec(state.conn()).await?;
+    state.rule_manager.unload(id);
+    ok_data(())
+}
+
+pub async fn disable(state: State<AppState>, Path(id): Path<i64>, Query(param): Query<DisableParam>) -> ApiResult<()> {
+    let model = RuleActiveModel {
+        rule_id: Set(id),
+        disabled: Set(param.disabled),
+        update_at: Set(chrono::Utc::now()),
+        ..Default::default()
+    };
+    model.update(state.conn()).await?;
+    state.rule_manager.reload_rule(id).await?;
+    ok_data(())
+}
+
+/// 测试未保存的规则,不执行动作
+pub async fn dry_run(state: State<AppState>, Json(param): Json<RuleParam>) -> ApiResult<RuleDryRunResult> {
+    let result = state.rule_manager
+        .dry_run(&param.triggers, &param.conditions, &param.actions)
+        .await?;
+    ok_data(result)
+}
+
+/// 测试已保存的规则,不执行动作
+pub async fn dry_run_by_id(state: State<AppState>, Path(id): Path<i64>) -> ApiResult<RuleDryRunResult> {
+    let rule = RuleEntity::find_by_id(id)
+        .one(state.conn())
+        .await?
+        .ok_or(api_err!("规则不存在"))?;
+    let result = state.rule_manager
+        .dry_run(&rule.triggers.0, &rule.conditions.0, &rule.actions.0)
+        .await?;
+    ok_data(result)
+}
@@ -0,0 +1,97 @@
+use axum::extract::{Path, State};
+use axum::Json;
+use sea_orm::{ActiveModelBehavior, ActiveModelTrait, EntityTrait, QueryOrder};
+use sea_orm::ActiveValue::Set;
+
+use crate::api::output::{ApiResult, ok_data};
+use crate::api::params::{SceneCaptureParam, SceneParam};
+use crate::api::state::AppState;
+use crate::api_err;
+use crate::db::entity::prelude::{HapAccessoryEntity, SceneActiveModel, SceneColumn, SceneEntity, SceneModel};
+use crate::db::entity::scene::{SceneItem, SceneItemVec};
+use crate::db::SNOWFLAKE;
+use crate::service::scene_service;
+use crate::service::scene_service::SceneApplyResult;
+
+pub async fn list(state: State<AppState>) -> ApiResult<Vec<SceneModel>> {
+    let list = SceneEntity::find()
+        .order_by_asc(SceneColumn::CreateAt)
+        .all(state.conn())
+        .await?;
+    ok_data(list)
+}
+
+pub async fn get(state: State<AppState>, Path(id): Path<i64>) -> ApiResult<SceneModel> {
+    let scene = SceneEntity::find_by_id(id)
+        .one(state.conn())
+        .await?
+        .ok_or(api_err!("场景不存在"))?;
+    ok_data(scene)
+}
+
+async fn insert_scene(state: &AppState, name: String, items: Vec<SceneItem>, bridge_id: Option<i64>, remark: Option<String>) -> anyhow::Result<i64> {
+    let scene_id = SNOWFLAKE.next_id();
+    let model = SceneActiveModel {
+        scene_id: Set(scene_id),
+        name: Set(name),
+        items: Set(SceneItemVec(items)),
+        bridge_id: Set(bridge_id),
+        remark: Set(remark),
+        ..SceneActiveModel::new()
+    };
+    let scene = model.insert(state.conn()).await?;
+    scene_service::sync_accessory(state.conn(), &state.hap_manager, &state.device_manager, &state.mqtt_export_manager, scene).await?;
+    Ok(scene_id)
+}
+
+pub async fn add(state: State<AppState>, Json(param): Json<SceneParam>) -> ApiResult<i64> {
+    let scene_id = insert_scene(&state, param.name, param.items, param.bridge_id, param.remark).await?;
+    ok_data(scene_id)
+}
+
+/// 读取设备当前值保存为场景
+pub async fn capture(state: State<AppState>, Json(param): Json<SceneCaptureParam>) -> ApiResult<i64> {
+    let items = scene_service::capture(&state.device_manager, &param.properties).await?;
+    let scene_id = insert_scene(&state, param.name, items, param.bridge_id, param.remark).await?;
+    ok_data(scene_id)
+}
+
+pub async fn update(state: State<AppState>, Json(param): Json<SceneParam>) -> ApiResult<()> {
+    let scene_id = param.scene_id.ok_or(api_err!("场景id不能为空"))?;
+    SceneEntity::find_by_id(scene_id)
+        .one(state.conn())
+        .await?
+        .ok_or(api_err!("场景不存在"))?;
+    let model = SceneActiveModel {
+        scene_id: Set(scene_id),
+        name: Set(param.name),
+        items: Set(SceneItemVec(param.items)),
+        bridge_id: Set(param.bridge_id),
+        remark: Set(param.remark),
+        update_at: Set(chrono::Utc::now()),
+        ..Default::default()
+    };
+    let scene = model.update(state.conn()).await?;
+    scene_service::sync_accessory(state.conn(), &state.hap_manager, &state.device_manager, &state.mqtt_export_manager, scene).await?;
+    ok_data(())
+}
+
+pub async fn delete(state: State<AppState>, Path(id): Path<i64>) -> ApiResult<()> {
+    let scene = SceneEntity::find_by_id(id)
+        .one(state.conn())
+        .await?
+        .ok_or(api_err!("场景不存在"))?;
+    if let Some(aid) = scene.aid {
+        if let Some(accessory) = HapAccessoryEntity::find_by_id(aid).one(state.conn()).await? {
+            scene_service::remove_accessory(state.conn(), &state.hap_manager, &state.device_manager, &state.mqtt_export_manager, accessory).await?;
+        }
+    }
+    SceneEntity::delete_by_id(id).exec(state.conn()).await?;
+    ok_data(())
+}
+
+/// 执行场景,返回每个设备的执行结果
+pub async fn apply(state: State<AppState>, Path(id): Path<i64>) -> ApiResult<Vec<SceneApplyResult>> {
+    let results = scene_service::apply_scene_by_id(state.conn(), &state.device_manager, id).await?;
+    ok_data(results)
+}
@@ -16,6 +16,7 @@ pub async fn list(state: State<AppState>, Query(param): Query<QueryIotDevicePara
         SourcePlatform::BleNative => {
             todo!()
         }
+        SourcePlatform::Mqtt => {}
     };
     ok_data(vec![])
 }
\ No newline at end of file
@@ -1,25 +1,44 @@
 use std::collections::HashMap;
 use axum::extract::State;
-use axum::Json;
+use axum::{Extension, Json};
+use sea_orm::EntityTrait;
+use crate::api::auth::Claims;
 use crate::api::output::{ApiResult, ok_data};
-use crate::api::params::LoginParam;
+use crate::api::params::{ChangePasswordParam, LoginParam};
 use crate::api::results::UserInfoResult;
 use crate::api::state::AppState;
+use crate::api_err;
+use crate::db::entity::prelude::SysUserEntity;
+use crate::service::sys_user_service;
 
+const DEFAULT_AVATAR: &str = "https://gw.alipayobjects.com/zos/rmsportal/BiazfanxmamNRoxxVxka.png";
 
-
-pub async fn info(state: State<AppState>) -> ApiResult<UserInfoResult> {
+pub async fn info(state: State<AppState>, Extension(claims): Extension<Claims>) -> ApiResult<UserInfoResult> {
+    let user = SysUserEntity::find_by_id(claims.sub)
+        .one(state.conn())
+        .await?
+        .ok_or(api_err!("用户不存在"))?;
     let result = UserInfoResult {
-        username: "admin".to_string(),
-        name: "Home link".to_string(),
-        avatar: "https://gw.alipayobjects.com/zos/rmsportal/BiazfanxmamNRoxxVxka.png".to_string(),
-        userid: 001,
-        roles: vec!["admin".to_string()],
+        roles: user.role_list(),
+        username: user.username,
+        name: user.name,
+        avatar: user.avatar.unwrap_or(DEFAULT_AVATAR.to_string()),
+        userid: user.user_id,
     };
     ok_data(result)
 }
+
 pub async fn login(state: State<AppState>, Json(param): Json<LoginParam>) -> ApiResult<HashMap<String, String>> {
+    let user = sys_user_service::check_login(state.conn(), param.username.as_str(), param.password.as_str()).await?;
+    let token = state.token_keys.encode(&user)?;
     let mut result = HashMap::new();
-    result.insert("token".to_string(), "token".to_string());
+    result.insert("token".to_string(), token);
     ok_data(result)
-}
\ No newline at end of file
+}
+
+pub async fn change_password(state: State<AppState>, Extension(claims): Extension<Claims>, Json(param): Json<ChangePasswordParam>) -> ApiResult<()> {
+    sys_user_service::change_password(state.conn(), claims.sub,
+                                      param.old_password.as_str(),
+                                      param.new_password.as_str()).await?;
+    ok_data(())
+}
@@ -1,6 +1,12 @@
 use axum::extract::State;
+use axum::http::header;
+use axum::Json;
+use axum::response::IntoResponse;
+use crate::api::errors::ApiError;
 use crate::api::output::{ApiResult, ok_data};
 use crate::api::state::AppState;
+use crate::service::backup_service;
+use crate::service::backup_service::{BackupArchive, RestoreResult};
 
 pub async fn restart(state: State<AppState>) -> ApiResult<()> {
     if let Some(s) = state.server_shutdown_signal.lock().await.take() {
@@ -9,4 +15,22 @@ pub async fn restart(state: State<AppState>) -> ApiResult<()> {
     //todo 开启脚本重新运行当前进程
 
     ok_data(())
-}
\ No newline at end of file
+}
+
+/// 导出备份文件
+pub async fn backup(state: State<AppState>) -> Result<impl IntoResponse, ApiError> {
+    let archive = backup_service::backup(state.conn()).await?;
+    let body = serde_json::to_vec(&archive).map_err(anyhow::Error::from)?;
+    let disposition = format!("attachment; filename=\"homelink-backup-{}.json\"",
+                              archive.create_at.format("%Y%m%d%H%M%S"));
+    Ok(([
+        (header::CONTENT_TYPE, "application/json".to_string()),
+        (header::CONTENT_DISPOSITION, disposition),
+    ], body))
+}
+
+/// 导入备份文件,重启后生效
+pub async fn restore(state: State<AppState>, Json(archive): Json<BackupArchive>) -> ApiResult<RestoreResult> {
+    let result = backup_service::restore(state.conn(), archive).await?;
+    ok_data(result)
+}
@@ -7,18 +7,22 @@ use sea_orm::*;
 use crate::api::errors::{ApiError, ApiErrorInner};
 use crate::api::output::{ApiResult, ok_data};
 use crate::api::params::GetTemplateParam;
-use crate::api::params::template::{ApplyTemplateParam, CheckTemplateParam};
+use crate::api::params::template::{ApplyMqttTemplateParam, ApplyTemplateParam, CheckTemplateParam, GenerateTemplateParam};
 use crate::api::results::{CheckTemplateResult, TemplateResult};
 use crate::api::state::AppState;
 use crate::api_err;
 use crate::db::entity::prelude::{IotDeviceColumn, IotDeviceEntity, MiotDeviceEntity, MiotDeviceModel};
-use crate::init::manager::template_manager::{ApplyTemplateOptions, SourcePlatformModel};
+use crate::init::manager::template_manager::{ApplyTemplateOptions, SourcePlatformModel, TemplateFileError};
+use miot_proto::spec::cache::MiotSpecCache;
+use crate::template::checker::{check_template, TemplateDiagnostic};
+use crate::template::generator::{generate_template, spec_cache_dir};
 use crate::template::hl_template::{HlDeviceTemplate, TemplateFormat};
 
 pub async fn check_template_add(state: State<AppState>, Json(param): Json<TemplateResult>) -> ApiResult<CheckTemplateResult> {
     let template = param.text.as_str();
     let template: HlDeviceTemplate = param.format.parse(template)
         .map_err(|e| api_err!("模板格式错误:{:?}", e))?;
+    let diagnostics = check_template(&state.hap_metadata, &template);
     let mut new_devices = vec![];
     let mut new_accessories = vec![];
     for device in template.devices {
@@ -30,13 +34,15 @@ pub async fn check_template_add(state: State<AppState>, Json(param): Json<Templa
     ok_data(CheckTemplateResult {
         new_devices,
         new_accessories,
+        diagnostics,
     })
 }
 
-pub async fn check_template_update(state: State<AppState>, Json(param): Json<CheckTemplateParam>) -> ApiResult<()> {
+pub async fn check_template_update(state: State<AppState>, Json(param): Json<CheckTemplateParam>) -> ApiResult<Vec<TemplateDiagnostic>> {
     let template = param.text.as_str();
     let template: HlDeviceTemplate = param.format.parse(template)
         .map_err(|e| api_err!("模板格式错误:{:?}", e))?;
+    let diagnostics = check_template(&state.hap_metadata, &template);
     let temp_id = template.id.clone();
     //batch_id => {
     // device:
@@ -76,7 +82,7 @@ pub async fn check_template_update(state: State<AppState>, Json(param): Json<Che
 
     // template.devices
 
-    ok_data(())
+    ok_data(diagnostics)
 }
 
 
@@ -93,7 +99,23 @@ pub async fn apply_mijia(state: State<AppState>, Json(param): Json<ApplyTemplate
         bridge_id: Some(param.bridge_id.clone()),
         platform: SourcePlatformModel::MiHome(model),
     }).await?;
-    let _ = state.device_manager.start_devices(Some(dev_ids)).await;
+    let _ = state.template_manager.start_devices(dev_ids).await;
+    ok_data(())
+}
+
+/// 应用mqtt 设备模板, 设备参数在模板 devices.params 中
+pub async fn apply_mqtt(state: State<AppState>, Json(param): Json<ApplyMqttTemplateParam>) -> ApiResult<()> {
+    let template: HlDeviceTemplate = param.format.parse(param.text.as_str())
+        .map_err(|e| api_err!("模板格式错误:{:?}", e))?;
+    let dev_ids = state.template_manager.apply_template(ApplyTemplateOptions {
+        template,
+        bridge_id: Some(param.bridge_id),
+        platform: SourcePlatformModel::Mqtt {
+            source_id: param.source_id,
+            name: param.name,
+        },
+    }).await?;
+    let _ = state.template_manager.start_devices(dev_ids).await;
     ok_data(())
 }
 
@@ -129,4 +151,26 @@ pub async fn get(state: State<AppState>, Path(id): Path<String>) -> ApiResult<Hl
         .clone();
     // let list = MiAccountEntity::find().all(state.conn()).await?;
     ok_data(temp)
-}
\ No newline at end of file
+}
+
+/// 根据miot spec 生成模板草稿
+pub async fn generate(Path(model): Path<String>, Json(param): Json<GenerateTemplateParam>) -> ApiResult<TemplateResult> {
+    let cache = MiotSpecCache::new(spec_cache_dir());
+    let spec = cache.get(model.as_str())
+        .await
+        .map_err(|e| api_err!("获取spec失败:{}", e))?;
+    let integration = param.integration.unwrap_or("xiaomi_wifi".to_string());
+    let template = generate_template(model.as_str(), integration.as_str(), &spec)
+        .map_err(|e| api_err!("生成模板失败:{}", e))?;
+    let format = param.format.unwrap_or(TemplateFormat::Toml);
+    let text = format.format_to_str(&template)?;
+    ok_data(TemplateResult {
+        text,
+        format,
+    })
+}
+
+/// 模板目录中解析失败的文件
+pub async fn errors(state: State<AppState>) -> ApiResult<Vec<TemplateFileError>> {
+    ok_data(state.template_manager.errors())
+}
@@ -5,5 +5,6 @@ mod output;
 mod errors;
 pub mod params;
 pub mod results;
+pub mod auth;
 mod json;
 // mod json_2;
\ No newline at end of file
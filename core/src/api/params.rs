@@ -6,6 +6,7 @@ use sea_orm::ActiveValue::Set;
 use sea_orm::JsonValue;
 use serde::Serialize;
 use crate::hap::hap_type::MappingHapType;
+use crate::hap::setup_code::QrCodeFormat;
 use serde_aux::prelude::deserialize_number_from_string;
 use crate::db::entity::hap_characteristic::HapCharInfoQueryResult;
 use serde_aux::prelude::deserialize_option_number_from_string;
@@ -16,6 +17,9 @@ use crate::db::entity::hap_bridge::BridgeCategory;
 use crate::db::entity::iot_device::SourcePlatform;
 use crate::db::entity::prelude::{HapAccessoryActiveModel, HapCharacteristicActiveModel};
 use crate::init::manager::template_manager::BridgeMode;
+use crate::db::entity::scene::SceneItem;
+use crate::rule::{RuleAction, RuleCondition, RuleTrigger};
+use crate::service::scene_service::SceneProperty;
 use crate::template::hl_template::TemplateFormat;
 
 #[derive(serde::Deserialize, Debug)]
@@ -25,6 +29,12 @@ pub struct LoginParam {
     pub username: String,
 }
 
+#[derive(serde::Deserialize, Debug)]
+pub struct ChangePasswordParam {
+    pub old_password: String,
+    pub new_password: String,
+}
+
 #[derive(serde::Deserialize, Debug)]
 pub struct AddHapBridgeParam {
     /// pin码
@@ -62,6 +72,18 @@ pub struct MiConvertByTemplateParam {
     pub gateway_id: Option<i64>,
 }
 
+/// 手动添加wifi设备
+#[derive(serde::Deserialize, Debug)]
+pub struct MiManualAddParam {
+    pub ip: String,
+    pub token: String,
+    pub model: String,
+    pub name: Option<String>,
+    /// 桥接器,设置时自动应用模板
+    #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
+    pub bridge_id: Option<i64>,
+}
+
 #[derive(serde::Deserialize, Debug)]
 pub struct MiConvertToIotParam {
     /// did
@@ -98,6 +120,17 @@ pub struct TestPropParam {
     pub value: Option<JsonValue>,
 }
 
+#[derive(serde::Deserialize, Debug)]
+pub struct ActionParam {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub siid: i32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub aiid: i32,
+    /// 输入参数
+    #[serde(rename = "in", default)]
+    pub ins: Vec<JsonValue>,
+}
+
 
 
 #[derive(serde::Deserialize, Debug)]
@@ -105,6 +138,12 @@ pub struct GetTemplateParam {
     pub format: TemplateFormat,
 }
 
+#[derive(serde::Deserialize, Debug)]
+pub struct SetupCodeParam {
+    /// 二维码格式,默认svg
+    pub format: Option<QrCodeFormat>,
+}
+
 #[derive(serde::Deserialize, Debug)]
 pub struct AddServiceParam {
     pub memo: Option<String>,
@@ -155,6 +194,46 @@ pub struct DisableParam {
     pub disabled: bool,
 }
 
+/// 添加,修改,测试规则
+#[derive(serde::Deserialize, Debug)]
+pub struct RuleParam {
+    /// 修改时必填
+    #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
+    pub rule_id: Option<i64>,
+    pub name: String,
+    #[serde(default)]
+    pub disabled: bool,
+    pub triggers: Vec<RuleTrigger>,
+    #[serde(default)]
+    pub conditions: Vec<RuleCondition>,
+    pub actions: Vec<RuleAction>,
+    pub remark: Option<String>,
+}
+
+/// 添加,修改场景
+#[derive(serde::Deserialize, Debug)]
+pub struct SceneParam {
+    /// 修改时必填
+    #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
+    pub scene_id: Option<i64>,
+    pub name: String,
+    pub items: Vec<SceneItem>,
+    /// 设置后在该桥接器上创建场景开关
+    #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
+    pub bridge_id: Option<i64>,
+    pub remark: Option<String>,
+}
+
+/// 从设备当前状态生成场景
+#[derive(serde::Deserialize, Debug)]
+pub struct SceneCaptureParam {
+    pub name: String,
+    pub properties: Vec<SceneProperty>,
+    #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
+    pub bridge_id: Option<i64>,
+    pub remark: Option<String>,
+}
+
 
 
 
@@ -10,6 +10,17 @@ pub struct ApplyTemplateParam {
     pub device_id: String,
 }
 
+#[derive(serde::Deserialize, Debug)]
+pub struct ApplyMqttTemplateParam {
+    pub(crate) text: String,
+    pub(crate) format: TemplateFormat,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub(crate) bridge_id: i64,
+    /// 设备标识,如 zigbee2mqtt 的 friendly_name, 重复应用时按此更新
+    pub source_id: String,
+    pub name: String,
+}
+
 #[derive(serde::Deserialize, Debug)]
 pub struct CheckTemplateParam {
     pub(crate) text: String,
@@ -18,5 +29,9 @@ pub struct CheckTemplateParam {
     pub source_platform: String,
 }
 
-
-
+#[derive(serde::Deserialize, Debug)]
+pub struct GenerateTemplateParam {
+    /// 接入方式,默认 xiaomi_wifi
+    pub integration: Option<String>,
+    pub format: Option<TemplateFormat>,
+}
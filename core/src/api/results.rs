@@ -6,7 +6,10 @@ use hap::characteristic::{Format, Unit};
 use hap_metadata::metadata::HapCharacteristic;
 use target_hap::types::HapCharInfo;
 use crate::db::entity::prelude::{HapAccessoryModel, HapBridgeEntity, HapBridgeModel, IotDeviceModel, MiotDeviceModel};
+use crate::hap::setup_code::{QrCodeFormat, SetupCode};
 use crate::init::manager::ble_manager::Status;
+use crate::init::manager::device_health::DeviceHealth;
+use crate::template::checker::TemplateDiagnostic;
 use crate::template::hap::accessory::AccessoryTemplate;
 use crate::template::hl_template::{DeviceTemplate, HlDeviceTemplate, TemplateFormat};
 
@@ -31,11 +34,22 @@ pub struct HapBridgeResult {
     pub is_paired: bool,
 }
 
+#[derive(Debug, serde::Serialize)]
+pub struct SetupCodeResult {
+    #[serde(flatten)]
+    pub code: SetupCode,
+    pub format: QrCodeFormat,
+    /// svg 文本或 png 的 data url
+    pub qr_code: String,
+}
+
 #[derive(Debug, serde::Serialize)]
 pub struct IotDeviceResult {
     #[serde(flatten)]
     pub model: IotDeviceModel,
     pub running: bool,
+    /// 健康状态,未运行的设备为空
+    pub health: Option<DeviceHealth>,
     pub source: Option<MiotDeviceResult>,
 }
 
@@ -52,6 +66,8 @@ pub struct HapAccessoryResult {
 pub struct CheckTemplateResult {
     pub new_devices: Vec<DeviceTemplate>,
     pub new_accessories: Vec<AccessoryTemplate>,
+    /// 模板检查结果
+    pub diagnostics: Vec<TemplateDiagnostic>,
 }
 
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
@@ -135,6 +151,20 @@ pub struct MiotDeviceResult {
     pub full: Option<String>,
 }
 
+/// 局域网扫描结果
+#[derive(Debug, Serialize)]
+pub struct MiotDiscoverResult {
+    pub did: String,
+    pub ip: String,
+    /// 已同步的设备名称,未同步为空
+    pub name: Option<String>,
+    pub model: Option<String>,
+    /// 更新前的ip
+    pub old_ip: Option<String>,
+    /// 是否更新了ip
+    pub updated: bool,
+}
+
 
 #[derive(Debug, serde::Serialize)]
 pub struct NativeBleStatus {
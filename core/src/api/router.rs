@@ -1,9 +1,10 @@
-use axum::Router;
+use axum::{middleware, Router};
+use axum::extract::DefaultBodyLimit;
 use axum::routing::{delete, get, post, put};
-use crate::api::controller;
+use crate::api::{auth, controller};
 use crate::api::state::AppState;
 
-pub fn api() -> Router<AppState> {
+pub fn api(state: AppState) -> Router<AppState> {
     Router::new()
         .nest(
             "/hap_service",
@@ -17,13 +18,16 @@ pub fn api() -> Router<AppState> {
         .nest(
             "/users",
             Router::new()
-                .route("/login", post(controller::sys_users::login))
-                .route("/info", get(controller::sys_users::info)),
+                .route("/info", get(controller::sys_users::info))
+                .route("/password", put(controller::sys_users::change_password)),
         )
         .nest(
             "/system",
             Router::new()
-                .route("/restart", post(controller::system::restart)),
+                .route("/restart", post(controller::system::restart))
+                .route("/backup", get(controller::system::backup))
+                // 备份文件包含模板,放宽请求体大小
+                .route("/restore", post(controller::system::restore).layer(DefaultBodyLimit::max(64 * 1024 * 1024))),
         )
         .nest(
             "/hap_characteristic",
@@ -46,6 +50,7 @@ pub fn api() -> Router<AppState> {
                   .route("/:id", delete(controller::iot_device::delete))
                   .route("/set_property/:id", post(controller::iot_device::set_property))
                   .route("/read_property/:id", post(controller::iot_device::read_property))
+                  .route("/action/:id", post(controller::iot_device::action))
                   .route("/", put(controller::iot_device::edit_device))
               ,
         )
@@ -61,6 +66,11 @@ pub fn api() -> Router<AppState> {
                   .route("/reset/:id", put(controller::hap_bridge::reset))
                   .route("/:id", delete(controller::hap_bridge::delete))
                   .route("/accessories_json/:id", get(controller::hap_bridge::accessories_json))
+                  .route("/setup_code/:id", get(controller::hap_bridge::setup_code))
+                  .route("/pairings/:id", get(controller::hap_bridge::pairings))
+                  .route("/pairings/:id/sessions", get(controller::hap_bridge::pairing_sessions))
+                  .route("/pairings/:id/logs", get(controller::hap_bridge::pairing_logs))
+                  .route("/pairings/:id/:pairing_id", delete(controller::hap_bridge::revoke_pairing))
                   .route("/", post(controller::hap_bridge::add))
                   .route("/", put(controller::hap_bridge::update))
               ,
@@ -88,6 +98,9 @@ pub fn api() -> Router<AppState> {
                   .route("/access", post(controller::miot_device::access))
                   .route("/convert_by_template", post(controller::miot_device::convert_by_template))
                   .route("/handshake", post(controller::miot_device::handshake))
+                  .route("/beacon_key", post(controller::miot_device::fetch_beacon_key))
+                  .route("/discover", post(controller::miot_device::discover))
+                  .route("/manual_add", post(controller::miot_device::manual_add))
                   .route("/accounts", get(controller::miot_device::accounts))
                   .route("/account", post(controller::miot_device::add_account))
                   .route("/account", delete(controller::miot_device::delete_account))
@@ -109,11 +122,14 @@ pub fn api() -> Router<AppState> {
         )
         .nest("/template",
               Router::new()
+                  .route("/errors", get(controller::template::errors))
                   .route("/:id", get(controller::template::get))
                   .route("/check_update", post(controller::template::check_template_update))
                   .route("/check_add", post(controller::template::check_template_add))
                   .route("/text/:id", get(controller::template::get_text))
                   .route("/apply_mijia", post(controller::template::apply_mijia))
+                  .route("/apply_mqtt", post(controller::template::apply_mqtt))
+                  .route("/generate/:model", post(controller::template::generate))
               ,
         )
         .nest("/native_ble",
@@ -122,4 +138,36 @@ pub fn api() -> Router<AppState> {
                   .route("/status", get(controller::native_ble_device::status))
               ,
         )
-}
\ No newline at end of file
+        .nest("/rule",
+              Router::new()
+                  .route("/list", get(controller::rule::list))
+                  .route("/dry_run", post(controller::rule::dry_run))
+                  .route("/dry_run/:id", post(controller::rule::dry_run_by_id))
+                  .route("/disable/:id", put(controller::rule::disable))
+                  .route("/:id", get(controller::rule::get))
+                  .route("/:id", delete(controller::rule::delete))
+                  .route("/", post(controller::rule::add))
+                  .route("/", put(controller::rule::update))
+              ,
+        )
+        .nest("/scene",
+              Router::new()
+                  .route("/list", get(controller::scene::list))
+                  .route("/capture", post(controller::scene::capture))
+                  .route("/apply/:id", post(controller::scene::apply))
+                  .route("/:id", get(controller::scene::get))
+                  .route("/:id", delete(controller::scene::delete))
+                  .route("/", post(controller::scene::add))
+                  .route("/", put(controller::scene::update))
+              ,
+        )
+        // 以上接口需要登入
+        .route_layer(middleware::from_fn_with_state(state, auth::auth))
+        .route("/users/login", post(controller::sys_users::login))
+}
+
+/// prometheus 采集,不需要登入
+pub fn metrics() -> Router<AppState> {
+    Router::new()
+        .route("/metrics", get(controller::metrics::metrics))
+}
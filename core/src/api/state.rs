@@ -2,6 +2,7 @@ use std::ops::Deref;
 use std::sync::{Arc};
 use sea_orm::DatabaseConnection;
 use tokio::sync::{Mutex, oneshot, RwLock};
+use crate::api::auth::TokenKeys;
 use crate::init::Managers;
 
 #[derive(Default, Clone)]
@@ -25,17 +26,20 @@ pub struct AppStateInner {
     conn: DatabaseConnection,
     managers: Managers,
     pub server_shutdown_signal: Mutex<Option<ServerShutdownSignal>>,
+    /// 登入token 密钥
+    pub token_keys: TokenKeys,
 
 }
 
 impl AppStateInner {
-    pub fn new(conn: DatabaseConnection, managers: Managers,
+    pub fn new(conn: DatabaseConnection, managers: Managers, token_keys: TokenKeys,
     ) -> Self {
         let conn_c = conn.clone();
         Self {
             conn,
             managers,
             server_shutdown_signal: Default::default(),
+            token_keys,
         }
     }
 
@@ -61,9 +65,10 @@ impl Deref for AppStateInner {
 impl AppState {
     pub fn new(conn: DatabaseConnection,
                managers: Managers,
+               token_keys: TokenKeys,
     ) -> Self {
         Self {
-            inner: Arc::new(AppStateInner::new(conn, managers)),
+            inner: Arc::new(AppStateInner::new(conn, managers, token_keys)),
         }
     }
 }
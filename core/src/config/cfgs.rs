@@ -4,9 +4,11 @@ use std::fmt::format;
 use std::{env, fs};
 use std::fs::File;
 use std::io::Read;
+use std::net::IpAddr;
 use std::pin::pin;
 use log::info;
 use hap::Pin;
+use target_mqtt::config::MqttExportConfig;
 
 // const CFG_FILE: &str = "config.toml";
 const CFG_FILE: &str = "config.toml";
@@ -41,11 +43,99 @@ pub struct Database {
 }
 
 
+/// 登入认证
+#[derive(Debug, Deserialize)]
+pub struct Auth {
+    /// token 签名密钥,为空时读取环境变量 TOKEN_SECRET,仍为空则每次启动随机生成
+    pub token_secret: Option<String>,
+    /// token 有效期(小时)
+    #[serde(default = "default_token_expire_hours")]
+    pub token_expire_hours: u64,
+    /// 初始管理员密码,为空时读取环境变量 ADMIN_PASSWORD,仍为空则随机生成并打印到日志
+    pub admin_password: Option<String>,
+}
+
+fn default_token_expire_hours() -> u64 {
+    24 * 7
+}
+
+impl Default for Auth {
+    fn default() -> Self {
+        Self {
+            token_secret: None,
+            token_expire_hours: default_token_expire_hours(),
+            admin_password: None,
+        }
+    }
+}
+
+impl Auth {
+    pub fn token_secret(&self) -> Option<String> {
+        self.token_secret.clone()
+            .or_else(|| env::var("TOKEN_SECRET").ok())
+            .filter(|s| !s.is_empty())
+    }
+    pub fn admin_password(&self) -> Option<String> {
+        self.admin_password.clone()
+            .or_else(|| env::var("ADMIN_PASSWORD").ok())
+            .filter(|s| !s.is_empty())
+    }
+}
+
+/// 数据加密
+#[derive(Debug, Deserialize, Default)]
+pub struct Security {
+    /// 主密钥,用于加密米家账号密码与会话,为空时读取环境变量 HL_MASTER_KEY
+    /// 不要与数据目录放在一起备份
+    pub master_key: Option<String>,
+}
+
+impl Security {
+    pub fn master_key(&self) -> Option<String> {
+        self.master_key.clone()
+            .or_else(|| env::var("HL_MASTER_KEY").ok())
+            .filter(|s| !s.is_empty())
+    }
+}
+
+/// 所在位置,用于计算日出日落
+#[derive(Debug, Clone, Deserialize)]
+pub struct Location {
+    /// 纬度,北纬为正
+    pub latitude: f64,
+    /// 经度,东经为正
+    pub longitude: f64,
+}
+
+
+/// hap 网络配置,多网卡或容器中自动选择的地址不对时指定
+#[derive(Debug, Clone, Deserialize, Default)]
+pub struct HapNetwork {
+    /// 绑定的网卡名称,如 eth0,为空时自动选择
+    pub interface: Option<String>,
+    /// mdns 广播的地址,为空时使用网卡上的地址
+    #[serde(default)]
+    pub advertised_ips: Vec<IpAddr>,
+}
+
 /// 配置文件
 #[derive(Debug, Deserialize)]
 pub struct Configs {
     /// 程序配置
     pub server: Server,
+    #[serde(default)]
+    pub auth: Auth,
+    #[serde(default)]
+    pub security: Security,
+    /// mqtt 导出,为空时不启用
+    #[serde(default)]
+    pub mqtt_export: Option<MqttExportConfig>,
+    /// 位置,规则中的日出日落触发需要
+    #[serde(default)]
+    pub location: Option<Location>,
+    /// hap 监听与 mdns 广播的网络
+    #[serde(default)]
+    pub hap: HapNetwork,
     // pub hap_config: HapConfig,
     // pub database: Database,
 }
@@ -84,6 +174,11 @@ impl Configs {
                 data_dir: "./data".to_string(),
                 db_schema: None,
             },
+            auth: Default::default(),
+            security: Default::default(),
+            mqtt_export: None,
+            location: None,
+            hap: Default::default(),
         };
         if let Ok(var) = env::var("DATA_DIR") {
             config.server.data_dir = var;
@@ -1,2 +1,3 @@
 pub mod cfgs;
-pub mod context;
\ No newline at end of file
+pub mod context;
+pub mod secret;
@@ -0,0 +1,38 @@
+use anyhow::anyhow;
+use once_cell::sync::OnceCell;
+use miot_proto::secret::SecretCipher;
+use crate::config::cfgs::Configs;
+
+/// 主密钥,启动时从配置初始化,数据库迁移时也会用到
+static MASTER_CIPHER: OnceCell<Option<SecretCipher>> = OnceCell::new();
+
+pub const MASTER_KEY_MISSING: &str = "未配置主密钥,请在配置文件 [security] master_key 或环境变量 HL_MASTER_KEY 中设置";
+
+pub fn init_master_key(config: &Configs) -> anyhow::Result<()> {
+    let cipher = match config.security.master_key() {
+        Some(key) => Some(SecretCipher::new(key.as_str())?),
+        None => None,
+    };
+    MASTER_CIPHER.set(cipher)
+        .map_err(|_| anyhow!("主密钥重复初始化"))
+}
+
+/// 获取主密钥,未配置时返回错误
+pub fn master_cipher() -> anyhow::Result<SecretCipher> {
+    MASTER_CIPHER.get()
+        .and_then(|c| c.clone())
+        .ok_or(anyhow!(MASTER_KEY_MISSING))
+}
+
+/// 加密敏感字段
+pub fn encrypt_secret(plain: &str) -> anyhow::Result<String> {
+    Ok(master_cipher()?.encrypt_str(plain))
+}
+
+/// 解密敏感字段,兼容未迁移的明文
+pub fn decrypt_secret(data: &str) -> anyhow::Result<String> {
+    if !SecretCipher::is_encrypted(data) {
+        return Ok(data.to_string());
+    }
+    master_cipher()?.decrypt_str(data)
+}
@@ -66,6 +66,9 @@ pub enum SourcePlatform {
     /// 本地蓝牙
     #[strum(serialize = "ble-native")]
     BleNative,
+    /// 通用mqtt
+    #[strum(serialize = "mqtt")]
+    Mqtt,
 }
 
 
@@ -109,6 +112,9 @@ pub struct BleParam {
     pub info: DeviceInfo,
     /// 规则
     pub mapping: Vec<MiotSpecIdType>,
+    /// 加密广播的 bindkey, 优先于米家云的 beacon_key
+    #[serde(default)]
+    pub bindkey: Option<String>,
 }
 
 impl BleParam {
@@ -30,6 +30,8 @@ impl EntityName for Entity {
 #[derive(Clone, Debug, PartialEq, DeriveModel, DeriveActiveModel, Eq, Serialize, Deserialize)]
 pub struct Model {
     pub account: String,
+    /// 主密钥加密后的密码
+    #[serde(skip_serializing)]
     pub password: String,
     pub status: MiAccountStatus,
     /// 更新时间
@@ -9,4 +9,8 @@ pub mod hap_characteristic;
 pub mod miot_device;
 pub mod common;
 
-pub mod mi_account;
\ No newline at end of file
+pub mod mi_account;
+pub mod sys_user;
+pub mod rule;
+pub mod scene;
+pub mod pairing_log;
@@ -36,4 +36,24 @@ pub use super::miot_device::Column as MiotDeviceColumn;
 pub use super::mi_account::Entity as MiAccountEntity;
 pub use super::mi_account::Model as MiAccountModel;
 pub use super::mi_account::ActiveModel as MiAccountActiveModel;
-pub use super::mi_account::Column as MiAccountColumn;
\ No newline at end of file
+pub use super::mi_account::Column as MiAccountColumn;
+
+pub use super::sys_user::Entity as SysUserEntity;
+pub use super::sys_user::Model as SysUserModel;
+pub use super::sys_user::ActiveModel as SysUserActiveModel;
+pub use super::sys_user::Column as SysUserColumn;
+
+pub use super::rule::Entity as RuleEntity;
+pub use super::rule::Model as RuleModel;
+pub use super::rule::ActiveModel as RuleActiveModel;
+pub use super::rule::Column as RuleColumn;
+
+pub use super::scene::Entity as SceneEntity;
+pub use super::scene::Model as SceneModel;
+pub use super::scene::ActiveModel as SceneActiveModel;
+pub use super::scene::Column as SceneColumn;
+
+pub use super::pairing_log::Entity as PairingLogEntity;
+pub use super::pairing_log::Model as PairingLogModel;
+pub use super::pairing_log::ActiveModel as PairingLogActiveModel;
+pub use super::pairing_log::Column as PairingLogColumn;
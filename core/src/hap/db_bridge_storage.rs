@@ -1,24 +1,36 @@
 use std::collections::HashMap;
+use std::sync::Arc;
+use anyhow::anyhow;
 use async_trait::async_trait;
-use impl_new::New;
-use log::info;
+use dashmap::DashMap;
+use lazy_static::lazy_static;
+use log::{error, info};
 use sea_orm::ActiveValue::Set;
 use sea_orm::{ActiveModelTrait, DatabaseConnection, EntityTrait, NotSet};
 use tap::TapFallible;
 use tokio::sync::RwLock;
-use hap::{Config, Ed25519Keypair};
+use hap::{BonjourStatusFlag, Config, Ed25519Keypair};
 use hap::Error::Unknown;
 use hap::pairing::Pairing;
 use hap::storage::Storage;
 use crate::db::entity::hap_bridge::{BonjourStatusFlagWrapper, Model};
+use crate::db::entity::pairing_log::PairingAction;
 use crate::db::entity::prelude::{HapBridgeActiveModel, HapBridgeEntity};
 use crate::hap::rand_utils::{compute_setup_hash, pin_code_from_str};
+use crate::service::pairing_service;
+use crate::service::pairing_service::is_admin;
+
+type PairingCache = Arc<RwLock<Option<HashMap<uuid::Uuid, Pairing>>>>;
+
+lazy_static! {
+    /// 桥接器的配对缓存,运行中的服务器和接口创建的存储共用,撤销配对后服务器立即生效
+    static ref PAIRING_CACHES: DashMap<i64, PairingCache> = DashMap::new();
+}
 
-#[derive(New)]
 pub struct DbBridgesStorage {
     bid: i64,
     conn: DatabaseConnection,
-    pairing_cache: RwLock<Option<HashMap<uuid::Uuid, Pairing>>>,
+    pairing_cache: PairingCache,
 }
 
 #[async_trait]
@@ -54,7 +66,6 @@ impl Storage for DbBridgesStorage {
     async fn save_config(&mut self, config: &Config) -> hap::Result<()> {
         let model = HapBridgeActiveModel {
             bridge_id: Set(self.bid),
-            host: Set(Some(config.host.to_string())),
             configuration_number: Set(config.configuration_number as i64),
             status_flag: Set(BonjourStatusFlagWrapper(config.status_flag)),
             create_at: NotSet,
@@ -105,7 +116,7 @@ impl Storage for DbBridgesStorage {
             map.insert(pairing.id, pairing.clone());
         }
         let mut bridge = self.find_bridge().await?;
-        bridge.pairings.0.insert(pairing.id, pairing.clone());
+        let old = bridge.pairings.0.insert(pairing.id, pairing.clone());
         let model = HapBridgeActiveModel {
             bridge_id: Set(self.bid),
             pairings: Set(bridge.pairings),
@@ -115,12 +126,17 @@ impl Storage for DbBridgesStorage {
             .await
             .tap_err(|e| println!("save err{:?}", e))
             .map_err(|e| Unknown(Box::new(e)))?;
+        let action = if old.is_some() { PairingAction::Update } else { PairingAction::Add };
+        self.log(Some(pairing), action, None).await;
         Ok(())
     }
 
     async fn delete_pairing(&mut self, id: &uuid::Uuid) -> hap::Result<()> {
+        if let Some(map) = self.pairing_cache.write().await.as_mut() {
+            map.remove(id);
+        }
         let mut bridge = self.find_bridge().await?;
-        bridge.pairings.0.remove(id);
+        let old = bridge.pairings.0.remove(id);
         let model = HapBridgeActiveModel {
             bridge_id: Set(self.bid),
             pairings: Set(bridge.pairings),
@@ -128,6 +144,9 @@ impl Storage for DbBridgesStorage {
         };
         model.save(&self.conn).await
             .map_err(|e| Unknown(Box::new(e)))?;
+        if let Some(old) = old {
+            self.log(Some(&old), PairingAction::Remove, None).await;
+        }
         Ok(())
     }
 
@@ -155,6 +174,52 @@ impl Storage for DbBridgesStorage {
 }
 
 impl DbBridgesStorage {
+    pub fn new(bid: i64, conn: DatabaseConnection) -> Self {
+        let pairing_cache = PAIRING_CACHES.entry(bid)
+            .or_default()
+            .clone();
+        Self { bid, conn, pairing_cache }
+    }
+
+    /// 直接修改数据库中的配对后清除缓存
+    pub async fn clear_cache(bid: i64) {
+        if let Some(cache) = PAIRING_CACHES.get(&bid).map(|c| c.clone()) {
+            cache.write().await.take();
+        }
+    }
+
+    /// 撤销控制器,不存在管理员时清空所有配对,返回是否已清空
+    pub async fn revoke_pairing(&mut self, id: &uuid::Uuid, operator: String) -> anyhow::Result<bool> {
+        let mut bridge = self.find_bridge().await
+            .map_err(|e| anyhow!("{:?}", e))?;
+        let pairing = bridge.pairings.0.remove(id)
+            .ok_or(anyhow!("配对不存在"))?;
+        let mut removed = vec![pairing];
+        let mut model = HapBridgeActiveModel {
+            bridge_id: Set(self.bid),
+            ..Default::default()
+        };
+        let cleared = !bridge.pairings.0.values().any(is_admin);
+        if cleared {
+            removed.extend(bridge.pairings.0.drain().map(|(_, p)| p));
+            model.status_flag = Set(BonjourStatusFlagWrapper(BonjourStatusFlag::NotPaired));
+        }
+        model.pairings = Set(bridge.pairings);
+        model.update(&self.conn).await?;
+        self.pairing_cache.write().await.take();
+        for p in removed.iter() {
+            self.log(Some(p), PairingAction::Revoke, Some(operator.clone())).await;
+        }
+        Ok(cleared)
+    }
+
+    /// 写审计日志,失败不影响配对
+    async fn log(&self, pairing: Option<&Pairing>, action: PairingAction, operator: Option<String>) {
+        if let Err(e) = pairing_service::add_log(&self.conn, self.bid, pairing, action, operator).await {
+            error!("写入配对日志失败:{:?}", e);
+        }
+    }
+
     async fn find_bridge(&self) -> Result<Model, hap::Error> {
         HapBridgeEntity::find_by_id(self.bid)
             .one(&self.conn)
@@ -164,11 +229,17 @@ impl DbBridgesStorage {
     }
 }
 
+#[cfg(test)]
 mod test {
+    use sea_orm::Database;
     use uuid::Uuid;
     use hap::pairing::{Pairing, Permissions};
     use hap::storage::Storage;
-    use crate::db::init::open_db;
+    use crate::db::entity::hap_bridge::BridgeCategory;
+    use crate::db::init::{migrator_up, open_db};
+    use crate::db::service::hap_bridge_service::create_hap_bridge;
+    use crate::hap::db_bridge_storage::DbBridgesStorage;
+    use crate::service::pairing_service;
 
     #[tokio::test]
     pub async fn test() {
@@ -192,4 +263,24 @@ mod test {
         println!("{:?}", config.status_flag);
         tokio::time::sleep(std::time::Duration::from_secs(1)).await;
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    pub async fn test_revoke_live_storage() {
+        let conn = Database::connect("sqlite::memory:").await.unwrap();
+        migrator_up(&conn).await;
+        let bridge = create_hap_bridge(&conn, None, BridgeCategory::Bridge, "test".to_string(), false).await.unwrap();
+        // 运行中的服务器持有的存储
+        let mut server_storage = DbBridgesStorage::new(bridge.bridge_id, conn.clone());
+        let admin = Pairing { id: Uuid::new_v4(), permissions: Permissions::Admin, public_key: [1; 32] };
+        let user = Pairing { id: Uuid::new_v4(), permissions: Permissions::User, public_key: [2; 32] };
+        server_storage.save_pairing(&admin).await.unwrap();
+        server_storage.save_pairing(&user).await.unwrap();
+        // 加载到缓存
+        assert!(server_storage.load_pairing(&user.id).await.is_ok());
+
+        let cleared = pairing_service::revoke_pairing(&conn, bridge.bridge_id, user.id, "admin".to_string()).await.unwrap();
+        assert!(!cleared);
+        assert!(server_storage.load_pairing(&user.id).await.is_err());
+        assert!(server_storage.load_pairing(&admin.id).await.is_ok());
+    }
+}
@@ -4,4 +4,10 @@
 pub mod hap_type;
 pub(crate) mod db_bridge_storage;
 pub mod rand_utils;
+/// hap 监听网卡与 mdns 地址
+pub mod network;
+/// 配对链接和二维码
+pub mod setup_code;
+/// 场景开关
+pub mod scene_switch;
 
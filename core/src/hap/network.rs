@@ -0,0 +1,103 @@
+use std::net::IpAddr;
+
+use anyhow::anyhow;
+use log::{info, warn};
+
+use crate::config::cfgs::HapNetwork;
+
+/// 本机网卡地址 (网卡名称, 地址)
+fn local_addrs() -> anyhow::Result<Vec<(String, IpAddr)>> {
+    let list = if_addrs::get_if_addrs()?
+        .into_iter()
+        .filter(|i| !i.is_loopback())
+        .map(|i| (i.name.clone(), i.ip()))
+        .collect();
+    Ok(list)
+}
+
+/// 网卡上的地址,ipv4 在前
+fn interface_ips(addrs: &[(String, IpAddr)], interface: &str) -> Vec<IpAddr> {
+    let mut ips: Vec<IpAddr> = addrs.iter()
+        .filter(|(name, _)| name == interface)
+        .map(|(_, ip)| *ip)
+        .collect();
+    ips.sort_by_key(|ip| !ip.is_ipv4());
+    ips
+}
+
+/// 启动时校验配置的网卡和广播地址
+pub fn validate(cfg: &HapNetwork) -> anyhow::Result<()> {
+    let addrs = local_addrs()?;
+    if let Some(interface) = cfg.interface.as_ref() {
+        let ips = interface_ips(&addrs, interface);
+        if ips.is_empty() {
+            let names: Vec<&str> = addrs.iter().map(|(n, _)| n.as_str()).collect();
+            return Err(anyhow!("hap 网卡:{}不存在或没有地址,可用网卡:{:?}", interface, names));
+        }
+        info!("hap 绑定网卡:{},地址:{:?}", interface, ips);
+    }
+    for ip in cfg.advertised_ips.iter() {
+        if !addrs.iter().any(|(_, i)| i == ip) {
+            // 容器端口映射时广播宿主机地址
+            warn!("hap 广播地址:{}不在本机网卡上", ip);
+        }
+    }
+    Ok(())
+}
+
+/// 桥接器 mdns 广播的地址
+/// 优先使用配置的广播地址(容器端口映射),其次是桥接器监听的地址,都为空时广播所有网卡
+pub fn mdns_ips(cfg: &HapNetwork, host: Option<IpAddr>) -> Vec<IpAddr> {
+    if !cfg.advertised_ips.is_empty() {
+        return cfg.advertised_ips.clone();
+    }
+    host.into_iter().collect()
+}
+
+/// 桥接器监听的地址
+/// 优先使用桥接器的 host,其次是配置的网卡,都为空时返回 None 自动选择
+pub fn bridge_host(cfg: &HapNetwork, host: Option<&str>) -> anyhow::Result<Option<IpAddr>> {
+    let addrs = local_addrs()?;
+    if let Some(host) = host.filter(|h| !h.is_empty()) {
+        match host.parse::<IpAddr>() {
+            Ok(ip) if addrs.iter().any(|(_, i)| *i == ip) => return Ok(Some(ip)),
+            Ok(_) => warn!("桥接器地址:{}不在本机网卡上,忽略", host),
+            Err(_) => warn!("桥接器地址:{}格式错误,忽略", host),
+        }
+    }
+    let ip = cfg.interface.as_ref()
+        .and_then(|interface| interface_ips(&addrs, interface).into_iter().next());
+    Ok(ip)
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::IpAddr;
+
+    use crate::config::cfgs::HapNetwork;
+    use crate::hap::network::{interface_ips, mdns_ips};
+
+    #[test]
+    fn test_interface_ips() {
+        let v6: IpAddr = "fe80::1".parse().unwrap();
+        let v4: IpAddr = "192.168.1.10".parse().unwrap();
+        let addrs = vec![
+            ("eth0".to_string(), v6),
+            ("eth0".to_string(), v4),
+            ("eth1".to_string(), "10.0.0.2".parse().unwrap()),
+        ];
+        assert_eq!(interface_ips(&addrs, "eth0"), vec![v4, v6]);
+        assert!(interface_ips(&addrs, "eth2").is_empty());
+    }
+
+    #[test]
+    fn test_mdns_ips() {
+        let host: IpAddr = "192.168.1.10".parse().unwrap();
+        let mut cfg = HapNetwork { interface: None, advertised_ips: vec![] };
+        assert_eq!(mdns_ips(&cfg, Some(host)), vec![host]);
+        assert!(mdns_ips(&cfg, None).is_empty());
+        let advertised: IpAddr = "10.0.0.2".parse().unwrap();
+        cfg.advertised_ips = vec![advertised];
+        assert_eq!(mdns_ips(&cfg, Some(host)), vec![advertised]);
+    }
+}
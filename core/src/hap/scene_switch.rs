@@ -0,0 +1,112 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use log::error;
+use serde_aux::prelude::deserialize_number_from_string;
+use serde_json::{json, Value};
+
+use hl_integration::JsonValue;
+use target_hap::delegate::{CharReadParam, CharReadResult, CharUpdateParam, CharUpdateResult};
+use target_hap::delegate::database::get_hap_model_ext_database;
+use target_hap::delegate::model::{AccessoryModelExtConstructor, ContextPointer, HapModelExt, HapModelExtPointer, ReadValueResult, UpdateValueResult};
+use target_hap::hap_type_wrapper::HapTypeWrapper;
+use target_hap::types::CharIdentifier;
+
+use crate::config::context::get_app_context;
+use crate::service::scene_service;
+
+pub const SCENE_SWITCH_MODEL: &str = "common.scene_switch";
+pub const SCENE_SWITCH_STAG: &str = "scene";
+/// 开关打开后自动复位的时间
+const RESET_DELAY: Duration = Duration::from_secs(1);
+
+#[derive(Debug, serde::Deserialize)]
+struct SceneSwitchParam {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    scene_id: i64,
+}
+
+fn is_on(value: &Value) -> bool {
+    value.as_bool().unwrap_or(false) || value.as_u64() == Some(1)
+}
+
+/// 场景开关模型
+/// 打开时执行场景,随后自动复位为关闭
+pub struct ModelExt {
+    ctx: ContextPointer,
+    scene_id: i64,
+}
+
+impl AccessoryModelExtConstructor for ModelExt {
+    fn new(ctx: ContextPointer, params: Option<JsonValue>) -> anyhow::Result<HapModelExtPointer> {
+        let params = params.ok_or(anyhow!("scene switch params is none"))?;
+        let param: SceneSwitchParam = serde_json::from_value(params)?;
+        Ok(Arc::new(Self {
+            ctx,
+            scene_id: param.scene_id,
+        }))
+    }
+}
+
+#[async_trait::async_trait]
+impl HapModelExt for ModelExt {
+    async fn read_chars_value(&self, params: Vec<CharReadParam>) -> ReadValueResult {
+        Ok(params.iter()
+            .map(|p| CharReadResult::success(p, Some(json!(false))))
+            .collect())
+    }
+
+    async fn update_chars_value(&self, params: Vec<CharUpdateParam>) -> UpdateValueResult {
+        let mut result = vec![];
+        for param in params.into_iter() {
+            if is_on(&param.new_value) {
+                let scene_id = self.scene_id;
+                tokio::spawn(async move {
+                    let context = get_app_context();
+                    if let Err(e) = scene_service::apply_scene_by_id(&context.conn, &context.device_manager, scene_id).await {
+                        error!("执行场景:{}失败:{:?}", scene_id, e);
+                    }
+                });
+                let ctx = self.ctx.clone();
+                tokio::spawn(async move {
+                    tokio::time::sleep(RESET_DELAY).await;
+                    let cid = CharIdentifier::new(SCENE_SWITCH_STAG.to_string(), HapTypeWrapper::PowerState);
+                    if let Err(e) = ctx.set_char_value(&cid, json!(false)).await {
+                        error!("场景开关复位失败:{:?}", e);
+                    }
+                });
+            }
+            result.push(CharUpdateResult {
+                cid: param.cid,
+                success: true,
+            });
+        }
+        Ok(result)
+    }
+
+    fn is_subscribe_event(&self) -> bool {
+        false
+    }
+}
+
+/// 注册内置的场景开关模型
+pub fn register_model_ext() -> anyhow::Result<()> {
+    get_hap_model_ext_database()
+        .insert(SCENE_SWITCH_MODEL.to_string(), ModelExt::new)
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use crate::hap::scene_switch::is_on;
+
+    #[test]
+    fn test_is_on() {
+        assert!(is_on(&json!(true)));
+        assert!(is_on(&json!(1)));
+        assert!(!is_on(&json!(false)));
+        assert!(!is_on(&json!(0)));
+    }
+}
@@ -0,0 +1,101 @@
+use anyhow::anyhow;
+use base64::Engine;
+use base64::prelude::BASE64_STANDARD;
+use image::{ImageOutputFormat, Luma};
+use qrcode::QrCode;
+use qrcode::render::svg;
+use sea_orm::ActiveEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::db::entity::prelude::HapBridgeModel;
+use crate::hap::rand_utils::{compute_setup_hash, gen_homekit_setup_uri, HomeKitSetupUri};
+
+/// 二维码最小尺寸
+const QR_CODE_SIZE: u32 = 256;
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum QrCodeFormat {
+    #[default]
+    Svg,
+    Png,
+}
+
+/// 桥接器的配对信息
+#[derive(Debug, Clone, Serialize)]
+pub struct SetupCode {
+    /// X-HM:// 配对链接
+    pub setup_uri: String,
+    /// 格式为 XXX-XX-XXX
+    pub pin_code: String,
+    pub setup_id: String,
+    /// mdns txt 记录中的 sh
+    pub setup_hash: String,
+}
+
+impl SetupCode {
+    pub fn from_bridge(model: &HapBridgeModel) -> Self {
+        let setup_uri = gen_homekit_setup_uri(&HomeKitSetupUri {
+            category: model.category.to_value() as u64,
+            password: model.pin_code as u64,
+            setup_id: model.setup_id.clone(),
+            ..Default::default()
+        });
+        Self {
+            setup_uri,
+            pin_code: format_pin_code(model.pin_code),
+            setup_id: model.setup_id.clone(),
+            setup_hash: compute_setup_hash(model.setup_id.as_str(), model.mac.as_str()),
+        }
+    }
+}
+
+pub fn format_pin_code(pin_code: i64) -> String {
+    let pin = format!("{:08}", pin_code);
+    format!("{}-{}-{}", &pin[0..3], &pin[3..5], &pin[5..8])
+}
+
+/// 渲染二维码, svg 返回文本, png 返回 data url
+pub fn render_qr_code(text: &str, format: QrCodeFormat) -> anyhow::Result<String> {
+    let code = QrCode::new(text.as_bytes())?;
+    match format {
+        QrCodeFormat::Svg => {
+            Ok(code.render::<svg::Color>()
+                .min_dimensions(QR_CODE_SIZE, QR_CODE_SIZE)
+                .build())
+        }
+        QrCodeFormat::Png => {
+            let image = code.render::<Luma<u8>>()
+                .min_dimensions(QR_CODE_SIZE, QR_CODE_SIZE)
+                .build();
+            let mut buf = std::io::Cursor::new(vec![]);
+            image.write_to(&mut buf, ImageOutputFormat::Png)
+                .map_err(|e| anyhow!("生成png失败:{e}"))?;
+            Ok(format!("data:image/png;base64,{}", BASE64_STANDARD.encode(buf.into_inner())))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::hap::rand_utils::{compute_setup_hash, gen_homekit_setup_uri, HomeKitSetupUri};
+    use crate::hap::setup_code::{format_pin_code, QrCodeFormat, render_qr_code};
+
+    #[test]
+    fn test_setup_code() {
+        let uri = gen_homekit_setup_uri(&HomeKitSetupUri {
+            password: 29826556,
+            setup_id: "3QYT".to_string(),
+            category: 8,
+            ..Default::default()
+        });
+        assert_eq!(uri, "X-HM://0081210CC3QYT");
+        assert_eq!(compute_setup_hash("F1PV", "5e:56:ea:f2:6e:7c"), "Wurniw==");
+        assert_eq!(format_pin_code(11122333), "111-22-333");
+
+        let svg = render_qr_code(uri.as_str(), QrCodeFormat::Svg).unwrap();
+        assert!(svg.contains("<svg"));
+        let png = render_qr_code(uri.as_str(), QrCodeFormat::Png).unwrap();
+        assert!(png.starts_with("data:image/png;base64,"));
+    }
+}
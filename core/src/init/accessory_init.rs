@@ -162,17 +162,6 @@ async fn init_hap_delegate(aid: u64, hap_accessory: HapAccessoryModel, device: &
         // 事件订阅
         let model = HapAccessoryDelegateModel::new(ctx, hap_accessory.hap_model_delegates.0).await?;
         model.init().await?;
-
-
-        if model.model_ext.is_subscribe_event() {
-            let model_c = model.clone();
-            device.add_listener(Box::new(move |data| {
-                let model_c = model_c.clone();
-                Box::pin(async move {
-                    model_c.on_event(data).await;
-                })
-            })).await;
-        };
         Some(model)
     }
     )
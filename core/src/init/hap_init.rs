@@ -1,13 +1,14 @@
 use std::str::FromStr;
 use anyhow::anyhow;
 use axum::body::HttpBody;
-use log::error;
+use log::{error, warn};
 use sea_orm::{ColumnTrait, ConnectionTrait, DatabaseConnection, EntityTrait, JsonValue, QueryFilter};
-use tokio::sync::RwLock;
+use tokio::sync::broadcast::error::RecvError;
 
 use hap::accessory::{AccessoryInformation, HapAccessory};
 use hap::accessory::bridge::BridgeAccessory;
 use hap::characteristic::configured_name::ConfiguredNameCharacteristic;
+use hap::characteristic::status_fault::StatusFaultCharacteristic;
 use hap::characteristic::{CharacteristicCallbacks, HapCharacteristic};
 use hap::HapType;
 use hap::server::{IpServer, Server};
@@ -17,6 +18,7 @@ use hap::storage::Storage;
 use crate::config::context::get_app_context;
 use crate::db::entity::prelude::{HapAccessoryColumn, HapAccessoryEntity, HapBridgeColumn, HapBridgeEntity, HapBridgeModel, HapCharacteristicModel, HapServiceModel};
 use crate::hap::db_bridge_storage::DbBridgesStorage;
+use crate::hap::network;
 use crate::init::{DevicePointer, HapAccessoryPointer};
 use crate::init::characteristic_init::to_characteristic;
 use crate::init::manager::device_manager::IotDeviceManager;
@@ -53,13 +55,17 @@ pub async fn add_hap_bridge(conn: &DatabaseConnection, bridge: HapBridgeModel, m
         .ok_or(anyhow!("未找到桥接器:{:?}", bid))?;
     let is_single_accessory = bridge_model.single_accessory;
 
-    let mut storage = DbBridgesStorage::new(bid, conn.clone(),RwLock::new(None));
+    let mut storage = DbBridgesStorage::new(bid, conn.clone());
     //config
     let mut hap_config = storage.load_config().await?;
-    hap_config.redetermine_local_ip();
+    let host = network::bridge_host(&config.hap, bridge_model.host.as_deref())?;
+    match host {
+        Some(ip) => hap_config.host = ip,
+        None => hap_config.redetermine_local_ip(),
+    }
     storage.save_config(&hap_config).await?;
 
-    let mdns = manage.get_mdns().await?;
+    let mdns = manage.get_mdns(network::mdns_ips(&config.hap, host)).await?;
 
     let server = IpServer::new(hap_config, storage, mdns).await?;
     if is_single_accessory {
@@ -96,10 +102,92 @@ pub async fn add_hap_bridge(conn: &DatabaseConnection, bridge: HapBridgeModel, m
         server.add_arc_accessory(accessory.accessory.clone()).await?;
     }
     server.configuration_number_incr().await;
+    let device_ids: Vec<i64> = accessories.iter().map(|a| a.device_id).collect();
     manage.push_server(bid, server, accessories);
+    for device_id in device_ids {
+        apply_device_health(&manage, &iot_device_map, device_id).await;
+    }
     Ok(())
 }
 
+/// 新加载的配件使用设备当前的在线状态
+async fn apply_device_health(manage: &HapManage, iot_device_map: &IotDeviceManager, device_id: i64) {
+    if let Some(health) = iot_device_map.health.get(device_id) {
+        manage.set_device_online(device_id, health.online).await;
+    }
+}
+
+
+/// 停止并重新创建桥接器服务,会断开桥接器上的所有会话
+pub async fn restart_hap_bridge(conn: &DatabaseConnection, manage: HapManage, iot_device_map: IotDeviceManager, bid: i64) -> anyhow::Result<()> {
+    manage.stop_server(bid).await?;
+    // 等mdns 注销成功
+    tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
+    let bridge = HapBridgeEntity::find_by_id(bid)
+        .one(conn)
+        .await?
+        .ok_or(anyhow!("桥接器不存在"))?;
+    add_hap_bridge(conn, bridge, manage, iot_device_map).await
+}
+
+/// 配件修改后在运行中的桥接器上添加,替换或移除配件,不重启桥接器
+/// 单配件桥接器仍然需要重启
+pub async fn reload_accessory(conn: &DatabaseConnection, manage: HapManage, iot_device_map: IotDeviceManager, aid: i64) -> anyhow::Result<()> {
+    let accessory = HapAccessoryEntity::find_by_id(aid)
+        .one(conn)
+        .await?;
+    let accessory = match accessory {
+        Some(a) if !a.disabled => a,
+        _ => {
+            return manage.remove_accessory(aid as u64).await;
+        }
+    };
+    let bid = accessory.bridge_id;
+    if !manage.is_server_running(bid) {
+        // 桥接器启动时加载
+        manage.remove_accessory(aid as u64).await?;
+        return Ok(());
+    }
+    let bridge = HapBridgeEntity::find_by_id(bid)
+        .one(conn)
+        .await?
+        .ok_or(anyhow!("桥接器不存在"))?;
+    if bridge.single_accessory {
+        return restart_hap_bridge(conn, manage, iot_device_map, bid).await;
+    }
+    let device_id = accessory.device_id;
+    let device = match iot_device_map.get_device(device_id) {
+        Some(d) => d,
+        None => {
+            // 设备未运行,移除旧配件,避免继续使用已停止的设备,桥接器重启时加载
+            warn!("配件:{}的设备:{}未运行,跳过加载", aid, device_id);
+            manage.remove_accessory(aid as u64).await?;
+            return Ok(());
+        }
+    };
+    let ptr = super::accessory_init::init_hap_accessory(conn, manage.clone(), device, accessory).await?;
+    manage.put_accessory(bid, AccessoryRelation {
+        aid: aid as u64,
+        device_id,
+        accessory: ptr,
+    }).await?;
+    apply_device_health(&manage, &iot_device_map, device_id).await;
+    Ok(())
+}
+
+/// 重新加载设备的所有配件,设备重启后配件需要使用新的设备,返回配件id
+pub async fn reload_device_accessories(conn: &DatabaseConnection, manage: HapManage, iot_device_map: IotDeviceManager, device_id: i64) -> anyhow::Result<Vec<i64>> {
+    let accessories = HapAccessoryEntity::find()
+        .filter(HapAccessoryColumn::DeviceId.eq(device_id))
+        .all(conn)
+        .await?;
+    let mut aids = vec![];
+    for accessory in accessories {
+        reload_accessory(conn, manage.clone(), iot_device_map.clone(), accessory.aid).await?;
+        aids.push(accessory.aid);
+    }
+    Ok(aids)
+}
 
 /// 基于设备初始化配件列表
 async fn init_hap_accessories<C: ConnectionTrait>(conn: &C, hap_manage: HapManage, bridge_id: i64, iot_device_map: IotDeviceManager) -> anyhow::Result<Vec<AccessoryRelation>> {
@@ -191,6 +279,18 @@ pub(crate) async fn add_service(ctx: InitServiceContext, service_chs: (HapServic
             hap_service.push_characteristic(Box::new(name));
         }
     };
+    // 主服务缺少状态特征时补一个 StatusFault,用于标记设备离线
+    if service.primary && success {
+        let has_status = hap_service.get_characteristics().iter()
+            .any(|c| matches!(c.get_type(), HapType::StatusFault | HapType::StatusActive));
+        if !has_status {
+            let id = ctx.sid + len as u64 + 1;
+            len += 1;
+            let mut fault = StatusFaultCharacteristic::new(id, ctx.aid);
+            fault.set_value(serde_json::json!(0)).await?;
+            hap_service.push_characteristic(Box::new(fault));
+        }
+    }
 
     if success {
         ctx.accessory.write().await.push_service(service.tag, Box::new(hap_service));
@@ -205,4 +305,23 @@ pub(crate) async fn add_service(ctx: InitServiceContext, service_chs: (HapServic
 pub fn test_format() {
     /* let f = Format::from_str(Default::default());
      println!("{:?}", f);*/
-}
\ No newline at end of file
+}
+/// 设备上下线时更新配件的 StatusFault/StatusActive
+pub fn sync_device_health(device_manager: IotDeviceManager, manage: HapManage) {
+    let mut rx = device_manager.health.subscribe();
+    tokio::spawn(async move {
+        // 先同步已离线的设备
+        for event in device_manager.health.list() {
+            if !event.health.online {
+                manage.set_device_online(event.device_id, false).await;
+            }
+        }
+        loop {
+            match rx.recv().await {
+                Ok(event) => manage.set_device_online(event.device_id, event.health.online).await,
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => break,
+            }
+        }
+    });
+}
@@ -1,6 +1,7 @@
 use anyhow::anyhow;
 use sea_orm::ActiveValue::Set;
-use sea_orm::NotSet;
+use sea_orm::{JsonValue, NotSet};
+use target_hap::delegate::model::check_delegate_chars;
 use target_hap::types::{CharIdentifier, HapCharInfo, ModelDelegateParam};
 use crate::db::entity::hap_accessory::ModelDelegateParamVec;
 use crate::db::entity::hap_characteristic::HapCharInfoQueryResult;
@@ -73,7 +74,7 @@ pub fn to_accessory_model(ctx: AccessoryCtx, accessory: &AccessoryTemplate) -> a
     if let Some(s) = accessory.hap_delegate.clone() {
         delegates.push(s);
     }
-    if delegates.len() > 1 && delegates.get(0).as_ref().unwrap().chars.is_none() {
+    if delegates.len() > 1 && delegates.iter().any(|i| i.chars.is_none()) {
         return Err(anyhow!("hap_delegates chars is none"));
     }
 
@@ -96,9 +97,10 @@ pub fn to_accessory_model(ctx: AccessoryCtx, accessory: &AccessoryTemplate) -> a
                 chars,
                 model: i.model.clone(),
                 params: i.params.clone(),
-                timeout: None,
+                timeout: i.timeout,
             }
         }).collect::<Vec<ModelDelegateParam>>();
+    check_delegate_chars(&hap_model_delegates)?;
 
 
     Ok(HapAccessoryActiveModel {
@@ -131,21 +133,34 @@ pub struct DeviceModelCtx {
     pub(crate) name: String,
     pub(crate) id: String,
     pub(crate) version: String,
+    pub(crate) platform: SourcePlatform,
     pub did: String,
 }
 
+/// 替换参数中的 {source_id} 占位符,如 mqtt 主题 zigbee2mqtt/{source_id}
+fn fill_source_id(value: &JsonValue, source_id: &str) -> JsonValue {
+    match value {
+        JsonValue::String(s) => JsonValue::String(s.replace("{source_id}", source_id)),
+        JsonValue::Array(list) => JsonValue::Array(list.iter().map(|v| fill_source_id(v, source_id)).collect()),
+        JsonValue::Object(map) => JsonValue::Object(map.iter()
+            .map(|(k, v)| (k.clone(), fill_source_id(v, source_id)))
+            .collect()),
+        v => v.clone(),
+    }
+}
+
 pub fn to_device_model(ctx: DeviceModelCtx, device: &DeviceTemplate) -> anyhow::Result<IotDeviceActiveModel> {
     Ok(IotDeviceActiveModel {
         device_id: Set(ctx.device_id),
         tag: Set(Some(device.tag.clone())),
         integration: Set(device.integration.clone()),
-        params: Set(device.params.clone()),
+        params: Set(fill_source_id(&device.params, ctx.did.as_str())),
         gateway_id: Default::default(),
         name: Set(ctx.name),
         memo: Set(device.memo.clone()),
         disabled: Set(device.disabled.unwrap_or(false)),
         device_type: Set(device.device_type.clone().unwrap_or(DeviceType::Normal)),
-        source_platform: Set(SourcePlatform::Mijia.as_ref().to_string()),
+        source_platform: Set(ctx.platform.as_ref().to_string()),
         source_id: Set(Some(ctx.did.clone())),
         temp_id: Set(Some(ctx.id)),
         temp_version: Set(Some(ctx.version)),
@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::ops::Deref;
 use std::pin::Pin;
 use std::sync::Arc;
@@ -8,6 +9,7 @@ use log::{error, info};
 use tokio::sync::{broadcast, RwLock};
 use ble_monitor::parse_advertisement::{parse_advertisement, ServiceDataPacket};
 use hap::futures::Stream;
+use crate::service::metrics_service::BLE_ADVERTISEMENTS;
 
 #[derive(Clone)]
 pub struct BleManager {
@@ -49,7 +51,8 @@ pub struct BleManagerInner {
     pub status: RwLock<Status>,
     pub sender: broadcast::Sender<ServiceDataPacket>,
     pub adapter: RwLock<Option<Adapter>>,
-
+    /// 加密设备的 mac 与 bindkey
+    aes_keys: Arc<RwLock<HashMap<[u8; 6], String>>>,
 }
 
 impl BleManagerInner {
@@ -59,8 +62,19 @@ impl BleManagerInner {
             status: RwLock::new(Status::EmptyAdapter),
             sender: tx,
             adapter: RwLock::new(None),
+            aes_keys: Default::default(),
         }
     }
+
+    /// 设置设备的 bindkey, v4/v5 为32位,v2/v3 为24位16进制
+    pub async fn set_aes_key(&self, mac: [u8; 6], key: String) {
+        self.aes_keys.write().await.insert(mac, key);
+    }
+
+    pub async fn remove_aes_key(&self, mac: &[u8; 6]) {
+        self.aes_keys.write().await.remove(mac);
+    }
+
     pub fn recv(&self) -> broadcast::Receiver<ServiceDataPacket> {
         self.sender.subscribe()
     }
@@ -84,14 +98,18 @@ impl BleManagerInner {
         let mut events = adapter.events().await?;
         self.adapter.write().await.replace(adapter);
         let sender = self.sender.clone();
+        let aes_keys = self.aes_keys.clone();
         //接受处理事件
         tokio::spawn(async move {
             while let Some(event) = events.next().await {
                 match event {
                     CentralEvent::ServiceDataAdvertisement { id, service_data } => {
+                        let keys = aes_keys.read().await;
                         for (uuid, bytes) in &service_data {
-                            match parse_advertisement(uuid, bytes.as_slice()) {
+                            match parse_advertisement(uuid, bytes.as_slice(), &keys) {
                                 Ok(data) => {
+                                    let result = if data.is_some() { "parsed" } else { "empty" };
+                                    BLE_ADVERTISEMENTS.with_label_values(&[result]).inc();
                                     if let Some(data) = data {
                                         // 上报设备事件
                                         //尝试解包获取类型,
@@ -100,6 +118,7 @@ impl BleManagerInner {
                                     }
                                 }
                                 Err(e) => {
+                                    BLE_ADVERTISEMENTS.with_label_values(&["error"]).inc();
                                     //  info!("parse_advertisement error:{:?}", e);
                                 }
                             }
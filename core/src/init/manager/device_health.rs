@@ -0,0 +1,116 @@
+use std::time::{Duration, UNIX_EPOCH};
+
+use dashmap::DashMap;
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+use crate::init::DevicePointer;
+
+/// 连续通信失败达到该次数视为离线
+const MAX_FAILURES: u32 = 3;
+/// 健康检查间隔
+pub const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// 设备健康状态
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DeviceHealth {
+    pub online: bool,
+    /// 最后一次成功通信时间戳,毫秒
+    pub last_contact: Option<i64>,
+    /// 连续失败次数
+    pub failures: u32,
+    /// 离线或失败原因
+    pub reason: Option<String>,
+}
+
+impl DeviceHealth {
+    /// 在线状态,失败次数或原因变化
+    fn status_changed(&self, other: &DeviceHealth) -> bool {
+        self.online != other.online
+            || self.failures != other.failures
+            || self.reason != other.reason
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DeviceHealthEvent {
+    pub device_id: i64,
+    #[serde(flatten)]
+    pub health: DeviceHealth,
+}
+
+/// 设备健康跟踪
+/// 根据设备任务的运行状态和 RetryInfo 中的通信状态计算健康状态,变化时广播
+pub struct DeviceHealthTracker {
+    /// 设备任务已退出,等待重试, 设备id -> 退出原因
+    exited: DashMap<i64, String>,
+    health: DashMap<i64, DeviceHealth>,
+    sender: broadcast::Sender<DeviceHealthEvent>,
+}
+
+impl Default for DeviceHealthTracker {
+    fn default() -> Self {
+        let (sender, _) = broadcast::channel(64);
+        Self {
+            exited: Default::default(),
+            health: Default::default(),
+            sender,
+        }
+    }
+}
+
+impl DeviceHealthTracker {
+    pub fn subscribe(&self) -> broadcast::Receiver<DeviceHealthEvent> {
+        self.sender.subscribe()
+    }
+
+    pub fn get(&self, device_id: i64) -> Option<DeviceHealth> {
+        self.health.get(&device_id).map(|h| h.clone())
+    }
+
+    pub fn list(&self) -> Vec<DeviceHealthEvent> {
+        self.health.iter()
+            .map(|i| DeviceHealthEvent { device_id: *i.key(), health: i.value().clone() })
+            .collect()
+    }
+
+    /// 设备任务开始运行
+    pub fn on_run(&self, device_id: i64) {
+        self.exited.remove(&device_id);
+    }
+
+    /// 设备任务退出
+    pub fn on_exit(&self, device_id: i64, reason: String) {
+        self.exited.insert(device_id, reason);
+    }
+
+    /// 设备被移除
+    pub fn remove(&self, device_id: i64) {
+        self.exited.remove(&device_id);
+        self.health.remove(&device_id);
+    }
+
+    /// 重新计算设备健康状态,变化时广播
+    pub async fn refresh(&self, device_id: i64, dev: &DevicePointer) -> DeviceHealth {
+        let retry_info = dev.retry_info();
+        let contact = retry_info.contact_info();
+        let retries = retry_info.count().await;
+        let exited = self.exited.get(&device_id).map(|r| r.clone());
+        let health = DeviceHealth {
+            online: exited.is_none() && contact.failures < MAX_FAILURES,
+            last_contact: contact.last_contact
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map(|d| d.as_millis() as i64),
+            failures: contact.failures.max(retries),
+            reason: exited.or(contact.reason),
+        };
+        let changed = self.health.get(&device_id)
+            .map(|old| old.status_changed(&health))
+            .unwrap_or(true);
+        self.health.insert(device_id, health.clone());
+        if changed {
+            let _ = self.sender.send(DeviceHealthEvent { device_id, health: health.clone() });
+        }
+        health
+    }
+}
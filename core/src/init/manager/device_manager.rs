@@ -2,6 +2,7 @@ mod init;
 mod mijia;
 mod native_ble;
 mod hl_virtual;
+mod mqtt;
 
 use std::ops::Deref;
 use std::sync::Arc;
@@ -10,7 +11,9 @@ use dashmap::DashMap;
 use dashmap::mapref::entry::Entry;
 use log::{error, info};
 use sea_orm::DatabaseConnection;
-use tokio::sync::oneshot;
+use tokio::sync::{broadcast, oneshot};
+
+use hl_integration::event::events::DeviceEventPointer;
 
 use miot_proto::device::common::emitter::MijiaEvent;
 use miot_proto::device::miot_spec_device::MiotSpecDevice;
@@ -18,6 +21,8 @@ use crate::config::context::get_app_context;
 use crate::init::DevicePointer;
 use crate::init::manager::ble_manager::BleManager;
 use crate::init::manager::mi_account_manager::MiAccountManager;
+use crate::init::manager::device_health::{CHECK_INTERVAL, DeviceHealthTracker};
+use crate::service::metrics_service::DEVICE_RUNS;
 
 
 pub struct DeviceTask {
@@ -34,6 +39,10 @@ pub struct IotDeviceManagerInner {
     conn: DatabaseConnection,
     mi_account_manager: MiAccountManager,
     ble_manager: BleManager,
+    /// 设备健康状态
+    pub health: Arc<DeviceHealthTracker>,
+    /// 所有设备的事件, (设备id,事件)
+    events: broadcast::Sender<(i64, DeviceEventPointer)>,
 }
 
 
@@ -49,12 +58,39 @@ impl IotDeviceManagerInner {
         };
         self.device_map.insert(device_id, device);
         //todo  self.did_map.insert(dev_c.get_info().did.clone(), device_id);
+        let health = self.health.clone();
+        let events = self.events.clone();
         //执行任务
         tokio::spawn(async move {
+            // 收到设备事件即视为通信成功, 弱引用避免循环引用
+            let weak = Arc::downgrade(&dev_c);
+            let health_c = health.clone();
+            dev_c.add_listener(Box::new(move |event| {
+                let weak = weak.clone();
+                let health = health_c.clone();
+                let _ = events.send((device_id, event));
+                Box::pin(async move {
+                    if let Some(dev) = weak.upgrade() {
+                        dev.retry_info().contact_ok();
+                        // 离线后恢复通信时才重置重试间隔
+                        if health.get(device_id).is_some_and(|h| !h.online) {
+                            dev.retry_info().reset().await;
+                            health.refresh(device_id, &dev).await;
+                        }
+                    }
+                })
+            })).await;
             let task = async move {
                 loop {
-
+                    DEVICE_RUNS.with_label_values(&[device_id.to_string().as_str(), dev_c.device_type()]).inc();
+                    health.on_run(device_id);
+                    health.refresh(device_id, &dev_c).await;
                     let res = dev_c.run().await;
+                    health.on_exit(device_id, match res.as_ref() {
+                        Ok(_) => "设备连接断开".to_string(),
+                        Err(e) => format!("{:?}", e),
+                    });
+                    health.refresh(device_id, &dev_c).await;
                     //标记重试次数+1
                     let incr = dev_c.retry_info().incr().await;
                     let interval = dev_c.retry_info().get().await;
@@ -74,6 +110,16 @@ impl IotDeviceManagerInner {
             }
         });
     }
+    /// 订阅所有设备的事件
+    pub fn subscribe_events(&self) -> broadcast::Receiver<(i64, DeviceEventPointer)> {
+        self.events.subscribe()
+    }
+    /// 运行中的设备
+    pub fn device_list(&self) -> Vec<(i64, DevicePointer)> {
+        self.device_map.iter()
+            .map(|i| (*i.key(), i.value().dev.clone()))
+            .collect()
+    }
     pub fn get_device(&self, device_id: i64) -> Option<DevicePointer> {
         self.device_map.get(&device_id).map(|i| i.value().dev.clone())
     }
@@ -85,6 +131,7 @@ impl IotDeviceManagerInner {
         if let Some((id, task)) = self.device_map.remove(&device_id) {
             let _ = task.close_sender.send(true);
         }
+        self.health.remove(device_id);
         // 等待设备停止成功
         Ok(())
     }
@@ -115,6 +162,7 @@ impl IotDeviceManager {
                mi_account_manager: MiAccountManager,
                ble_manager: BleManager,
     ) -> Self {
+        let (events, _) = broadcast::channel(256);
         Self {
             inner: Arc::new(
                 IotDeviceManagerInner {
@@ -124,11 +172,26 @@ impl IotDeviceManager {
                     conn,
                     mi_account_manager,
                     ble_manager,
+                    health: Default::default(),
+                    events,
                 }
             )
         }
     }
 
+    /// 定时检查设备健康状态
+    pub fn start_health_check(&self) {
+        let manager = self.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(CHECK_INTERVAL).await;
+                for (id, dev) in manager.device_list() {
+                    manager.health.refresh(id, &dev).await;
+                }
+            }
+        });
+    }
+
     pub fn is_running(&self, id: i64) -> bool {
         self.device_map.contains_key(&id)
     }
@@ -137,6 +200,7 @@ impl IotDeviceManager {
         if let Some((id, task)) = dev {
             let _ = task.close_sender.send(true);
         }
+        self.health.remove(id);
         Ok(())
     }
 
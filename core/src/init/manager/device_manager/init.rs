@@ -76,6 +76,9 @@ impl IotDeviceManagerInner {
             "hl-virtual" => {
                 self.init_hl_virtual(dev).await?
             }
+            "mqtt" => {
+                self.init_mqtt_device(dev).await?
+            }
             _ => {
                 return Err(anyhow!("暂不支持:{}类型设备接入",dev.source_platform.as_str()));
             }
@@ -1,5 +1,6 @@
 use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Duration;
 use anyhow::anyhow;
 use log::error;
 use sea_orm::DatabaseConnection;
@@ -8,6 +9,7 @@ use miot_proto::device::miot_spec_device::{AsMiotDevice, DeviceInfo, MiotDeviceT
 use crate::db::entity::prelude::{IotDeviceModel, MiotDeviceEntity};
 use sea_orm::*;
 use miot_proto::device::ble::ble_device::BleDevice;
+use miot_proto::device::common::shadow::ShadowParam;
 use miot_proto::device::cloud_device::{MiCloudDevice, MiCloudDeviceInner};
 use miot_proto::device::gateway::gateway::OpenMiioGatewayDevice;
 use miot_proto::device::mesh_device::MeshDevice;
@@ -31,7 +33,9 @@ impl IotDeviceManagerInner {
                 return Ok(Arc::new(ble_dev));
             }
             MiotDeviceType::Mesh => {
-                let mesh_dev = MeshDevice::new_mesh_device(dev_info, gw);
+                // 属性由网关上报,影子默认30秒有效
+                let max_age = ShadowParam::max_age(&dev.params, Duration::from_secs(30));
+                let mesh_dev = MeshDevice::new_mesh_device(dev_info, gw, max_age);
                 return Ok(Arc::new(mesh_dev));
             }
             _ => {
@@ -0,0 +1,18 @@
+use std::sync::Arc;
+
+use tap::TapFallible;
+use log::error;
+
+use mqtt_integration::device::{MqttDevice, MqttDeviceParam};
+use crate::db::entity::prelude::IotDeviceModel;
+use crate::init::DevicePointer;
+use crate::init::manager::device_manager::IotDeviceManagerInner;
+
+impl IotDeviceManagerInner {
+    /// mqtt 设备, 参数为 MqttDeviceParam
+    pub async fn init_mqtt_device(&self, dev: IotDeviceModel) -> anyhow::Result<DevicePointer> {
+        let param: MqttDeviceParam = serde_json::from_value(dev.params)
+            .tap_err(|e| error!("mqtt 设备参数错误:{}", e))?;
+        Ok(Arc::new(MqttDevice::new(dev.device_id.to_string(), param)))
+    }
+}
@@ -17,6 +17,7 @@ use miot_proto::proto::miio_proto::MiotSpecProtocolPointer;
 use miot_proto::proto::protocol::ExitError;
 use miot_proto::proto::transport::cloud_miio_proto::CloudMiioProto;
 use crate::config::context::get_data_dir;
+use crate::config::secret::{decrypt_secret, master_cipher};
 use crate::db::entity::mi_account::MiAccountStatus;
 use crate::db::entity::prelude::{MiAccountActiveModel, MiAccountEntity};
 
@@ -38,7 +39,7 @@ impl MiAccountManagerInner {
 
     async fn new_cloud(username: String, password: String) -> anyhow::Result<MiCloud> {
         let path = format!("{}/mi_cloud", get_data_dir());
-        let cloud = MiCloud::new(path.as_str(), username, Some(password)).await?;
+        let cloud = MiCloud::new(path.as_str(), username, Some(password), master_cipher()?).await?;
         Ok(cloud)
     }
     /// 获取cloud
@@ -57,7 +58,8 @@ impl MiAccountManagerInner {
                     .one(&self.conn)
                     .await?
                     .ok_or(anyhow!("账号:{}不存在", account))?;
-                let cloud = Self::new_cloud(model.account, model.password).await?;
+                let password = decrypt_secret(model.password.as_str())?;
+                let cloud = Self::new_cloud(model.account, password).await?;
                 let cloud = Arc::new(RwLock::new(cloud));
                 write.insert(account.to_string(), cloud.clone());
                 cloud
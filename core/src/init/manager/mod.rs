@@ -2,3 +2,6 @@ pub mod device_manager;
 pub mod mi_account_manager;
 pub mod template_manager;
 pub mod ble_manager;
+pub mod device_health;
+pub mod rule_manager;
+pub mod mqtt_export_manager;
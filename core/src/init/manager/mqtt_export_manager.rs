@@ -0,0 +1,94 @@
+use std::ops::Deref;
+use std::sync::Arc;
+
+use log::error;
+use sea_orm::{DatabaseConnection, EntityTrait};
+use tokio::sync::RwLock;
+
+use target_hap::hap_manager::HapManage;
+use target_mqtt::config::MqttExportConfig;
+use target_mqtt::exporter::MqttExporter;
+
+use crate::db::entity::prelude::HapAccessoryEntity;
+use crate::init::manager::device_manager::IotDeviceManager;
+use crate::init::mqtt_export_init::{init_mqtt_export, to_export_accessory};
+
+/// mqtt 导出管理器
+/// 配件添加,修改,删除后同步到导出器,未开启导出时忽略
+#[derive(Clone)]
+pub struct MqttExportManager {
+    inner: Arc<MqttExportManagerInner>,
+}
+
+impl Deref for MqttExportManager {
+    type Target = MqttExportManagerInner;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+pub struct MqttExportManagerInner {
+    conn: DatabaseConnection,
+    hap_manager: HapManage,
+    device_manager: IotDeviceManager,
+    exporter: RwLock<Option<MqttExporter>>,
+}
+
+impl MqttExportManager {
+    pub fn new(conn: DatabaseConnection, hap_manager: HapManage, device_manager: IotDeviceManager) -> Self {
+        Self {
+            inner: Arc::new(MqttExportManagerInner {
+                conn,
+                hap_manager,
+                device_manager,
+                exporter: RwLock::new(None),
+            }),
+        }
+    }
+}
+
+impl MqttExportManagerInner {
+    /// 启动导出,导出已运行的配件
+    pub async fn init(&self, config: MqttExportConfig) -> anyhow::Result<()> {
+        let exporter = init_mqtt_export(&self.conn, config, self.hap_manager.clone(), self.device_manager.clone()).await?;
+        self.exporter.write().await.replace(exporter);
+        Ok(())
+    }
+
+    /// 按配件当前状态添加,替换或移除导出
+    pub async fn sync_accessory(&self, aid: i64) {
+        let exporter = match self.exporter.read().await.clone() {
+            None => return,
+            Some(e) => e,
+        };
+        if let Err(e) = self.do_sync_accessory(&exporter, aid).await {
+            error!("同步导出配件:{aid}失败:{e:?}");
+        }
+    }
+
+    async fn do_sync_accessory(&self, exporter: &MqttExporter, aid: i64) -> anyhow::Result<()> {
+        let accessory = HapAccessoryEntity::find_by_id(aid)
+            .one(&self.conn)
+            .await?
+            .filter(|a| !a.disabled);
+        let device = accessory.as_ref()
+            .and_then(|a| self.device_manager.get_device(a.device_id));
+        match (accessory, device) {
+            // 只导出已初始化的配件
+            (Some(accessory), Some(device)) if self.hap_manager.accessory_map.contains_key(&(aid as u64)) => {
+                let export = to_export_accessory(&self.conn, &self.hap_manager, &device, accessory).await?;
+                exporter.add_accessory(export, device).await;
+            }
+            _ => exporter.remove_accessory(aid as u64).await,
+        }
+        Ok(())
+    }
+
+    /// 配件删除后移除导出
+    pub async fn remove_accessory(&self, aid: i64) {
+        if let Some(exporter) = self.exporter.read().await.clone() {
+            exporter.remove_accessory(aid as u64).await;
+        }
+    }
+}
@@ -0,0 +1,631 @@
+use std::ops::Deref;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use chrono::Local;
+use dashmap::DashMap;
+use log::{error, info, warn};
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter};
+use serde::Serialize;
+use serde_json::{json, Value};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::task::AbortHandle;
+
+use ble_monitor::parse_advertisement::ServiceDataPacket;
+use hl_integration::event::events::DeviceEventPointer;
+use hl_integration::HlSourceDevice;
+use miot_proto::device::common::emitter::MijiaEvent;
+use miot_proto::device::miot_spec_device::{AsMiotDevice, MiotDeviceArc};
+use miot_proto::proto::miio_proto::MiotSpecId;
+use mqtt_integration::device::{MqttDevice, MqttEvent};
+use xiaomi_ble_packet::ble_value_type::{BleValue, MiBleValueType};
+
+use crate::config::cfgs::Location;
+use crate::db::entity::prelude::{RuleColumn, RuleEntity, RuleModel};
+use crate::init::DevicePointer;
+use crate::init::manager::ble_manager::BleManager;
+use crate::init::manager::device_manager::IotDeviceManager;
+use crate::rule::{CompareOp, PropertyRef, RuleAction, RuleCondition, RuleTrigger};
+use crate::rule::schedule::{check_weekdays, in_time_range, next_fire, parse_time};
+
+/// 定时任务最长等待时间,之后重新计算,避免系统时间调整后错过
+const MAX_SCHEDULE_SLEEP: Duration = Duration::from_secs(3600);
+
+/// 事件中的属性变化
+struct PropertyChange {
+    spec: Option<(i32, i32)>,
+    name: Option<String>,
+    value: Value,
+}
+
+impl PropertyChange {
+    fn matches(&self, property: &PropertyRef) -> bool {
+        match (self.spec, self.name.as_deref()) {
+            (Some((siid, piid)), _) => property.matches_spec(siid, piid),
+            (_, Some(name)) => property.matches_name(name),
+            _ => false,
+        }
+    }
+}
+
+fn property_changes(event: &DeviceEventPointer) -> Vec<PropertyChange> {
+    if let Some(event) = event.downcast_ref::<MijiaEvent>() {
+        let list = match event {
+            MijiaEvent::UpdateProperty(dto) => vec![dto.clone()],
+            MijiaEvent::PropertiesChanged(list) => list.clone(),
+            _ => vec![],
+        };
+        return list.into_iter()
+            .filter_map(|dto| dto.value.map(|value| PropertyChange {
+                spec: Some((dto.siid, dto.piid)),
+                name: None,
+                value,
+            }))
+            .collect();
+    }
+    if let Some(MqttEvent::PropertiesChanged(list)) = event.downcast_ref::<MqttEvent>() {
+        return list.iter()
+            .map(|p| PropertyChange { spec: None, name: Some(p.name.clone()), value: p.value.clone() })
+            .collect();
+    }
+    vec![]
+}
+
+fn format_mac(mac: &[u8; 6]) -> String {
+    mac.iter().map(|b| format!("{:02X}", b)).collect::<Vec<_>>().join(":")
+}
+
+/// 蓝牙广播中的值,温湿度为原始值(0.1单位)
+fn ble_value(packet: &ServiceDataPacket) -> Option<Value> {
+    let value = MiBleValueType::try_from(packet.etype).ok()?
+        .unpack(packet.edata.as_slice())
+        .ok()?;
+    Some(match value {
+        BleValue::U8(v) => json!(v),
+        BleValue::I16(v) => json!(v),
+    })
+}
+
+/// 属性触发器是否满足,事件中没有该属性时返回 None
+fn property_trigger_match(trigger: &RuleTrigger, device_id: i64, changes: &[PropertyChange]) -> Option<bool> {
+    match trigger {
+        RuleTrigger::Property { property, op, value } if property.device_id == device_id => changes.iter()
+            .filter(|c| c.matches(property))
+            .last()
+            .map(|c| CompareOp::check(*op, value.as_ref(), &c.value)),
+        _ => None,
+    }
+}
+
+/// 蓝牙触发器是否满足,广播不是该触发器的设备和类型时返回 None
+fn ble_trigger_match(trigger: &RuleTrigger, mac: &str, etype: u16, current: &Value) -> Option<bool> {
+    match trigger {
+        RuleTrigger::Ble { mac: m, etype: t, op, value } if m.eq_ignore_ascii_case(mac) && *t == etype => {
+            Some(CompareOp::check(*op, value.as_ref(), current))
+        }
+        _ => None,
+    }
+}
+
+/// 属性条件是否满足,没有当前值时不满足
+fn condition_passed(op: CompareOp, expect: &Value, current: Option<&Value>) -> bool {
+    current.map(|c| op.compare(c, expect)).unwrap_or(false)
+}
+
+/// 触发器是否设置了比较值,未设置时每次变化都触发
+fn has_compare(trigger: &RuleTrigger) -> bool {
+    match trigger {
+        RuleTrigger::Property { value, .. } | RuleTrigger::Ble { value, .. } => value.is_some(),
+        _ => false,
+    }
+}
+
+/// 触发器上一次的计算结果, (规则id,触发器序号) -> 是否满足
+/// 比较结果从不满足变为满足时才触发,避免持续满足时重复执行
+#[derive(Default)]
+struct TriggerStates {
+    states: DashMap<(i64, usize), bool>,
+}
+
+impl TriggerStates {
+    /// 记录本次结果,返回是否需要触发
+    fn update(&self, rule_id: i64, index: usize, trigger: &RuleTrigger, matched: bool) -> bool {
+        if !has_compare(trigger) {
+            return matched;
+        }
+        let last = self.states.insert((rule_id, index), matched).unwrap_or(false);
+        matched && !last
+    }
+
+    fn remove_rule(&self, rule_id: i64) {
+        self.states.retain(|(id, _), _| *id != rule_id);
+    }
+}
+
+fn property_spec(property: &PropertyRef) -> anyhow::Result<MiotSpecId> {
+    match (property.siid, property.piid) {
+        (Some(siid), Some(piid)) => Ok(MiotSpecId::new(siid, piid)),
+        _ => Err(anyhow!("米家设备属性需要设置 siid,piid")),
+    }
+}
+
+fn property_name(property: &PropertyRef) -> anyhow::Result<&str> {
+    property.name.as_deref().ok_or(anyhow!("mqtt 设备属性需要设置 name"))
+}
+
+/// 条件测试结果
+#[derive(Debug, Clone, Serialize)]
+pub struct ConditionResult {
+    pub condition: RuleCondition,
+    pub passed: bool,
+    /// 属性当前值
+    pub current: Option<Value>,
+    pub error: Option<String>,
+}
+
+/// 动作测试结果,不会真正执行
+#[derive(Debug, Clone, Serialize)]
+pub struct ActionPlan {
+    pub action: RuleAction,
+    /// 设备不存在或不支持时的错误
+    pub error: Option<String>,
+}
+
+/// 规则测试结果
+#[derive(Debug, Clone, Serialize)]
+pub struct RuleDryRunResult {
+    /// 条件是否全部满足
+    pub passed: bool,
+    pub conditions: Vec<ConditionResult>,
+    pub actions: Vec<ActionPlan>,
+    /// 定时触发器的下一次触发时间戳,毫秒
+    pub next_fire: Option<i64>,
+}
+
+/// 自动化规则管理器
+/// 监听设备事件,蓝牙广播和定时,条件满足后执行动作
+pub struct RuleManagerInner {
+    conn: DatabaseConnection,
+    device_manager: IotDeviceManager,
+    ble_manager: BleManager,
+    location: Option<Location>,
+    /// 启用的规则
+    rules: DashMap<i64, Arc<RuleModel>>,
+    /// 规则的定时任务
+    schedules: DashMap<i64, AbortHandle>,
+    trigger_states: TriggerStates,
+}
+
+impl RuleManagerInner {
+    fn running_device(&self, device_id: i64) -> anyhow::Result<DevicePointer> {
+        self.device_manager.get_device(device_id)
+            .ok_or(anyhow!("设备:{}未运行", device_id))
+    }
+
+    /// 属性当前值,米家设备优先使用影子中的值
+    pub async fn read_value(&self, property: &PropertyRef) -> anyhow::Result<Option<Value>> {
+        let dev = self.running_device(property.device_id)?;
+        let source: &dyn HlSourceDevice = dev.as_ref();
+        if let Some(mqtt) = source.downcast_ref::<MqttDevice>() {
+            return Ok(mqtt.get_property(property_name(property)?).await);
+        }
+        let id = property_spec(property)?;
+        let dev = MiotDeviceArc(dev);
+        if let Some(v) = dev.as_miot_device()?.get_base().shadow.get(&id).await {
+            return Ok(Some(v.value));
+        }
+        dev.read_property(id.siid, id.piid).await
+    }
+
+    /// 计算条件,返回是否满足和属性当前值
+    pub async fn check_condition(&self, condition: &RuleCondition) -> anyhow::Result<(bool, Option<Value>)> {
+        match condition {
+            RuleCondition::Property { property, op, value } => {
+                let current = self.read_value(property).await?;
+                let passed = condition_passed(*op, value, current.as_ref());
+                Ok((passed, current))
+            }
+            RuleCondition::TimeRange { from, to, weekdays } => {
+                Ok((in_time_range(&Local::now(), from, to, weekdays)?, None))
+            }
+        }
+    }
+
+    async fn execute(&self, action: &RuleAction) -> anyhow::Result<()> {
+        match action {
+            RuleAction::SetProperty { property, value } => {
+                let dev = self.running_device(property.device_id)?;
+                let source: &dyn HlSourceDevice = dev.as_ref();
+                if let Some(mqtt) = source.downcast_ref::<MqttDevice>() {
+                    let name = property_name(property)?.to_string();
+                    return mqtt.set_properties(vec![(name, value.clone())]).await;
+                }
+                let id = property_spec(property)?;
+                MiotDeviceArc(dev).set_property(id, value.clone()).await
+            }
+            RuleAction::CallAction { device_id, siid, aiid, ins } => {
+                let dev = self.running_device(*device_id)?;
+                MiotDeviceArc(dev).call_action(*siid, *aiid, ins.clone()).await?;
+                Ok(())
+            }
+            RuleAction::Delay { ms } => {
+                tokio::time::sleep(Duration::from_millis(*ms)).await;
+                Ok(())
+            }
+        }
+    }
+
+    /// 检查动作的设备是否可用
+    fn check_action(&self, action: &RuleAction) -> anyhow::Result<()> {
+        match action {
+            RuleAction::SetProperty { property, .. } => {
+                let dev = self.running_device(property.device_id)?;
+                let source: &dyn HlSourceDevice = dev.as_ref();
+                if source.downcast_ref::<MqttDevice>().is_some() {
+                    property_name(property)?;
+                } else {
+                    property_spec(property)?;
+                    MiotDeviceArc(dev).as_miot_device()?;
+                }
+            }
+            RuleAction::CallAction { device_id, .. } => {
+                let dev = self.running_device(*device_id)?;
+                MiotDeviceArc(dev).as_miot_device()?;
+            }
+            RuleAction::Delay { .. } => {}
+        }
+        Ok(())
+    }
+
+    /// 检查属性配置,设备运行中时按设备类型检查
+    fn check_property(&self, property: &PropertyRef) -> anyhow::Result<()> {
+        property.check()?;
+        if let Ok(dev) = self.running_device(property.device_id) {
+            let source: &dyn HlSourceDevice = dev.as_ref();
+            if source.downcast_ref::<MqttDevice>().is_some() {
+                property_name(property)?;
+            } else {
+                property_spec(property)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// 检查规则配置
+    pub fn validate(&self, triggers: &[RuleTrigger], conditions: &[RuleCondition], actions: &[RuleAction]) -> anyhow::Result<()> {
+        if triggers.is_empty() {
+            return Err(anyhow!("至少需要一个触发器"));
+        }
+        if actions.is_empty() {
+            return Err(anyhow!("至少需要一个动作"));
+        }
+        for trigger in triggers {
+            match trigger {
+                RuleTrigger::Property { property, .. } => {
+                    self.check_property(property)?;
+                }
+                RuleTrigger::Time { at, weekdays } => {
+                    parse_time(at)?;
+                    check_weekdays(weekdays)?;
+                }
+                RuleTrigger::Sun { weekdays, .. } => {
+                    if self.location.is_none() {
+                        return Err(anyhow!("日出日落触发需要在配置文件中设置 location"));
+                    }
+                    check_weekdays(weekdays)?;
+                }
+                RuleTrigger::Ble { .. } => {}
+            }
+        }
+        for condition in conditions {
+            match condition {
+                RuleCondition::Property { property, .. } => {
+                    self.check_property(property)?;
+                }
+                RuleCondition::TimeRange { from, to, weekdays } => {
+                    parse_time(from)?;
+                    parse_time(to)?;
+                    check_weekdays(weekdays)?;
+                }
+            }
+        }
+        for action in actions {
+            if let RuleAction::SetProperty { property, .. } = action {
+                self.check_property(property)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// 测试规则,计算条件和下一次触发时间,不执行动作
+    pub async fn dry_run(&self, triggers: &[RuleTrigger], conditions: &[RuleCondition], actions: &[RuleAction]) -> anyhow::Result<RuleDryRunResult> {
+        self.validate(triggers, conditions, actions)?;
+        let mut results = vec![];
+        for condition in conditions {
+            let result = match self.check_condition(condition).await {
+                Ok((passed, current)) => ConditionResult { condition: condition.clone(), passed, current, error: None },
+                Err(e) => ConditionResult { condition: condition.clone(), passed: false, current: None, error: Some(e.to_string()) },
+            };
+            results.push(result);
+        }
+        let now = Local::now();
+        let next = triggers.iter()
+            .filter_map(|t| next_fire(t, &now, self.location.as_ref()).ok().flatten())
+            .min();
+        Ok(RuleDryRunResult {
+            passed: results.iter().all(|r| r.passed),
+            conditions: results,
+            actions: actions.iter()
+                .map(|a| ActionPlan { action: a.clone(), error: self.check_action(a).err().map(|e| e.to_string()) })
+                .collect(),
+            next_fire: next.map(|t| t.timestamp_millis()),
+        })
+    }
+}
+
+#[derive(Clone)]
+pub struct RuleManager {
+    inner: Arc<RuleManagerInner>,
+}
+
+impl RuleManager {
+    pub fn new(conn: DatabaseConnection,
+               device_manager: IotDeviceManager,
+               ble_manager: BleManager,
+               location: Option<Location>,
+    ) -> Self {
+        Self {
+            inner: Arc::new(RuleManagerInner {
+                conn,
+                device_manager,
+                ble_manager,
+                location,
+                rules: Default::default(),
+                schedules: Default::default(),
+                trigger_states: Default::default(),
+            })
+        }
+    }
+
+    /// 加载启用的规则,开始监听设备事件和蓝牙广播
+    pub async fn init(&self) -> anyhow::Result<()> {
+        let rules = RuleEntity::find()
+            .filter(RuleColumn::Disabled.eq(false))
+            .all(&self.conn)
+            .await?;
+        info!("加载规则:{}条", rules.len());
+        for rule in rules {
+            self.load(rule);
+        }
+        self.listen_device_events();
+        self.listen_ble();
+        Ok(())
+    }
+
+    /// 重新加载规则,添加,修改,启用禁用后调用
+    pub async fn reload_rule(&self, rule_id: i64) -> anyhow::Result<()> {
+        self.unload(rule_id);
+        let rule = RuleEntity::find_by_id(rule_id)
+            .one(&self.conn)
+            .await?;
+        if let Some(rule) = rule.filter(|r| !r.disabled) {
+            self.load(rule);
+        }
+        Ok(())
+    }
+
+    /// 卸载规则,删除后调用
+    pub fn unload(&self, rule_id: i64) {
+        self.rules.remove(&rule_id);
+        self.trigger_states.remove_rule(rule_id);
+        if let Some((_, handle)) = self.schedules.remove(&rule_id) {
+            handle.abort();
+        }
+    }
+
+    fn load(&self, rule: RuleModel) {
+        let rule = Arc::new(rule);
+        if rule.triggers.0.iter().any(|t| t.is_schedule()) {
+            let handle = self.spawn_schedule(rule.clone());
+            self.schedules.insert(rule.rule_id, handle);
+        }
+        self.rules.insert(rule.rule_id, rule);
+    }
+
+    fn spawn_schedule(&self, rule: Arc<RuleModel>) -> AbortHandle {
+        let manager = self.clone();
+        tokio::spawn(async move {
+            loop {
+                let now = Local::now();
+                let next = rule.triggers.0.iter()
+                    .filter_map(|t| next_fire(t, &now, manager.location.as_ref())
+                        .map_err(|e| error!("规则:{}计算触发时间失败:{}", rule.name, e))
+                        .ok()
+                        .flatten())
+                    .min();
+                let next = match next {
+                    None => {
+                        warn!("规则:{}没有下一次触发时间", rule.name);
+                        break;
+                    }
+                    Some(t) => t,
+                };
+                let wait = (next - now).to_std().unwrap_or_default();
+                tokio::time::sleep(wait.min(MAX_SCHEDULE_SLEEP)).await;
+                if Local::now() >= next {
+                    manager.fire(rule.clone(), "定时");
+                }
+            }
+        }).abort_handle()
+    }
+
+    fn listen_device_events(&self) {
+        let manager = self.clone();
+        let mut rx = self.device_manager.subscribe_events();
+        tokio::spawn(async move {
+            loop {
+                match rx.recv().await {
+                    Ok((device_id, event)) => manager.on_device_event(device_id, &event),
+                    Err(RecvError::Lagged(n)) => warn!("规则引擎丢失设备事件:{}", n),
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+    }
+
+    fn listen_ble(&self) {
+        let manager = self.clone();
+        let mut rx = self.ble_manager.recv();
+        tokio::spawn(async move {
+            loop {
+                match rx.recv().await {
+                    Ok(packet) => manager.on_ble(&packet),
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+    }
+
+    fn on_device_event(&self, device_id: i64, event: &DeviceEventPointer) {
+        let changes = property_changes(event);
+        if changes.is_empty() {
+            return;
+        }
+        let rules = self.matched_rules(|t| property_trigger_match(t, device_id, &changes));
+        for rule in rules {
+            self.fire(rule, "属性变化");
+        }
+    }
+
+    fn on_ble(&self, packet: &ServiceDataPacket) {
+        let value = match ble_value(packet) {
+            None => return,
+            Some(v) => v,
+        };
+        let mac = format_mac(&packet.mac);
+        let rules = self.matched_rules(|t| ble_trigger_match(t, mac.as_str(), packet.etype, &value));
+        for rule in rules {
+            self.fire(rule, "蓝牙广播");
+        }
+    }
+
+    /// 计算所有触发器并记录结果,返回需要触发的规则
+    fn matched_rules(&self, check: impl Fn(&RuleTrigger) -> Option<bool>) -> Vec<Arc<RuleModel>> {
+        let mut rules = vec![];
+        for rule in self.rules.iter() {
+            let mut fired = false;
+            for (index, trigger) in rule.triggers.0.iter().enumerate() {
+                if let Some(matched) = check(trigger) {
+                    // 所有触发器都要记录结果,不能提前返回
+                    fired |= self.trigger_states.update(rule.rule_id, index, trigger, matched);
+                }
+            }
+            if fired {
+                rules.push(rule.value().clone());
+            }
+        }
+        rules
+    }
+
+    /// 触发规则,条件全部满足后按顺序执行动作
+    pub fn fire(&self, rule: Arc<RuleModel>, reason: &str) {
+        let manager = self.clone();
+        let reason = reason.to_string();
+        tokio::spawn(async move {
+            for condition in rule.conditions.0.iter() {
+                match manager.check_condition(condition).await {
+                    Ok((true, _)) => {}
+                    Ok((false, _)) => return,
+                    Err(e) => {
+                        warn!("规则:{}条件计算失败:{}", rule.name, e);
+                        return;
+                    }
+                }
+            }
+            info!("执行规则:{},触发:{}", rule.name, reason);
+            for action in rule.actions.0.iter() {
+                if let Err(e) = manager.execute(action).await {
+                    error!("规则:{}执行动作失败:{:?},{}", rule.name, action, e);
+                    return;
+                }
+            }
+        });
+    }
+}
+
+impl Deref for RuleManager {
+    type Target = RuleManagerInner;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use crate::init::manager::rule_manager::{ble_trigger_match, condition_passed, property_trigger_match, PropertyChange, TriggerStates};
+    use crate::rule::{CompareOp, PropertyRef, RuleTrigger};
+
+    fn temperature_trigger() -> RuleTrigger {
+        RuleTrigger::Property {
+            property: PropertyRef { device_id: 1, siid: Some(3), piid: Some(1), name: None },
+            op: Some(CompareOp::Gt),
+            value: Some(json!(30)),
+        }
+    }
+
+    fn change(piid: i32, value: i32) -> PropertyChange {
+        PropertyChange { spec: Some((3, piid)), name: None, value: json!(value) }
+    }
+
+    #[test]
+    fn test_trigger_match() {
+        let trigger = temperature_trigger();
+        assert_eq!(property_trigger_match(&trigger, 1, &[change(1, 31)]), Some(true));
+        assert_eq!(property_trigger_match(&trigger, 1, &[change(1, 29)]), Some(false));
+        // 同一事件中多次变化以最后一次为准
+        assert_eq!(property_trigger_match(&trigger, 1, &[change(1, 31), change(1, 29)]), Some(false));
+        assert_eq!(property_trigger_match(&trigger, 1, &[change(2, 31)]), None);
+        assert_eq!(property_trigger_match(&trigger, 2, &[change(1, 31)]), None);
+
+        let trigger = RuleTrigger::Ble { mac: "A4:C1:38:00:00:01".to_string(), etype: 0x1006, op: Some(CompareOp::Ge), value: Some(json!(700)) };
+        assert_eq!(ble_trigger_match(&trigger, "a4:c1:38:00:00:01", 0x1006, &json!(700)), Some(true));
+        assert_eq!(ble_trigger_match(&trigger, "A4:C1:38:00:00:01", 0x1004, &json!(700)), None);
+    }
+
+    #[test]
+    fn test_condition_passed() {
+        assert!(condition_passed(CompareOp::Eq, &json!(true), Some(&json!(true))));
+        assert!(condition_passed(CompareOp::Lt, &json!(50), Some(&json!(20))));
+        assert!(!condition_passed(CompareOp::Lt, &json!(50), Some(&json!(80))));
+        assert!(!condition_passed(CompareOp::Eq, &json!(true), None));
+    }
+
+    #[test]
+    fn test_trigger_transition() {
+        let states = TriggerStates::default();
+        let trigger = temperature_trigger();
+        assert!(states.update(1, 0, &trigger, true));
+        // 持续满足时不重复触发
+        assert!(!states.update(1, 0, &trigger, true));
+        assert!(!states.update(1, 0, &trigger, false));
+        assert!(states.update(1, 0, &trigger, true));
+        // 不同触发器分别记录
+        assert!(states.update(1, 1, &trigger, true));
+        states.remove_rule(1);
+        assert!(states.update(1, 0, &trigger, true));
+
+        // 没有比较值的触发器每次变化都触发
+        let any = RuleTrigger::Property {
+            property: PropertyRef { device_id: 1, siid: Some(2), piid: Some(1), name: None },
+            op: None,
+            value: None,
+        };
+        assert!(states.update(2, 0, &any, true));
+        assert!(states.update(2, 0, &any, true));
+    }
+}
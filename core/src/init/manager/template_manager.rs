@@ -1,19 +1,25 @@
+use std::collections::BTreeSet;
 use std::env;
 use std::env::VarError;
 use std::ops::Deref;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 use anyhow::anyhow;
 use async_recursion::async_recursion;
 use axum::http::Method;
 use dashmap::DashMap;
-use log::error;
-use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseConnection, DatabaseTransaction, EntityTrait, IntoActiveModel, NotSet, QueryFilter, TransactionTrait, TryIntoModel};
+use hap_metadata::metadata::HapMetadata;
+use log::{error, info, warn};
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseConnection, DatabaseTransaction, EntityTrait, IntoActiveModel, JsonValue, NotSet, QueryFilter, TransactionTrait, TryIntoModel};
 use sea_orm::ActiveValue::Set;
 use serde::{Deserialize, Serialize};
+use target_hap::delegate::model::check_delegate_chars;
 use target_hap::hap_manager::HapManage;
+use tokio::sync::{broadcast, mpsc};
 
 use crate::config::context::get_data_dir;
 use crate::db::entity::hap_accessory::ModelDelegateParamVec;
@@ -22,10 +28,35 @@ use crate::db::entity::iot_device::{ActiveModel, Model, SourcePlatform};
 use crate::db::entity::prelude::{HapAccessoryActiveModel, HapAccessoryColumn, HapAccessoryEntity, HapCharacteristicActiveModel, HapCharacteristicColumn, HapCharacteristicEntity, HapServiceActiveModel, HapServiceColumn, HapServiceEntity, IotDeviceColumn, IotDeviceEntity, MiotDeviceModel};
 use crate::db::service::hap_bridge_service::create_hap_bridge;
 use crate::db::SNOWFLAKE;
+use crate::init::hap_init::{reload_accessory, reload_device_accessories};
 use crate::init::helper::template_helper::{AccessoryCtx, DeviceModelCtx, to_accessory_model, to_char_model, to_device_model, to_service_model};
+use crate::init::manager::ble_manager::BleManager;
+use crate::init::manager::device_manager::IotDeviceManager;
+use crate::init::manager::mqtt_export_manager::MqttExportManager;
+use crate::service::bind_key_service::sync_device_bind_key;
+use crate::template::checker::validate_template;
 use crate::template::hap::accessory::AccessoryTemplate;
 use crate::template::hap::service::ServiceTemplate;
-use crate::template::hl_template::{HlDeviceTemplate};
+use crate::template::hl_template::{HlDeviceTemplate, TemplateFormat};
+
+/// 模板目录变化的合并时间
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// 模板文件解析失败
+#[derive(Debug, Clone, Serialize)]
+pub struct TemplateFileError {
+    pub path: String,
+    pub error: String,
+}
+
+/// 模板文件变化,通过 socket.io 推送
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TemplateEvent {
+    Loaded { path: String, id: String },
+    Removed { path: String, id: String },
+    Error(TemplateFileError),
+}
 
 /// 模板管理器
 ///
@@ -34,7 +65,18 @@ pub struct TemplateManagerInner {
     // platform_templates: DashMap<SourcePlatform, u8>,
     ///model 和模板的映射
     pub templates: DashMap<String, HlDeviceTemplate>,
+    /// 文件路径和模板id 的映射,文件删除时移除模板
+    sources: DashMap<PathBuf, String>,
+    /// 解析失败的文件
+    errors: DashMap<PathBuf, String>,
+    events: broadcast::Sender<TemplateEvent>,
+    /// 目录监听,drop 后停止
+    watcher: Mutex<Option<RecommendedWatcher>>,
     hap_manager: HapManage,
+    device_manager: IotDeviceManager,
+    mqtt_export_manager: MqttExportManager,
+    ble_manager: BleManager,
+    hap_metadata: Arc<HapMetadata>,
     conn: DatabaseConnection,
 }
 
@@ -42,6 +84,21 @@ pub struct TemplateManagerInner {
 pub enum SourcePlatformModel {
     ///米家模型
     MiHome(MiotDeviceModel),
+    /// mqtt 设备, source_id 为设备标识
+    Mqtt {
+        source_id: String,
+        name: String,
+    },
+}
+
+impl SourcePlatformModel {
+    /// 平台,来源id,默认名称
+    fn source(&self) -> (SourcePlatform, String, String) {
+        match self {
+            SourcePlatformModel::MiHome(model) => (SourcePlatform::Mijia, model.did.clone(), model.name.clone()),
+            SourcePlatformModel::Mqtt { source_id, name } => (SourcePlatform::Mqtt, source_id.clone(), name.clone()),
+        }
+    }
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -71,29 +128,20 @@ pub struct ApplyTemplateOptions {
 }
 
 impl TemplateManagerInner {
-    /// 模板路径中
-    pub async fn init(&self) -> anyhow::Result<()> {
-        let dir = get_data_dir();
-        let template_dir = match env::var("TEMPLATES_DIR") {
+    pub fn template_dir() -> PathBuf {
+        match env::var("TEMPLATES_DIR") {
             Ok(v) => {
                 PathBuf::from(v.as_str())
             }
             Err(_) => {
-                PathBuf::from(format!("{dir}/templates"))
+                PathBuf::from(format!("{}/templates", get_data_dir()))
             }
-        };
-        self.scan_dir_start(template_dir).await
+        }
     }
     pub async fn scan_dir_start(&self, template_dir: PathBuf) -> anyhow::Result<()> {
-        //遍历模板目录
+        //遍历模板目录,和目录监听一致,顶层的模板文件也加载
         if template_dir.is_dir() {
-            let mut entries = tokio::fs::read_dir(template_dir).await?;
-            while let Some(entry) = entries.next_entry().await? {
-                let path = entry.path();
-                if path.is_dir() {
-                    self.scan_dir(path).await?;
-                }
-            }
+            self.scan_dir(template_dir).await?;
         } else {
             error!("模板目录不存在,或不是一个目录:{:?}", template_dir);
         }
@@ -109,20 +157,7 @@ impl TemplateManagerInner {
         while let Some(file) = files.next_entry().await? {
             let file_path = file.path();
             if file_path.is_file() {
-                if Some(true) == file_path.extension()
-                    .map(|i| i == "toml") {
-                    //读取文件内容
-                    let content = tokio::fs::read_to_string(file_path).await?;
-                    //解析文件内容
-                    match HlDeviceTemplate::from_str(content.as_str()) {
-                        Ok(temp) => {
-                            self.templates.insert(temp.id.clone(), temp);
-                        }
-                        Err(e) => {
-                            log::error!("解析模板文件:{:?}\n失败:{:?}", file.path(),e);
-                        }
-                    }
-                }
+                self.load_file(file_path).await;
             } else {
                 //递归
                 self.scan_dir(file_path).await?;
@@ -131,6 +166,71 @@ impl TemplateManagerInner {
         Ok(())
     }
 
+    /// 加载单个模板文件,不是模板文件时忽略
+    pub async fn load_file(&self, path: PathBuf) {
+        let format = match TemplateFormat::from_path(path.as_path()) {
+            Some(f) => f,
+            None => return,
+        };
+        let result = match tokio::fs::read_to_string(path.as_path()).await {
+            Ok(content) => format.parse::<HlDeviceTemplate>(content.as_str())
+                .and_then(|temp| validate_template(&self.hap_metadata, &temp).map(|_| temp)),
+            Err(e) => Err(e.into()),
+        };
+        match result {
+            Ok(temp) => {
+                let id = temp.id.clone();
+                if let Some(old_id) = self.sources.insert(path.clone(), id.clone()) {
+                    if old_id != id {
+                        self.remove_template(old_id.as_str());
+                    }
+                }
+                self.templates.insert(id.clone(), temp);
+                self.errors.remove(&path);
+                let _ = self.events.send(TemplateEvent::Loaded { path: path_str(&path), id });
+            }
+            Err(e) => {
+                error!("解析模板文件:{:?}\n失败:{:?}", path, e);
+                let error = TemplateFileError { path: path_str(&path), error: format!("{:?}", e) };
+                self.errors.insert(path, error.error.clone());
+                let _ = self.events.send(TemplateEvent::Error(error));
+            }
+        }
+    }
+
+    /// 移除文件或目录下的模板
+    pub fn remove_path(&self, path: &Path) {
+        self.errors.retain(|p, _| !p.starts_with(path));
+        let removed: Vec<PathBuf> = self.sources.iter()
+            .filter(|e| e.key().starts_with(path))
+            .map(|e| e.key().clone())
+            .collect();
+        for p in removed {
+            if let Some((p, id)) = self.sources.remove(&p) {
+                self.remove_template(id.as_str());
+                let _ = self.events.send(TemplateEvent::Removed { path: path_str(&p), id });
+            }
+        }
+    }
+
+    /// 没有其他文件提供该模板时才移除
+    fn remove_template(&self, id: &str) {
+        if !self.sources.iter().any(|e| e.value() == id) {
+            self.templates.remove(id);
+        }
+    }
+
+    /// 解析失败的模板文件
+    pub fn errors(&self) -> Vec<TemplateFileError> {
+        self.errors.iter()
+            .map(|e| TemplateFileError { path: path_str(e.key()), error: e.value().clone() })
+            .collect()
+    }
+
+    pub fn subscribe_events(&self) -> broadcast::Receiver<TemplateEvent> {
+        self.events.subscribe()
+    }
+
     pub fn has_template(&self, platform: SourcePlatform, model: &str) -> bool {
         match platform {
             SourcePlatform::Mijia => {
@@ -154,15 +254,13 @@ impl TemplateManagerInner {
     pub async fn apply_template(&self, options: ApplyTemplateOptions) -> anyhow::Result<Vec<i64>> {
         // self.mihome_templates.get(model).map(|v| v.clone())
 
-        Ok(match &options.platform {
-            SourcePlatformModel::MiHome(model) => {
-                self.apply_mihome_template(model, &options).await?
-            }
-        })
+        self.apply_device_template(&options).await
     }
-    /// 应用米家模板
-    pub async fn apply_mihome_template(&self, model: &MiotDeviceModel, option: &ApplyTemplateOptions) -> anyhow::Result<Vec<i64>> {
+    /// 应用设备模板
+    pub async fn apply_device_template(&self, option: &ApplyTemplateOptions) -> anyhow::Result<Vec<i64>> {
         let temp = &option.template;
+        validate_template(&self.hap_metadata, temp)?;
+        let (platform, source_id, default_name) = option.platform.source();
         //开启事务
         let txn = self.conn.begin().await?;
         let batch_id = SNOWFLAKE.next_id();
@@ -170,12 +268,13 @@ impl TemplateManagerInner {
         for device in temp.devices.iter() {
             let device_id = SNOWFLAKE.next_id();
             //去设备名称
-            let name = device.name.clone().unwrap_or(model.name.clone());
+            let name = device.name.clone().unwrap_or(default_name.clone());
             let mut dev_ctx = DeviceModelCtx {
                 device_id,
                 name: name.clone(),
                 id: temp.id.clone(),
-                did: model.did.clone(),
+                platform,
+                did: source_id.clone(),
                 version: temp.version.clone(),
                 temp_batch_id: batch_id,
             };
@@ -239,7 +338,7 @@ impl TemplateManagerInner {
                                   accessory: AccessoryTemplate) -> anyhow::Result<()> {
         let accessory_model = accessory.clone().try_into_update_model()?;
         let txn = conn.begin().await?;
-        accessory_model.update(&txn).await?;
+        let aid = accessory_model.update(&txn).await?.aid;
         //服务转换
         for svc in accessory.services.into_iter() {
             for char in svc.chars.clone().into_iter() {
@@ -251,6 +350,27 @@ impl TemplateManagerInner {
         }
 
         txn.commit().await?;
+        // 替换运行中的配件
+        reload_accessory(conn, self.hap_manager.clone(), self.device_manager.clone(), aid).await?;
+        self.mqtt_export_manager.sync_accessory(aid).await;
+        Ok(())
+    }
+
+    /// 启动应用模板后的设备,并在运行中的桥接器上加载其配件
+    pub async fn start_devices(&self, dev_ids: Vec<i64>) -> anyhow::Result<()> {
+        let devices = IotDeviceEntity::find()
+            .filter(IotDeviceColumn::DeviceId.is_in(dev_ids.clone()))
+            .all(&self.conn)
+            .await?;
+        let params: Vec<&JsonValue> = devices.iter().map(|d| &d.params).collect();
+        sync_device_bind_key(&self.conn, &self.ble_manager, params.as_slice()).await;
+        self.device_manager.start_devices(Some(dev_ids.clone())).await?;
+        for device_id in dev_ids {
+            let aids = reload_device_accessories(&self.conn, self.hap_manager.clone(), self.device_manager.clone(), device_id).await?;
+            for aid in aids {
+                self.mqtt_export_manager.sync_accessory(aid).await;
+            }
+        }
         Ok(())
     }
 }
@@ -337,6 +457,7 @@ pub fn into_accessory_model(method: ApplyMethod, temp: &AccessoryTemplate) -> an
     for t in temp.hap_delegates.clone().into_iter() {
         delegate.push(t.try_into()?);
     }
+    check_delegate_chars(&delegate)?;
 
     Ok(HapAccessoryActiveModel {
         aid: Set(match method {
@@ -405,26 +526,157 @@ impl Deref for TemplateManager {
 }
 
 impl TemplateManager {
-    pub fn new(conn: DatabaseConnection, hap_manager: HapManage) -> Self {
+    pub fn new(conn: DatabaseConnection, hap_manager: HapManage, device_manager: IotDeviceManager,
+               mqtt_export_manager: MqttExportManager, ble_manager: BleManager,
+               hap_metadata: Arc<HapMetadata>) -> Self {
+        let (events, _) = broadcast::channel(64);
         Self {
             inner: Arc::new(TemplateManagerInner {
                 templates: Default::default(),
+                sources: Default::default(),
+                errors: Default::default(),
+                events,
+                watcher: Mutex::new(None),
                 conn,
                 hap_manager,
+                device_manager,
+                mqtt_export_manager,
+                ble_manager,
+                hap_metadata,
             }),
         }
     }
+
+    /// 模板路径中
+    pub async fn init(&self) -> anyhow::Result<()> {
+        let template_dir = TemplateManagerInner::template_dir();
+        self.scan_dir_start(template_dir.clone()).await?;
+        if template_dir.is_dir() {
+            if let Err(e) = self.watch(template_dir.as_path()) {
+                error!("监听模板目录失败:{:?}", e);
+            }
+        }
+        Ok(())
+    }
+
+    /// 监听模板目录,文件变化时重新加载
+    fn watch(&self, dir: &Path) -> anyhow::Result<()> {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let mut watcher = notify::recommended_watcher(move |res: notify::Result<Event>| {
+            let _ = tx.send(res);
+        })?;
+        watcher.watch(dir, RecursiveMode::Recursive)?;
+        self.watcher.lock().unwrap().replace(watcher);
+        info!("监听模板目录:{:?}", dir);
+        let manager = self.clone();
+        tokio::spawn(async move {
+            while let Some(res) = rx.recv().await {
+                // 编辑器保存时会产生多个事件,合并一段时间内的变化后再处理
+                let mut paths = BTreeSet::new();
+                let mut next = Some(res);
+                while let Some(res) = next {
+                    match res {
+                        Ok(event) if !event.kind.is_access() => paths.extend(event.paths),
+                        Ok(_) => {}
+                        Err(e) => warn!("模板目录监听错误:{:?}", e),
+                    }
+                    next = tokio::time::timeout(WATCH_DEBOUNCE, rx.recv()).await.ok().flatten();
+                }
+                for path in paths {
+                    if path.is_file() {
+                        manager.load_file(path).await;
+                    } else if path.is_dir() {
+                        if let Err(e) = manager.scan_dir(path).await {
+                            warn!("扫描模板目录失败:{:?}", e);
+                        }
+                    } else {
+                        manager.remove_path(path.as_path());
+                    }
+                }
+            }
+        });
+        Ok(())
+    }
 }
 
+fn path_str(path: &Path) -> String {
+    path.to_string_lossy().to_string()
+}
+
+#[cfg(test)]
 mod test {
     use std::path::PathBuf;
     use std::str::FromStr;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use hap_metadata::hap_metadata;
+    use sea_orm::DatabaseConnection;
+    use target_hap::hap_manager::HapManage;
 
     use crate::db::entity::prelude::HapAccessoryActiveModel;
     use crate::init::logger_init::init_logger;
+    use crate::init::manager::ble_manager::BleManager;
+    use crate::init::manager::device_manager::IotDeviceManager;
+    use crate::init::manager::mi_account_manager::MiAccountManager;
+    use crate::init::manager::mqtt_export_manager::MqttExportManager;
     use crate::init::manager::template_manager::TemplateManager;
     use crate::template::hl_template::HlDeviceTemplate;
 
+    const TEMPLATE: &str = include_str!("../../../../templates/mijia/zimi/zimi.switch.dhkg01.toml");
+
+    /// 不连接数据库,只用于加载模板文件
+    fn manager() -> TemplateManager {
+        let conn = DatabaseConnection::Disconnected;
+        let hap_manager = HapManage::new();
+        let ble_manager = BleManager::new();
+        let device_manager = IotDeviceManager::new(conn.clone(), MiAccountManager::new(conn.clone()), ble_manager.clone());
+        let mqtt_export_manager = MqttExportManager::new(conn.clone(), hap_manager.clone(), device_manager.clone());
+        TemplateManager::new(conn, hap_manager, device_manager, mqtt_export_manager, ble_manager, Arc::new(hap_metadata().unwrap()))
+    }
+
+    /// 等待监听处理完成
+    async fn wait_for(check: impl Fn() -> bool) -> bool {
+        for _ in 0..50 {
+            if check() {
+                return true;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        false
+    }
+
+    #[tokio::test]
+    pub async fn test_watch() {
+        let dir = std::env::temp_dir().join(format!("hl-templates-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("switch.toml");
+        std::fs::write(&file, TEMPLATE).unwrap();
+
+        let manager = manager();
+        // 顶层文件在扫描时加载
+        manager.scan_dir_start(dir.clone()).await.unwrap();
+        assert!(manager.templates.contains_key("zimi.switch.dhkg01"));
+        manager.watch(dir.as_path()).unwrap();
+
+        // 新建子目录中的文件
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        std::fs::write(dir.join("sub/other.toml"), TEMPLATE.replace("id = \"zimi.switch.dhkg01\"", "id = \"other\"")).unwrap();
+        assert!(wait_for(|| manager.templates.contains_key("other")).await);
+
+        // 修改 id 后旧模板移除
+        std::fs::write(&file, TEMPLATE.replace("id = \"zimi.switch.dhkg01\"", "id = \"modified\"")).unwrap();
+        assert!(wait_for(|| manager.templates.contains_key("modified") && !manager.templates.contains_key("zimi.switch.dhkg01")).await);
+
+        // 删除文件
+        std::fs::remove_file(&file).unwrap();
+        assert!(wait_for(|| !manager.templates.contains_key("modified")).await);
+        assert!(manager.templates.contains_key("other"));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
 
     #[tokio::test]
     pub async fn test_file() {
@@ -447,7 +699,7 @@ mod test {
     #[tokio::test]
     pub async fn test() {
         init_logger();
-        let manager = TemplateManager::new();
+        let manager = manager();
         let template_dir = PathBuf::from("/Users/huanxi/project/homelink/data/templates");
         let a = manager.scan_dir_start(template_dir).await.unwrap();
         for a in &manager.templates {
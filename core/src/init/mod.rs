@@ -19,6 +19,7 @@ pub mod manager;
 mod accessory_init;
 pub(crate) mod helper;
 pub mod logger_init;
+pub mod mqtt_export_init;
 
 pub type FuturesMutex<T> = futures_util::lock::Mutex<T>;
 pub type TokioMutex<T> = tokio::sync::Mutex<T>;
@@ -39,4 +40,6 @@ pub struct Managers {
     pub mi_account_manager: manager::mi_account_manager::MiAccountManager,
     pub template_manager: manager::template_manager::TemplateManager,
     pub ble_manager: manager::ble_manager::BleManager,
+    pub rule_manager: manager::rule_manager::RuleManager,
+    pub mqtt_export_manager: manager::mqtt_export_manager::MqttExportManager,
 }
\ No newline at end of file
@@ -0,0 +1,95 @@
+use std::str::FromStr;
+
+use log::{error, info};
+use sea_orm::*;
+
+use hl_integration::platform::hap::hap_device::AsHapDevice;
+use target_hap::hap_manager::HapManage;
+use target_hap::hap_type_wrapper::HapTypeWrapper;
+use target_mqtt::config::MqttExportConfig;
+use target_mqtt::discovery::{ExportAccessory, ExportChar, ExportService};
+use target_mqtt::exporter::MqttExporter;
+
+use crate::db::entity::prelude::{HapAccessoryColumn, HapAccessoryEntity, HapAccessoryModel, HapCharacteristicEntity, HapServiceColumn, HapServiceEntity};
+use crate::init::DevicePointer;
+use crate::init::manager::device_manager::IotDeviceManager;
+
+/// 启动mqtt 导出,导出已运行的hap 配件
+pub async fn init_mqtt_export(conn: &DatabaseConnection,
+                              config: MqttExportConfig,
+                              hap_manager: HapManage,
+                              device_manager: IotDeviceManager) -> anyhow::Result<MqttExporter> {
+    let exporter = MqttExporter::new(config, hap_manager.clone());
+    let accessories = HapAccessoryEntity::find()
+        .filter(HapAccessoryColumn::Disabled.eq(false))
+        .all(conn)
+        .await?;
+    for accessory in accessories.into_iter() {
+        let aid = accessory.aid;
+        // 只导出已初始化的配件
+        if !hap_manager.accessory_map.contains_key(&(aid as u64)) {
+            continue;
+        }
+        let device = match device_manager.get_device(accessory.device_id) {
+            None => continue,
+            Some(d) => d,
+        };
+        match to_export_accessory(conn, &hap_manager, &device, accessory).await {
+            Ok(export) => exporter.add_accessory(export, device).await,
+            Err(e) => error!("导出配件:{aid}失败:{e}"),
+        }
+    }
+    let exporter_c = exporter.clone();
+    tokio::spawn(async move {
+        exporter_c.run().await;
+    });
+    info!("mqtt 导出已启动");
+    Ok(exporter)
+}
+
+/// 读取配件的服务和特征
+pub(crate) async fn to_export_accessory<C: ConnectionTrait>(conn: &C,
+                                                 hap_manager: &HapManage,
+                                                 device: &DevicePointer,
+                                                 accessory: HapAccessoryModel) -> anyhow::Result<ExportAccessory> {
+    let info = device.as_hap_device().map(|d| d.get_hap_info());
+    let services = HapServiceEntity::find()
+        .filter(HapServiceColumn::AccessoryId.eq(accessory.aid)
+            .and(HapServiceColumn::Disabled.eq(false)))
+        .find_with_related(HapCharacteristicEntity)
+        .all(conn)
+        .await?;
+    let mut list = vec![];
+    for (svc, chars) in services.into_iter() {
+        let mut export_chars = vec![];
+        for ch in chars.into_iter().filter(|c| !c.disabled) {
+            let ctag = HapTypeWrapper::from_str(ch.characteristic_type.as_str())?;
+            let mut info = ch.info.0;
+            // 未配置权限时使用默认权限
+            if info.perms.is_empty() {
+                if let Some(df) = hap_manager.get_hap_default_info(ctag.into()) {
+                    info.perms = df.perms;
+                }
+            }
+            export_chars.push(ExportChar {
+                ctag,
+                name: ch.name,
+                info,
+            });
+        }
+        list.push(ExportService {
+            stag: svc.tag.unwrap_or("default".to_string()),
+            service_type: HapTypeWrapper::from_str(svc.service_type.as_str())?,
+            name: svc.configured_name,
+            chars: export_chars,
+        });
+    }
+    Ok(ExportAccessory {
+        aid: accessory.aid as u64,
+        device_id: accessory.device_id,
+        name: accessory.name,
+        manufacturer: info.as_ref().map(|i| i.manufacturer.clone()),
+        model: info.map(|i| i.model),
+        services: list,
+    })
+}
@@ -11,6 +11,8 @@ pub mod migration;
 mod device;
 /// 转换模板
 pub mod template;
+/// 自动化规则
+pub mod rule;
 pub mod unit_convertor;
 pub mod socketio;
 // pub mod graphql;
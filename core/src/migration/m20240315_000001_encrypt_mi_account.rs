@@ -0,0 +1,43 @@
+use log::info;
+use sea_orm::{ActiveModelTrait, EntityTrait};
+use sea_orm::ActiveValue::Set;
+use sea_orm_migration::prelude::*;
+use miot_proto::secret::SecretCipher;
+use crate::config::secret::master_cipher;
+use crate::db::entity::prelude::{MiAccountActiveModel, MiAccountEntity};
+
+/// 使用主密钥加密历史明文的米家账号密码
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+        let accounts: Vec<_> = MiAccountEntity::find()
+            .all(db)
+            .await?
+            .into_iter()
+            .filter(|a| !SecretCipher::is_encrypted(a.password.as_str()))
+            .collect();
+        if accounts.is_empty() {
+            return Ok(());
+        }
+        let cipher = master_cipher()
+            .map_err(|e| DbErr::Custom(format!("加密米家账号密码失败:{}", e)))?;
+        for account in accounts {
+            let model = MiAccountActiveModel {
+                account: Set(account.account.clone()),
+                password: Set(cipher.encrypt_str(account.password.as_str())),
+                ..Default::default()
+            };
+            model.update(db).await?;
+            info!("加密米家账号密码:{}", account.account);
+        }
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        Ok(())
+    }
+}
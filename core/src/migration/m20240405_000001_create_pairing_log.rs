@@ -0,0 +1,23 @@
+use sea_orm::Schema;
+use sea_orm_migration::prelude::*;
+use crate::db::entity::pairing_log;
+use crate::migration::db_utils::create_one_table;
+
+/// 配对审计日志表
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+        let builder = manager.get_database_backend();
+        let schema = Schema::new(builder);
+        create_one_table(db, builder, &schema, pairing_log::Entity).await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        Ok(())
+    }
+}
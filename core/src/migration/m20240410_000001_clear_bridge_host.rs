@@ -0,0 +1,29 @@
+use log::info;
+use sea_orm::{ColumnTrait, EntityTrait, QueryFilter};
+use sea_orm::prelude::Expr;
+use sea_orm_migration::prelude::*;
+use crate::db::entity::prelude::{HapBridgeColumn, HapBridgeEntity};
+
+/// 清空旧版本启动时自动写入的桥接器 host, 之后 host 只保存手动配置的地址
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+        let res = HapBridgeEntity::update_many()
+            .col_expr(HapBridgeColumn::Host, Expr::value(Option::<String>::None))
+            .filter(HapBridgeColumn::Host.is_not_null())
+            .exec(db)
+            .await?;
+        if res.rows_affected > 0 {
+            info!("清空自动检测的桥接器地址:{}个", res.rows_affected);
+        }
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        Ok(())
+    }
+}
@@ -3,12 +3,26 @@ use sea_orm_migration::{MigrationTrait, MigratorTrait};
 pub mod m20220101_000001_create_table;
 mod db_utils;
 mod m20230309_000001_add_column;
+mod m20240301_000001_create_sys_user;
+mod m20240315_000001_encrypt_mi_account;
+mod m20240401_000001_create_rule;
+mod m20240402_000001_create_scene;
+mod m20240405_000001_create_pairing_log;
+mod m20240410_000001_clear_bridge_host;
 
 pub struct Migrator;
 
 #[async_trait::async_trait]
 impl MigratorTrait for Migrator {
     fn migrations() -> Vec<Box<dyn MigrationTrait>> {
-        vec![Box::new(m20220101_000001_create_table::Migration)]
+        vec![
+            Box::new(m20220101_000001_create_table::Migration),
+            Box::new(m20240301_000001_create_sys_user::Migration),
+            Box::new(m20240315_000001_encrypt_mi_account::Migration),
+            Box::new(m20240401_000001_create_rule::Migration),
+            Box::new(m20240402_000001_create_scene::Migration),
+            Box::new(m20240405_000001_create_pairing_log::Migration),
+            Box::new(m20240410_000001_clear_bridge_host::Migration),
+        ]
     }
 }
\ No newline at end of file
@@ -0,0 +1,240 @@
+use std::cmp::Ordering;
+
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use serde_aux::prelude::deserialize_number_from_string;
+use serde_json::Value;
+
+pub mod schedule;
+
+/// 设备属性, 米家设备使用 siid,piid, mqtt 设备使用 name
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PropertyRef {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub device_id: i64,
+    pub siid: Option<i32>,
+    pub piid: Option<i32>,
+    pub name: Option<String>,
+}
+
+impl PropertyRef {
+    pub fn matches_spec(&self, siid: i32, piid: i32) -> bool {
+        self.siid == Some(siid) && self.piid == Some(piid)
+    }
+    pub fn matches_name(&self, name: &str) -> bool {
+        self.name.as_deref() == Some(name)
+    }
+
+    /// 检查属性配置, siid,piid 需要同时设置且大于0, 否则需要设置 name
+    pub fn check(&self) -> anyhow::Result<()> {
+        match (self.siid, self.piid) {
+            (Some(siid), Some(piid)) if siid > 0 && piid > 0 => Ok(()),
+            (Some(siid), Some(piid)) => Err(anyhow!("属性 siid:{siid},piid:{piid} 错误,应大于0")),
+            (Some(_), None) | (None, Some(_)) => Err(anyhow!("属性 siid,piid 需要同时设置")),
+            (None, None) => match self.name.as_deref() {
+                Some(name) if !name.is_empty() => Ok(()),
+                _ => Err(anyhow!("属性需要设置 siid,piid 或 name")),
+            }
+        }
+    }
+}
+
+/// 日出/日落
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SunEvent {
+    Sunrise,
+    Sunset,
+}
+
+/// 规则触发器
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RuleTrigger {
+    /// 设备属性变化,设置了 op,value 时变化后的值满足比较才触发
+    Property {
+        #[serde(flatten)]
+        property: PropertyRef,
+        op: Option<CompareOp>,
+        value: Option<Value>,
+    },
+    /// 蓝牙广播值, mac 如 A4:C1:38:00:00:00, etype 为米家蓝牙值类型
+    Ble {
+        mac: String,
+        etype: u16,
+        op: Option<CompareOp>,
+        value: Option<Value>,
+    },
+    /// 定时, at 为 HH:MM, weekdays 1-7 表示周一到周日,为空表示每天
+    Time {
+        at: String,
+        #[serde(default)]
+        weekdays: Vec<u32>,
+    },
+    /// 日出日落,需要配置 location
+    Sun {
+        event: SunEvent,
+        /// 偏移分钟,负数表示提前
+        #[serde(default)]
+        offset_minutes: i64,
+        #[serde(default)]
+        weekdays: Vec<u32>,
+    },
+}
+
+impl RuleTrigger {
+    /// 是否为定时类触发器
+    pub fn is_schedule(&self) -> bool {
+        matches!(self, RuleTrigger::Time { .. } | RuleTrigger::Sun { .. })
+    }
+}
+
+/// 规则条件,全部满足才执行动作
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RuleCondition {
+    /// 设备属性当前值比较
+    Property {
+        #[serde(flatten)]
+        property: PropertyRef,
+        #[serde(default)]
+        op: CompareOp,
+        value: Value,
+    },
+    /// 时间段 HH:MM, from 大于 to 时表示跨天
+    TimeRange {
+        from: String,
+        to: String,
+        #[serde(default)]
+        weekdays: Vec<u32>,
+    },
+}
+
+/// 规则动作,按顺序执行
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RuleAction {
+    /// 设置属性
+    SetProperty {
+        #[serde(flatten)]
+        property: PropertyRef,
+        value: Value,
+    },
+    /// 调用米家设备动作
+    CallAction {
+        #[serde(deserialize_with = "deserialize_number_from_string")]
+        device_id: i64,
+        siid: i32,
+        aiid: i32,
+        #[serde(default)]
+        ins: Vec<Value>,
+    },
+    /// 延时
+    Delay {
+        ms: u64,
+    },
+}
+
+/// 比较方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CompareOp {
+    #[default]
+    Eq,
+    Ne,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+}
+
+fn as_number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(n) => n.as_f64(),
+        Value::Bool(b) => Some(if *b { 1.0 } else { 0.0 }),
+        _ => None,
+    }
+}
+
+impl CompareOp {
+    /// left op right, 数字和布尔按数值比较,字符串按字典序,其他类型只支持 eq,ne
+    pub fn compare(&self, left: &Value, right: &Value) -> bool {
+        let ordering = match (as_number(left), as_number(right), left, right) {
+            (Some(l), Some(r), _, _) => l.partial_cmp(&r),
+            (_, _, Value::String(l), Value::String(r)) => Some(l.cmp(r)),
+            _ => None,
+        };
+        match ordering {
+            Some(o) => match self {
+                CompareOp::Eq => o == Ordering::Equal,
+                CompareOp::Ne => o != Ordering::Equal,
+                CompareOp::Gt => o == Ordering::Greater,
+                CompareOp::Ge => o != Ordering::Less,
+                CompareOp::Lt => o == Ordering::Less,
+                CompareOp::Le => o != Ordering::Greater,
+            },
+            None => match self {
+                CompareOp::Eq => left == right,
+                CompareOp::Ne => left != right,
+                _ => false,
+            },
+        }
+    }
+
+    /// 触发器中的可选比较,未设置时总是满足
+    pub fn check(op: Option<CompareOp>, expect: Option<&Value>, current: &Value) -> bool {
+        match expect {
+            None => true,
+            Some(expect) => op.unwrap_or_default().compare(current, expect),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use crate::rule::{CompareOp, PropertyRef, RuleAction, RuleTrigger};
+
+    #[test]
+    fn test_compare() {
+        assert!(CompareOp::Eq.compare(&json!(1), &json!(1.0)));
+        assert!(CompareOp::Eq.compare(&json!(true), &json!(1)));
+        assert!(CompareOp::Gt.compare(&json!(26.5), &json!(26)));
+        assert!(!CompareOp::Lt.compare(&json!(26.5), &json!(26)));
+        assert!(CompareOp::Le.compare(&json!("a"), &json!("b")));
+        assert!(CompareOp::Ne.compare(&json!("ON"), &json!(1)));
+        assert!(!CompareOp::Gt.compare(&json!("ON"), &json!(1)));
+        assert!(CompareOp::check(None, None, &json!(0)));
+        assert!(CompareOp::check(None, Some(&json!(true)), &json!(true)));
+    }
+
+    #[test]
+    fn test_property_check() {
+        let property = |siid, piid, name: Option<&str>| PropertyRef { device_id: 1, siid, piid, name: name.map(str::to_string) };
+        assert!(property(Some(2), Some(1), None).check().is_ok());
+        assert!(property(None, None, Some("power")).check().is_ok());
+        assert!(property(Some(2), None, None).check().is_err());
+        assert!(property(Some(0), Some(1), None).check().is_err());
+        assert!(property(None, None, Some("")).check().is_err());
+        assert!(property(None, None, None).check().is_err());
+    }
+
+    #[test]
+    fn test_serde() {
+        let trigger: RuleTrigger = serde_json::from_value(json!({
+            "type": "property", "device_id": 1, "siid": 2, "piid": 1, "op": "gt", "value": 30
+        })).unwrap();
+        match trigger {
+            RuleTrigger::Property { property, op, .. } => {
+                assert!(property.matches_spec(2, 1));
+                assert_eq!(op, Some(CompareOp::Gt));
+            }
+            _ => panic!("触发器类型错误"),
+        }
+        let action: RuleAction = serde_json::from_value(json!({
+            "type": "set_property", "device_id": "1194242687084003328", "name": "power", "value": "ON"
+        })).unwrap();
+        assert!(matches!(action, RuleAction::SetProperty { ref property, .. } if property.matches_name("power")));
+    }
+}
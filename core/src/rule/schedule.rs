@@ -0,0 +1,183 @@
+use anyhow::anyhow;
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveTime, TimeZone, Utc};
+
+use crate::config::cfgs::Location;
+use crate::rule::{RuleTrigger, SunEvent};
+
+/// 向后查找的天数
+const SEARCH_DAYS: i64 = 8;
+
+/// 解析 HH:MM 或 HH:MM:SS
+pub fn parse_time(s: &str) -> anyhow::Result<NaiveTime> {
+    NaiveTime::parse_from_str(s, "%H:%M")
+        .or_else(|_| NaiveTime::parse_from_str(s, "%H:%M:%S"))
+        .map_err(|_| anyhow!("时间格式错误:{s},应为 HH:MM"))
+}
+
+/// 检查星期,只能为 1-7
+pub fn check_weekdays(weekdays: &[u32]) -> anyhow::Result<()> {
+    match weekdays.iter().find(|d| !(1..=7).contains(*d)) {
+        Some(d) => Err(anyhow!("星期:{d}错误,应为 1-7")),
+        None => Ok(()),
+    }
+}
+
+/// weekdays 为空表示每天
+pub fn weekday_match(weekdays: &[u32], date: NaiveDate) -> bool {
+    weekdays.is_empty() || weekdays.contains(&date.weekday().number_from_monday())
+}
+
+/// 计算日出日落(utc), 极昼极夜时返回 None
+pub fn sun_times(date: NaiveDate, latitude: f64, longitude: f64) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+    let epoch = NaiveDate::from_ymd_opt(2000, 1, 1)?;
+    let n = (date - epoch).num_days() as f64;
+    // 平太阳时
+    let j_star = n - longitude / 360.0;
+    // 平近点角
+    let m = (357.5291 + 0.98560028 * j_star).rem_euclid(360.0).to_radians();
+    // 中心差
+    let c = 1.9148 * m.sin() + 0.02 * (2.0 * m).sin() + 0.0003 * (3.0 * m).sin();
+    // 黄经
+    let lambda = (m.to_degrees() + c + 282.9372).rem_euclid(360.0).to_radians();
+    // 太阳过中天
+    let j_transit = 2451545.0 + j_star + 0.0053 * m.sin() - 0.0069 * (2.0 * lambda).sin();
+    // 赤纬
+    let sin_d = lambda.sin() * 23.4397f64.to_radians().sin();
+    let cos_d = (1.0 - sin_d * sin_d).sqrt();
+    let lat = latitude.to_radians();
+    let cos_w = ((-0.833f64).to_radians().sin() - lat.sin() * sin_d) / (lat.cos() * cos_d);
+    if !(-1.0..=1.0).contains(&cos_w) {
+        return None;
+    }
+    let w = cos_w.acos().to_degrees() / 360.0;
+    let to_utc = |j: f64| Utc.timestamp_millis_opt(((j - 2440587.5) * 86400000.0) as i64).single();
+    Some((to_utc(j_transit - w)?, to_utc(j_transit + w)?))
+}
+
+/// 定时触发器在 now 之后的下一次触发时间
+pub fn next_fire<Tz: TimeZone>(trigger: &RuleTrigger, now: &DateTime<Tz>, location: Option<&Location>) -> anyhow::Result<Option<DateTime<Tz>>> {
+    let tz = now.timezone();
+    let today = now.date_naive();
+    match trigger {
+        RuleTrigger::Time { at, weekdays } => {
+            let time = parse_time(at)?;
+            for i in 0..SEARCH_DAYS {
+                let date = today + Duration::days(i);
+                if !weekday_match(weekdays, date) {
+                    continue;
+                }
+                if let Some(t) = tz.from_local_datetime(&date.and_time(time)).earliest() {
+                    if t > *now {
+                        return Ok(Some(t));
+                    }
+                }
+            }
+            Ok(None)
+        }
+        RuleTrigger::Sun { event, offset_minutes, weekdays } => {
+            let location = location.ok_or(anyhow!("未配置 location,无法计算日出日落"))?;
+            for i in -1..SEARCH_DAYS {
+                let date = today + Duration::days(i);
+                let sun = match sun_times(date, location.latitude, location.longitude) {
+                    None => continue,
+                    Some(s) => s,
+                };
+                let t = match event {
+                    SunEvent::Sunrise => sun.0,
+                    SunEvent::Sunset => sun.1,
+                } + Duration::minutes(*offset_minutes);
+                let t = t.with_timezone(&tz);
+                if t > *now && weekday_match(weekdays, t.date_naive()) {
+                    return Ok(Some(t));
+                }
+            }
+            Ok(None)
+        }
+        _ => Ok(None),
+    }
+}
+
+/// 当前时间是否在时间段内
+pub fn in_time_range<Tz: TimeZone>(now: &DateTime<Tz>, from: &str, to: &str, weekdays: &[u32]) -> anyhow::Result<bool> {
+    let (from, to) = (parse_time(from)?, parse_time(to)?);
+    let time = now.time();
+    let date = now.date_naive();
+    Ok(if from <= to {
+        weekday_match(weekdays, date) && from <= time && time < to
+    } else if time >= from {
+        weekday_match(weekdays, date)
+    } else {
+        // 跨天,星期按开始那天计算
+        time < to && weekday_match(weekdays, date - Duration::days(1))
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{FixedOffset, NaiveDate, TimeZone, Timelike};
+
+    use crate::config::cfgs::Location;
+    use crate::rule::{RuleTrigger, SunEvent};
+    use crate::rule::schedule::{check_weekdays, in_time_range, next_fire, sun_times};
+
+    fn hm(t: chrono::DateTime<FixedOffset>) -> u32 {
+        t.hour() * 60 + t.minute()
+    }
+
+    #[test]
+    fn test_check_weekdays() {
+        assert!(check_weekdays(&[]).is_ok());
+        assert!(check_weekdays(&[1, 7]).is_ok());
+        assert!(check_weekdays(&[0]).is_err());
+        assert!(check_weekdays(&[1, 8]).is_err());
+    }
+
+    #[test]
+    fn test_sun_times() {
+        let tz = FixedOffset::east_opt(8 * 3600).unwrap();
+        // 北京夏至 04:46 19:46
+        let (rise, set) = sun_times(NaiveDate::from_ymd_opt(2024, 6, 21).unwrap(), 39.9042, 116.4074).unwrap();
+        assert!(hm(rise.with_timezone(&tz)).abs_diff(4 * 60 + 46) <= 1);
+        assert!(hm(set.with_timezone(&tz)).abs_diff(19 * 60 + 46) <= 1);
+        // 上海冬至 06:48 16:55
+        let (rise, set) = sun_times(NaiveDate::from_ymd_opt(2024, 12, 21).unwrap(), 31.2304, 121.4737).unwrap();
+        assert!(hm(rise.with_timezone(&tz)).abs_diff(6 * 60 + 48) <= 1);
+        assert!(hm(set.with_timezone(&tz)).abs_diff(16 * 60 + 55) <= 1);
+        // 北极圈极昼
+        assert!(sun_times(NaiveDate::from_ymd_opt(2024, 6, 21).unwrap(), 80.0, 0.0).is_none());
+    }
+
+    #[test]
+    fn test_next_fire() {
+        let tz = FixedOffset::east_opt(8 * 3600).unwrap();
+        // 2024-06-21 周五
+        let now = tz.with_ymd_and_hms(2024, 6, 21, 8, 0, 0).unwrap();
+        let trigger = RuleTrigger::Time { at: "07:30".to_string(), weekdays: vec![] };
+        let next = next_fire(&trigger, &now, None).unwrap().unwrap();
+        assert_eq!(next, tz.with_ymd_and_hms(2024, 6, 22, 7, 30, 0).unwrap());
+
+        // 只在周一
+        let trigger = RuleTrigger::Time { at: "07:30".to_string(), weekdays: vec![1] };
+        let next = next_fire(&trigger, &now, None).unwrap().unwrap();
+        assert_eq!(next, tz.with_ymd_and_hms(2024, 6, 24, 7, 30, 0).unwrap());
+
+        let trigger = RuleTrigger::Sun { event: SunEvent::Sunset, offset_minutes: -30, weekdays: vec![] };
+        assert!(next_fire(&trigger, &now, None).is_err());
+        let location = Location { latitude: 39.9042, longitude: 116.4074 };
+        let next = next_fire(&trigger, &now, Some(&location)).unwrap().unwrap();
+        assert_eq!(next.date_naive(), now.date_naive());
+        assert!(hm(next).abs_diff(19 * 60 + 16) <= 1);
+    }
+
+    #[test]
+    fn test_time_range() {
+        let tz = FixedOffset::east_opt(8 * 3600).unwrap();
+        let now = tz.with_ymd_and_hms(2024, 6, 21, 23, 0, 0).unwrap();
+        assert!(in_time_range(&now, "22:00", "06:00", &[]).unwrap());
+        assert!(!in_time_range(&now, "08:00", "22:00", &[]).unwrap());
+        let now = tz.with_ymd_and_hms(2024, 6, 22, 1, 0, 0).unwrap();
+        // 周五晚开始的时间段
+        assert!(in_time_range(&now, "22:00", "06:00", &[5]).unwrap());
+        assert!(!in_time_range(&now, "22:00", "06:00", &[6]).unwrap());
+    }
+}
@@ -0,0 +1,487 @@
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::anyhow;
+use log::info;
+use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseConnection, DatabaseTransaction, EntityTrait, IntoActiveModel, QueryFilter, TransactionTrait};
+use sea_orm::ActiveValue::Set;
+use serde::{Deserialize, Serialize};
+
+use crate::config::secret::{decrypt_secret, encrypt_secret};
+use crate::db::entity::mi_account::MiAccountStatus;
+use crate::db::entity::prelude::{HapAccessoryEntity, HapAccessoryModel, HapBridgeColumn, HapBridgeEntity, HapBridgeModel, HapCharacteristicEntity, HapCharacteristicModel, HapServiceEntity, HapServiceModel, IotDeviceEntity, IotDeviceModel, MiAccountActiveModel, MiAccountEntity, MiotDeviceEntity, MiotDeviceModel, PairingLogEntity, PairingLogModel, RuleEntity, RuleModel, SceneEntity, SceneModel};
+use crate::db::SNOWFLAKE;
+use crate::init::manager::template_manager::TemplateManagerInner;
+use crate::rule::{RuleAction, RuleCondition, RuleTrigger};
+use crate::template::hl_template::TemplateFormat;
+
+/// 备份格式版本,不兼容的修改需要增加
+pub const BACKUP_VERSION: u32 = 1;
+
+/// 米家账号,密码为主密钥加密后的密文,恢复时需要配置相同的主密钥
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MiAccountBackup {
+    pub account: String,
+    pub password: String,
+    pub status: MiAccountStatus,
+    pub memo: Option<String>,
+}
+
+/// 模板目录中的文件, path 为相对模板目录的路径
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TemplateFile {
+    pub path: String,
+    pub content: String,
+}
+
+/// 备份文件
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupArchive {
+    pub version: u32,
+    pub app_version: String,
+    pub create_at: chrono::DateTime<chrono::Utc>,
+    /// 包含配对列表和 ed25519 密钥
+    #[serde(default)]
+    pub bridges: Vec<HapBridgeModel>,
+    #[serde(default)]
+    pub accessories: Vec<HapAccessoryModel>,
+    #[serde(default)]
+    pub services: Vec<HapServiceModel>,
+    #[serde(default)]
+    pub characteristics: Vec<HapCharacteristicModel>,
+    #[serde(default)]
+    pub iot_devices: Vec<IotDeviceModel>,
+    #[serde(default)]
+    pub miot_devices: Vec<MiotDeviceModel>,
+    #[serde(default)]
+    pub mi_accounts: Vec<MiAccountBackup>,
+    #[serde(default)]
+    pub templates: Vec<TemplateFile>,
+    #[serde(default)]
+    pub rules: Vec<RuleModel>,
+    #[serde(default)]
+    pub scenes: Vec<SceneModel>,
+    #[serde(default)]
+    pub pairing_logs: Vec<PairingLogModel>,
+}
+
+/// 恢复结果
+#[derive(Debug, Default, Serialize)]
+pub struct RestoreResult {
+    pub bridges: usize,
+    pub accessories: usize,
+    pub services: usize,
+    pub characteristics: usize,
+    pub iot_devices: usize,
+    pub miot_devices: usize,
+    pub mi_accounts: usize,
+    pub templates: usize,
+    pub rules: usize,
+    pub scenes: usize,
+    pub pairing_logs: usize,
+    /// id 冲突重新分配的数量
+    pub remapped: usize,
+    /// 已存在跳过的米家账号和设备
+    pub skipped: usize,
+}
+
+/// 旧 id 到新 id 的映射,id 已被占用时重新分配
+#[derive(Default)]
+struct IdMap {
+    map: HashMap<i64, i64>,
+    remapped: usize,
+}
+
+impl IdMap {
+    fn alloc(&mut self, old: i64, exists: bool) -> i64 {
+        let new = if exists {
+            self.remapped += 1;
+            SNOWFLAKE.next_id()
+        } else {
+            old
+        };
+        self.map.insert(old, new);
+        new
+    }
+
+    fn get(&self, old: i64) -> Option<i64> {
+        self.map.get(&old).copied()
+    }
+
+    /// 不在备份中的 id 保持原值
+    fn get_or(&self, old: i64) -> i64 {
+        self.get(old).unwrap_or(old)
+    }
+}
+
+/// 更新规则中引用的设备id
+fn remap_rule_devices(rule: &mut RuleModel, device_ids: &IdMap) {
+    for trigger in rule.triggers.0.iter_mut() {
+        if let RuleTrigger::Property { property, .. } = trigger {
+            property.device_id = device_ids.get_or(property.device_id);
+        }
+    }
+    for condition in rule.conditions.0.iter_mut() {
+        if let RuleCondition::Property { property, .. } = condition {
+            property.device_id = device_ids.get_or(property.device_id);
+        }
+    }
+    for action in rule.actions.0.iter_mut() {
+        match action {
+            RuleAction::SetProperty { property, .. } => property.device_id = device_ids.get_or(property.device_id),
+            RuleAction::CallAction { device_id, .. } => *device_id = device_ids.get_or(*device_id),
+            RuleAction::Delay { .. } => {}
+        }
+    }
+}
+
+pub async fn backup(conn: &DatabaseConnection) -> anyhow::Result<BackupArchive> {
+    let mut mi_accounts = vec![];
+    for account in MiAccountEntity::find().all(conn).await? {
+        // 兼容未迁移的明文密码
+        let password = encrypt_secret(decrypt_secret(account.password.as_str())?.as_str())?;
+        mi_accounts.push(MiAccountBackup {
+            account: account.account,
+            password,
+            status: account.status,
+            memo: account.memo,
+        });
+    }
+    Ok(BackupArchive {
+        version: BACKUP_VERSION,
+        app_version: env!("CARGO_PKG_VERSION").to_string(),
+        create_at: chrono::Utc::now(),
+        bridges: HapBridgeEntity::find().all(conn).await?,
+        accessories: HapAccessoryEntity::find().all(conn).await?,
+        services: HapServiceEntity::find().all(conn).await?,
+        characteristics: HapCharacteristicEntity::find().all(conn).await?,
+        iot_devices: IotDeviceEntity::find().all(conn).await?,
+        miot_devices: MiotDeviceEntity::find().all(conn).await?,
+        mi_accounts,
+        templates: read_templates(TemplateManagerInner::template_dir().as_path()).await?,
+        rules: RuleEntity::find().all(conn).await?,
+        scenes: SceneEntity::find().all(conn).await?,
+        pairing_logs: PairingLogEntity::find().all(conn).await?,
+    })
+}
+
+async fn read_templates(dir: &Path) -> anyhow::Result<Vec<TemplateFile>> {
+    let mut files = vec![];
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(d) = dirs.pop() {
+        if !d.is_dir() {
+            continue;
+        }
+        let mut entries = tokio::fs::read_dir(d).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else if TemplateFormat::from_path(path.as_path()).is_some() {
+                let relative = path.strip_prefix(dir)?
+                    .components()
+                    .map(|c| c.as_os_str().to_string_lossy().to_string())
+                    .collect::<Vec<_>>()
+                    .join("/");
+                files.push(TemplateFile {
+                    path: relative,
+                    content: tokio::fs::read_to_string(path.as_path()).await?,
+                });
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// 模板路径只能是模板目录下的相对路径
+fn check_template_path(path: &str) -> anyhow::Result<PathBuf> {
+    let p = PathBuf::from(path);
+    let valid = p.components().all(|c| matches!(c, Component::Normal(_)))
+        && TemplateFormat::from_path(p.as_path()).is_some();
+    if !valid {
+        return Err(anyhow!("模板路径:{}不合法", path));
+    }
+    Ok(p)
+}
+
+/// 恢复前检查
+fn validate(archive: &BackupArchive) -> anyhow::Result<()> {
+    if archive.version > BACKUP_VERSION {
+        return Err(anyhow!("备份版本:{}高于当前支持的版本:{}", archive.version, BACKUP_VERSION));
+    }
+    for account in archive.mi_accounts.iter() {
+        decrypt_secret(account.password.as_str())
+            .map_err(|e| anyhow!("米家账号:{}密码解密失败,请确认主密钥与备份时一致:{}", account.account, e))?;
+    }
+    for t in archive.templates.iter() {
+        check_template_path(t.path.as_str())?;
+    }
+    Ok(())
+}
+
+/// 模板先写入临时文件,事务提交后再替换,失败时删除
+/// 临时文件的扩展名不是模板格式,不会被 template_manager 加载
+async fn stage_templates(templates: &[TemplateFile]) -> anyhow::Result<Vec<(PathBuf, PathBuf)>> {
+    let dir = TemplateManagerInner::template_dir();
+    let mut staged = vec![];
+    for t in templates.iter() {
+        let path = dir.join(check_template_path(t.path.as_str())?);
+        let mut tmp = path.clone().into_os_string();
+        tmp.push(".restore");
+        let tmp = PathBuf::from(tmp);
+        let res = async {
+            if let Some(parent) = path.parent() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+            tokio::fs::write(tmp.as_path(), t.content.as_bytes()).await
+        }.await;
+        staged.push((tmp, path));
+        if let Err(e) = res {
+            discard_templates(&staged).await;
+            return Err(anyhow!("写入模板:{}失败:{}", t.path, e));
+        }
+    }
+    Ok(staged)
+}
+
+async fn discard_templates(staged: &[(PathBuf, PathBuf)]) {
+    for (tmp, _) in staged.iter() {
+        let _ = tokio::fs::remove_file(tmp).await;
+    }
+}
+
+/// 在一个事务中导入备份, 其他 id 被占用时重新分配并更新引用
+/// 桥接器的 aid,配对和密钥保持不变,iOS 中的配对迁移后仍然有效, aid 被占用时恢复失败
+pub async fn restore(conn: &DatabaseConnection, mut archive: BackupArchive) -> anyhow::Result<RestoreResult> {
+    validate(&archive)?;
+    let templates = std::mem::take(&mut archive.templates);
+    let staged = stage_templates(&templates).await?;
+    let mut result = RestoreResult::default();
+    let res = async {
+        let txn = conn.begin().await?;
+        restore_tables(&txn, archive, &mut result).await?;
+        txn.commit().await?;
+        anyhow::Ok(())
+    }.await;
+    if let Err(e) = res {
+        discard_templates(&staged).await;
+        return Err(e);
+    }
+    // 模板目录由 template_manager 监听,替换后自动加载
+    for (tmp, path) in staged.iter() {
+        tokio::fs::rename(tmp, path).await?;
+        result.templates += 1;
+    }
+    info!("恢复备份:{:?}", result);
+    Ok(result)
+}
+
+async fn restore_tables(txn: &DatabaseTransaction, archive: BackupArchive, result: &mut RestoreResult) -> anyhow::Result<()> {
+    let mut bridge_ids = IdMap::default();
+    for bridge in archive.bridges.into_iter() {
+        let conflict = HapBridgeEntity::find()
+            .filter(HapBridgeColumn::PinCode.eq(bridge.pin_code)
+                .or(HapBridgeColumn::SetupId.eq(bridge.setup_id.clone())))
+            .one(txn)
+            .await?;
+        if let Some(c) = conflict {
+            return Err(anyhow!("桥接器:{}与已存在的桥接器:{}配对码冲突,可能已恢复过", bridge.name, c.name));
+        }
+        let conflict = HapBridgeEntity::find()
+            .filter(HapBridgeColumn::Port.eq(bridge.port))
+            .one(txn)
+            .await?;
+        if let Some(c) = conflict {
+            return Err(anyhow!("桥接器:{}的端口:{}已被桥接器:{}使用", bridge.name, bridge.port, c.name));
+        }
+        let exists = HapBridgeEntity::find_by_id(bridge.bridge_id).one(txn).await?.is_some();
+        let bridge_id = bridge_ids.alloc(bridge.bridge_id, exists);
+        let mut model = bridge.into_active_model().reset_all();
+        model.bridge_id = Set(bridge_id);
+        HapBridgeEntity::insert(model).exec_without_returning(txn).await?;
+        result.bridges += 1;
+    }
+
+    let mut device_ids = IdMap::default();
+    for device in archive.iot_devices.iter() {
+        let exists = IotDeviceEntity::find_by_id(device.device_id).one(txn).await?.is_some();
+        device_ids.alloc(device.device_id, exists);
+    }
+    for device in archive.iot_devices.into_iter() {
+        let device_id = device_ids.get_or(device.device_id);
+        // 网关不在备份中时保持原值
+        let gateway_id = device.gateway_id.map(|id| device_ids.get_or(id));
+        let mut model = device.into_active_model().reset_all();
+        model.device_id = Set(device_id);
+        model.gateway_id = Set(gateway_id);
+        IotDeviceEntity::insert(model).exec_without_returning(txn).await?;
+        result.iot_devices += 1;
+    }
+
+    let mut aids = IdMap::default();
+    for accessory in archive.accessories.into_iter() {
+        // aid 是 iOS 中配件的标识,重新分配后 HomeKit 中的配件会失效
+        if let Some(c) = HapAccessoryEntity::find_by_id(accessory.aid).one(txn).await? {
+            return Err(anyhow!("配件:{}的aid:{}已被配件:{}使用", accessory.name, accessory.aid, c.name));
+        }
+        let bridge_id = bridge_ids.get(accessory.bridge_id)
+            .ok_or(anyhow!("配件:{}的桥接器不在备份中", accessory.name))?;
+        let device_id = device_ids.get(accessory.device_id)
+            .ok_or(anyhow!("配件:{}的设备不在备份中", accessory.name))?;
+        aids.alloc(accessory.aid, false);
+        let mut model = accessory.into_active_model().reset_all();
+        model.bridge_id = Set(bridge_id);
+        model.device_id = Set(device_id);
+        HapAccessoryEntity::insert(model).exec_without_returning(txn).await?;
+        result.accessories += 1;
+    }
+
+    let mut service_ids = IdMap::default();
+    for service in archive.services.into_iter() {
+        let exists = HapServiceEntity::find_by_id(service.id).one(txn).await?.is_some();
+        aids.get(service.accessory_id)
+            .ok_or(anyhow!("服务:{}的配件不在备份中", service.id))?;
+        let id = service_ids.alloc(service.id, exists);
+        let mut model = service.into_active_model().reset_all();
+        model.id = Set(id);
+        HapServiceEntity::insert(model).exec_without_returning(txn).await?;
+        result.services += 1;
+    }
+
+    let mut cids = IdMap::default();
+    for char in archive.characteristics.into_iter() {
+        let exists = HapCharacteristicEntity::find_by_id(char.cid).one(txn).await?.is_some();
+        let service_id = service_ids.get(char.service_id)
+            .ok_or(anyhow!("特征:{}的服务不在备份中", char.cid))?;
+        let cid = cids.alloc(char.cid, exists);
+        let mut model = char.into_active_model().reset_all();
+        model.cid = Set(cid);
+        model.service_id = Set(service_id);
+        HapCharacteristicEntity::insert(model).exec_without_returning(txn).await?;
+        result.characteristics += 1;
+    }
+
+    let mut rule_ids = IdMap::default();
+    for mut rule in archive.rules.into_iter() {
+        let exists = RuleEntity::find_by_id(rule.rule_id).one(txn).await?.is_some();
+        let rule_id = rule_ids.alloc(rule.rule_id, exists);
+        remap_rule_devices(&mut rule, &device_ids);
+        let mut model = rule.into_active_model().reset_all();
+        model.rule_id = Set(rule_id);
+        RuleEntity::insert(model).exec_without_returning(txn).await?;
+        result.rules += 1;
+    }
+
+    let mut scene_ids = IdMap::default();
+    for mut scene in archive.scenes.into_iter() {
+        let exists = SceneEntity::find_by_id(scene.scene_id).one(txn).await?.is_some();
+        let scene_id = scene_ids.alloc(scene.scene_id, exists);
+        for item in scene.items.0.iter_mut() {
+            item.device_id = device_ids.get_or(item.device_id);
+        }
+        scene.bridge_id = scene.bridge_id.map(|id| bridge_ids.get_or(id));
+        let mut model = scene.into_active_model().reset_all();
+        model.scene_id = Set(scene_id);
+        SceneEntity::insert(model).exec_without_returning(txn).await?;
+        result.scenes += 1;
+    }
+
+    let mut log_ids = IdMap::default();
+    for log in archive.pairing_logs.into_iter() {
+        let exists = PairingLogEntity::find_by_id(log.id).one(txn).await?.is_some();
+        let id = log_ids.alloc(log.id, exists);
+        let bridge_id = bridge_ids.get_or(log.bridge_id);
+        let mut model = log.into_active_model().reset_all();
+        model.id = Set(id);
+        model.bridge_id = Set(bridge_id);
+        PairingLogEntity::insert(model).exec_without_returning(txn).await?;
+        result.pairing_logs += 1;
+    }
+    result.remapped = bridge_ids.remapped + device_ids.remapped + service_ids.remapped + cids.remapped
+        + rule_ids.remapped + scene_ids.remapped + log_ids.remapped;
+
+    for device in archive.miot_devices.into_iter() {
+        if MiotDeviceEntity::find_by_id(device.did.as_str()).one(txn).await?.is_some() {
+            result.skipped += 1;
+            continue;
+        }
+        MiotDeviceEntity::insert(device.into_active_model().reset_all())
+            .exec_without_returning(txn)
+            .await?;
+        result.miot_devices += 1;
+    }
+
+    for account in archive.mi_accounts.into_iter() {
+        if MiAccountEntity::find_by_id(account.account.as_str()).one(txn).await?.is_some() {
+            result.skipped += 1;
+            continue;
+        }
+        let model = MiAccountActiveModel {
+            account: Set(account.account),
+            password: Set(account.password),
+            status: Set(account.status),
+            memo: Set(account.memo),
+            update_at: Set(chrono::Utc::now()),
+            last_login_at: Set(None),
+        };
+        MiAccountEntity::insert(model).exec_without_returning(txn).await?;
+        result.mi_accounts += 1;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use crate::db::entity::prelude::RuleModel;
+    use crate::db::entity::rule::{RuleActionVec, RuleConditionVec, RuleTriggerVec};
+    use crate::rule::{PropertyRef, RuleAction, RuleTrigger};
+    use crate::service::backup_service::{check_template_path, IdMap, remap_rule_devices};
+
+    #[test]
+    fn test_remap_rule_devices() {
+        let mut device_ids = IdMap::default();
+        let new = device_ids.alloc(1, true);
+        let property = |device_id| PropertyRef { device_id, siid: Some(2), piid: Some(1), name: None };
+        let mut rule = RuleModel {
+            rule_id: 1,
+            name: "test".to_string(),
+            disabled: false,
+            triggers: RuleTriggerVec(vec![RuleTrigger::Property { property: property(1), op: None, value: None }]),
+            conditions: RuleConditionVec(vec![]),
+            actions: RuleActionVec(vec![
+                RuleAction::SetProperty { property: property(2), value: json!(true) },
+                RuleAction::CallAction { device_id: 1, siid: 2, aiid: 1, ins: vec![] },
+            ]),
+            remark: None,
+            create_at: chrono::Utc::now(),
+            update_at: chrono::Utc::now(),
+        };
+        remap_rule_devices(&mut rule, &device_ids);
+        assert_eq!(rule.triggers.0[0], RuleTrigger::Property { property: property(new), op: None, value: None });
+        // 不在备份中的设备保持原值
+        assert_eq!(rule.actions.0[0], RuleAction::SetProperty { property: property(2), value: json!(true) });
+        assert_eq!(rule.actions.0[1], RuleAction::CallAction { device_id: new, siid: 2, aiid: 1, ins: vec![] });
+    }
+
+    #[test]
+    fn test_id_map() {
+        let mut map = IdMap::default();
+        assert_eq!(map.alloc(1, false), 1);
+        let new = map.alloc(2, true);
+        assert_ne!(new, 2);
+        assert_eq!(map.get(2), Some(new));
+        assert_eq!(map.get(3), None);
+        assert_eq!(map.remapped, 1);
+    }
+
+    #[test]
+    fn test_template_path() {
+        assert!(check_template_path("mijia/yeelink/yeelink.light.lamp22.toml").is_ok());
+        assert!(check_template_path("mqtt/light.yaml").is_ok());
+        assert!(check_template_path("../config.toml").is_err());
+        assert!(check_template_path("/etc/passwd.toml").is_err());
+        assert!(check_template_path("mijia/readme.md").is_err());
+    }
+}
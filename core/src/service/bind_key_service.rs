@@ -0,0 +1,158 @@
+use anyhow::anyhow;
+use log::{info, warn};
+use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, JsonValue, QueryFilter};
+use sea_orm::ActiveValue::Set;
+
+use crate::db::entity::iot_device::DeviceParam;
+use crate::db::entity::prelude::{IotDeviceEntity, MiotDeviceActiveModel, MiotDeviceColumn, MiotDeviceEntity};
+use crate::init::manager::ble_manager::BleManager;
+use crate::init::manager::mi_account_manager::MiAccountManager;
+
+/// iot_device params 中配置的 (mac, bindkey)
+pub fn device_bind_key(params: &JsonValue) -> Option<(String, String)> {
+    match serde_json::from_value::<DeviceParam>(params.clone()) {
+        Ok(DeviceParam::BleParam(param)) => param.info.mac.zip(param.bindkey),
+        _ => None,
+    }
+}
+
+/// 解析 A4:C1:38:11:22:33 或 a4c138112233
+pub fn parse_mac(mac: &str) -> anyhow::Result<[u8; 6]> {
+    let str: String = mac.chars().filter(|c| c.is_ascii_hexdigit()).collect();
+    let bytes = hex::decode(str.as_str()).map_err(|_| anyhow!("mac:{}格式错误", mac))?;
+    bytes.try_into().map_err(|_| anyhow!("mac:{}长度错误", mac))
+}
+
+/// 加载所有 bindkey 到蓝牙管理器,iot_device 中配置的优先于米家云的 beacon_key
+pub async fn load_bind_keys(conn: &DatabaseConnection, ble_manager: &BleManager) -> anyhow::Result<usize> {
+    let mut count = 0;
+    let devices = MiotDeviceEntity::find()
+        .filter(MiotDeviceColumn::BeaconKey.is_not_null())
+        .all(conn)
+        .await?;
+    for dev in devices {
+        let (Some(mac), Some(key)) = (dev.mac.as_ref(), dev.beacon_key) else { continue };
+        match parse_mac(mac) {
+            Ok(mac) => {
+                ble_manager.set_aes_key(mac, key).await;
+                count += 1;
+            }
+            Err(e) => warn!("米家设备:{} {}", dev.did, e),
+        }
+    }
+    for dev in IotDeviceEntity::find().all(conn).await? {
+        let Some((mac, key)) = device_bind_key(&dev.params) else { continue };
+        match parse_mac(mac.as_str()) {
+            Ok(mac) => {
+                ble_manager.set_aes_key(mac, key).await;
+                count += 1;
+            }
+            Err(e) => warn!("设备:{} {}", dev.device_id, e),
+        }
+    }
+    info!("加载蓝牙bindkey:{}个", count);
+    Ok(count)
+}
+
+/// 从米家云获取 beaconkey,保存并加载到蓝牙管理器
+pub async fn fetch_beacon_key(conn: &DatabaseConnection,
+                              mi_account_manager: &MiAccountManager,
+                              ble_manager: &BleManager,
+                              did: &str) -> anyhow::Result<String> {
+    let dev = MiotDeviceEntity::find_by_id(did.to_string())
+        .one(conn)
+        .await?
+        .ok_or(anyhow!("米家设备did:{}不存在", did))?;
+    let mac = dev.mac.as_ref()
+        .ok_or(anyhow!("米家设备did:{}没有mac", did))
+        .and_then(|m| parse_mac(m))?;
+    let cloud = mi_account_manager.get_cloud(dev.user_id.as_str()).await?;
+    let key = cloud.read().await.get_beacon_key(did).await?;
+    let model = MiotDeviceActiveModel {
+        did: Set(did.to_string()),
+        beacon_key: Set(Some(key.clone())),
+        ..Default::default()
+    };
+    model.update(conn).await?;
+    reload_bind_key(conn, ble_manager, mac).await?;
+    Ok(key)
+}
+
+/// 按数据库重新加载 mac 的 bindkey, iot_device 中配置的优先, 都没有时移除
+pub async fn reload_bind_key(conn: &DatabaseConnection, ble_manager: &BleManager, mac: [u8; 6]) -> anyhow::Result<()> {
+    for dev in IotDeviceEntity::find().all(conn).await? {
+        let Some((dev_mac, key)) = device_bind_key(&dev.params) else { continue };
+        if parse_mac(dev_mac.as_str()).ok() == Some(mac) {
+            ble_manager.set_aes_key(mac, key).await;
+            return Ok(());
+        }
+    }
+    let devices = MiotDeviceEntity::find()
+        .filter(MiotDeviceColumn::BeaconKey.is_not_null())
+        .all(conn)
+        .await?;
+    for dev in devices {
+        let (Some(dev_mac), Some(key)) = (dev.mac.as_ref(), dev.beacon_key) else { continue };
+        if parse_mac(dev_mac).ok() == Some(mac) {
+            ble_manager.set_aes_key(mac, key).await;
+            return Ok(());
+        }
+    }
+    ble_manager.remove_aes_key(&mac).await;
+    Ok(())
+}
+
+/// 设备保存或删除后重新加载新旧 params 中的 bindkey
+pub async fn sync_device_bind_key(conn: &DatabaseConnection, ble_manager: &BleManager, params: &[&JsonValue]) {
+    let macs: Vec<String> = params.iter()
+        .filter_map(|p| device_bind_key(p))
+        .map(|(mac, _)| mac)
+        .collect();
+    reload_bind_keys(conn, ble_manager, macs.as_slice()).await;
+}
+
+/// 重新加载多个 mac 的 bindkey, 失败只记录日志
+pub async fn reload_bind_keys(conn: &DatabaseConnection, ble_manager: &BleManager, macs: &[String]) {
+    let mut loaded = vec![];
+    for mac in macs {
+        let mac = match parse_mac(mac.as_str()) {
+            Ok(mac) if !loaded.contains(&mac) => mac,
+            Ok(_) => continue,
+            Err(e) => {
+                warn!("bindkey {}", e);
+                continue;
+            }
+        };
+        loaded.push(mac);
+        if let Err(e) = reload_bind_key(conn, ble_manager, mac).await {
+            warn!("加载蓝牙bindkey失败:{:?}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use crate::service::bind_key_service::{device_bind_key, parse_mac};
+
+    #[test]
+    fn test_parse_mac() {
+        let mac = [0xa4, 0xc1, 0x38, 0x11, 0x22, 0x33];
+        assert_eq!(parse_mac("A4:C1:38:11:22:33").unwrap(), mac);
+        assert_eq!(parse_mac("a4c138112233").unwrap(), mac);
+        assert!(parse_mac("a4:c1:38").is_err());
+    }
+
+    #[test]
+    fn test_device_bind_key() {
+        let mut params = json!({"type":"BleParam","did":"blt.3.1","token":"","model":"miaomiaoce.sensor_ht.t1","name":"温湿度传感器",
+            "mac":"A4:C1:38:11:22:33","mapping":[]});
+        assert!(device_bind_key(&params).is_none());
+        params["bindkey"] = json!("814aac74c4f17b6c1581e1ab87816b99");
+        let (mac, key) = device_bind_key(&params).unwrap();
+        assert_eq!(mac, "A4:C1:38:11:22:33");
+        assert_eq!(key, "814aac74c4f17b6c1581e1ab87816b99");
+        assert!(device_bind_key(&json!({"type":"WifiDeviceParam"})).is_none());
+    }
+}
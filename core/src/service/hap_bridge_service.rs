@@ -1,12 +1,12 @@
 use anyhow::anyhow;
-use sea_orm::{ActiveEnum, EntityTrait};
+use sea_orm::EntityTrait;
 use hap::BonjourStatusFlag;
 use target_hap::hap_manager::HapManage;
 use crate::api::results::HapBridgeResult;
 use crate::api::state::AppState;
 use sea_orm::*;
 use crate::db::entity::prelude::{HapAccessoryColumn, HapAccessoryEntity, HapBridgeColumn, HapBridgeEntity, HapBridgeModel};
-use crate::hap::rand_utils::gen_homekit_setup_uri_default;
+use crate::hap::setup_code::SetupCode;
 
 
 pub async fn add_check(state: &AppState, name: &str, pin_code: i64) -> anyhow::Result<()> {
@@ -36,9 +36,7 @@ pub async fn to_model_result(state: &AppState, manager: &HapManage, model: HapBr
             s.read().await.iter().map(|i| i.clone()).collect()
         }
     };
-    let setup_uri = gen_homekit_setup_uri_default(
-        model.pin_code as u64, model.category.to_value() as u64,
-        model.setup_id.clone());
+    let setup_uri = SetupCode::from_bridge(&model).setup_uri;
     let running = config.is_some();
     let is_paired = model.status_flag.0 != BonjourStatusFlag::NotPaired;
     let accessory_count = HapAccessoryEntity::find()
@@ -0,0 +1,74 @@
+use lazy_static::lazy_static;
+use prometheus::{Encoder, IntCounterVec, IntGaugeVec, register_int_counter_vec, register_int_gauge_vec, TextEncoder};
+use sea_orm::EntityTrait;
+
+use crate::api::state::AppState;
+use crate::db::entity::prelude::HapBridgeEntity;
+
+lazy_static! {
+    /// 设备运行次数,断开重连后加1
+    pub static ref DEVICE_RUNS: IntCounterVec = register_int_counter_vec!(
+        "homelink_device_runs_total",
+        "设备运行次数",
+        &["device_id", "device_type"]
+    ).unwrap();
+    /// 设备当前连续重试次数
+    static ref DEVICE_RETRIES: IntGaugeVec = register_int_gauge_vec!(
+        "homelink_device_retry_count",
+        "设备当前连续重试次数",
+        &["device_id", "device_type"]
+    ).unwrap();
+    /// 收到的蓝牙广播
+    pub static ref BLE_ADVERTISEMENTS: IntCounterVec = register_int_counter_vec!(
+        "homelink_ble_advertisements_total",
+        "收到的蓝牙广播数量",
+        &["result"]
+    ).unwrap();
+    /// 桥接器已配对的控制器
+    static ref HAP_PAIRED_CONTROLLERS: IntGaugeVec = register_int_gauge_vec!(
+        "homelink_hap_paired_controllers",
+        "桥接器已配对的控制器数量",
+        &["bridge_id", "name"]
+    ).unwrap();
+    /// 桥接器当前连接
+    static ref HAP_ACTIVE_SESSIONS: IntGaugeVec = register_int_gauge_vec!(
+        "homelink_hap_active_sessions",
+        "桥接器当前连接数量",
+        &["bridge_id", "name"]
+    ).unwrap();
+}
+
+/// 采集时刷新设备和桥接器状态
+async fn collect(state: &AppState) -> anyhow::Result<()> {
+    DEVICE_RETRIES.reset();
+    for (id, dev) in state.device_manager.device_list() {
+        let count = dev.retry_info().count().await;
+        DEVICE_RETRIES.with_label_values(&[id.to_string().as_str(), dev.device_type()])
+            .set(count as i64);
+    }
+
+    HAP_PAIRED_CONTROLLERS.reset();
+    HAP_ACTIVE_SESSIONS.reset();
+    let bridges = HapBridgeEntity::find().all(state.conn()).await?;
+    for bridge in bridges {
+        // 只统计运行中的桥接器
+        let peers = match state.hap_manager.get_bridge_server_peer(bridge.bridge_id) {
+            None => continue,
+            Some(p) => p,
+        };
+        let sessions = peers.read().await.iter().count();
+        let bid = bridge.bridge_id.to_string();
+        let labels = [bid.as_str(), bridge.name.as_str()];
+        HAP_PAIRED_CONTROLLERS.with_label_values(&labels).set(bridge.pairings.0.len() as i64);
+        HAP_ACTIVE_SESSIONS.with_label_values(&labels).set(sessions as i64);
+    }
+    Ok(())
+}
+
+/// prometheus 文本格式的指标
+pub async fn gather(state: &AppState) -> anyhow::Result<String> {
+    collect(state).await?;
+    let mut buffer = vec![];
+    TextEncoder::new().encode(&prometheus::gather(), &mut buffer)?;
+    Ok(String::from_utf8(buffer)?)
+}
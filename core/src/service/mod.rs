@@ -1,4 +1,9 @@
 pub mod hap_service_service;
 mod hap_meta_service;
 pub mod hap_bridge_service;
-
+pub mod sys_user_service;
+pub mod metrics_service;
+pub mod scene_service;
+pub mod backup_service;
+pub mod pairing_service;
+pub mod bind_key_service;
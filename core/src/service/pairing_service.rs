@@ -0,0 +1,112 @@
+use std::iter::repeat;
+
+use anyhow::anyhow;
+use crypto::digest::Digest;
+use crypto::sha2::Sha256;
+use sea_orm::{ActiveModelBehavior, ActiveModelTrait, ColumnTrait, ConnectionTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder};
+use sea_orm::ActiveValue::Set;
+use serde::Serialize;
+
+use hap::pairing::{Pairing, Permissions};
+use hap::storage::Storage;
+use target_hap::hap_manager::{HapManage, HapSession};
+
+use crate::db::entity::pairing_log::PairingAction;
+use crate::db::entity::prelude::{PairingLogActiveModel, PairingLogColumn, PairingLogEntity, PairingLogModel};
+use crate::db::SNOWFLAKE;
+use crate::hap::db_bridge_storage::DbBridgesStorage;
+
+/// 已配对的控制器
+#[derive(Debug, Clone, Serialize)]
+pub struct PairingInfo {
+    pub id: uuid::Uuid,
+    pub admin: bool,
+    /// 公钥指纹
+    pub fingerprint: String,
+}
+
+impl From<&Pairing> for PairingInfo {
+    fn from(pairing: &Pairing) -> Self {
+        Self {
+            id: pairing.id,
+            admin: is_admin(pairing),
+            fingerprint: fingerprint(&pairing.public_key),
+        }
+    }
+}
+
+pub fn is_admin(pairing: &Pairing) -> bool {
+    pairing.permissions == Permissions::Admin
+}
+
+/// 公钥 sha256 的前8字节, 如 AB:CD:...
+pub fn fingerprint(public_key: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.input(public_key);
+    let mut buf: Vec<u8> = repeat(0).take(hasher.output_bytes()).collect();
+    hasher.result(buf.as_mut());
+    buf[..8].iter()
+        .map(|b| format!("{:02X}", b))
+        .collect::<Vec<String>>()
+        .join(":")
+}
+
+/// 记录配对变更
+pub async fn add_log<C: ConnectionTrait>(conn: &C, bid: i64, pairing: Option<&Pairing>,
+                                         action: PairingAction, operator: Option<String>) -> anyhow::Result<()> {
+    let info = pairing.map(PairingInfo::from);
+    let model = PairingLogActiveModel {
+        id: Set(SNOWFLAKE.next_id()),
+        bridge_id: Set(bid),
+        pairing_id: Set(info.as_ref().map(|i| i.id.to_string())),
+        action: Set(action),
+        admin: Set(info.as_ref().map(|i| i.admin)),
+        fingerprint: Set(info.map(|i| i.fingerprint)),
+        operator: Set(operator),
+        ..PairingLogActiveModel::new()
+    };
+    model.insert(conn).await?;
+    Ok(())
+}
+
+pub async fn list_logs(conn: &DatabaseConnection, bid: i64) -> anyhow::Result<Vec<PairingLogModel>> {
+    let list = PairingLogEntity::find()
+        .filter(PairingLogColumn::BridgeId.eq(bid))
+        .order_by_desc(PairingLogColumn::CreateAt)
+        .all(conn)
+        .await?;
+    Ok(list)
+}
+
+fn storage(conn: &DatabaseConnection, bid: i64) -> DbBridgesStorage {
+    DbBridgesStorage::new(bid, conn.clone())
+}
+
+pub async fn list_pairings(conn: &DatabaseConnection, bid: i64) -> anyhow::Result<Vec<PairingInfo>> {
+    let pairings = storage(conn, bid)
+        .list_pairings()
+        .await
+        .map_err(|e| anyhow!("读取配对失败:{:?}", e))?;
+    Ok(pairings.iter().map(PairingInfo::from).collect())
+}
+
+/// 撤销单个控制器, 返回是否已清空所有配对
+pub async fn revoke_pairing(conn: &DatabaseConnection, bid: i64, id: uuid::Uuid, operator: String) -> anyhow::Result<bool> {
+    storage(conn, bid).revoke_pairing(&id, operator).await
+}
+
+/// 当前连接的控制器会话
+pub async fn sessions(manage: &HapManage, bid: i64) -> Vec<HapSession> {
+    manage.get_bridge_sessions(bid).await
+}
+
+#[cfg(test)]
+mod test {
+    use crate::service::pairing_service::fingerprint;
+
+    #[test]
+    fn test_fingerprint() {
+        let fp = fingerprint(&[0u8; 32]);
+        assert_eq!(fp, "66:68:7A:AD:F8:62:BD:77");
+    }
+}
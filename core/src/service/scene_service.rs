@@ -0,0 +1,384 @@
+use std::collections::BTreeMap;
+
+use anyhow::anyhow;
+use futures_util::future::join_all;
+use log::info;
+use sea_orm::{ActiveModelTrait, ColumnTrait, ConnectionTrait, DatabaseConnection, EntityTrait, QueryFilter, TransactionTrait};
+use sea_orm::ActiveValue::Set;
+use serde::{Deserialize, Serialize};
+use serde_aux::prelude::deserialize_number_from_string;
+use serde_json::{json, Value};
+
+use hap::HapType;
+use miot_proto::device::miot_spec_device::{AsMiotDevice, MiotDeviceArc, MiotSpecDevice};
+use miot_proto::proto::miio_proto::MiotSpecId;
+use target_hap::hap_manager::HapManage;
+use target_hap::hap_type_wrapper::HapTypeWrapper;
+use target_hap::types::{CharIdentifier, ModelDelegateParam};
+
+use crate::db::entity::hap_accessory::ModelDelegateParamVec;
+use crate::db::entity::hap_bridge::BridgeCategory;
+use crate::db::entity::hap_characteristic::HapCharInfoQueryResult;
+use crate::db::entity::iot_device::DeviceType;
+use crate::db::entity::prelude::{HapAccessoryActiveModel, HapAccessoryEntity, HapAccessoryModel, HapBridgeEntity, HapCharacteristicActiveModel, HapCharacteristicColumn, HapCharacteristicEntity, HapServiceActiveModel, HapServiceColumn, HapServiceEntity, IotDeviceActiveModel, IotDeviceEntity, SceneActiveModel, SceneEntity, SceneModel};
+use crate::db::entity::scene::SceneItem;
+use crate::db::SNOWFLAKE;
+use crate::hap::scene_switch::{SCENE_SWITCH_MODEL, SCENE_SWITCH_STAG};
+use crate::init::hap_init::reload_accessory;
+use crate::init::manager::device_manager::IotDeviceManager;
+use crate::init::manager::mqtt_export_manager::MqttExportManager;
+
+/// 场景中的设备属性,用于从设备当前状态生成场景
+#[derive(Debug, Clone, Deserialize)]
+pub struct SceneProperty {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub device_id: i64,
+    pub siid: i32,
+    pub piid: i32,
+}
+
+/// 设置失败的属性
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ScenePropertyFailure {
+    pub siid: i32,
+    pub piid: i32,
+    /// 设备返回的错误码
+    pub code: i32,
+}
+
+/// 单个设备的执行结果
+#[derive(Debug, Clone, Serialize)]
+pub struct SceneApplyResult {
+    pub device_id: i64,
+    /// 写入成功的属性数量
+    pub count: usize,
+    /// 设备返回失败的属性
+    pub failed: Vec<ScenePropertyFailure>,
+    pub error: Option<String>,
+}
+
+/// 写入单个设备的属性,按返回的 code 区分每个属性是否成功
+async fn apply_device(dev: &dyn MiotSpecDevice, device_id: i64, props: Vec<(MiotSpecId, Value)>) -> SceneApplyResult {
+    match dev.try_set_properties(props).await {
+        Ok((success, failed)) => {
+            let failed: Vec<ScenePropertyFailure> = failed.into_iter()
+                .map(|(id, code)| ScenePropertyFailure { siid: id.siid, piid: id.piid, code })
+                .collect();
+            let error = (!failed.is_empty()).then(|| format!("{}个属性设置失败", failed.len()));
+            SceneApplyResult { device_id, count: success.len(), failed, error }
+        }
+        Err(e) => SceneApplyResult { device_id, count: 0, failed: vec![], error: Some(e.to_string()) },
+    }
+}
+
+/// 按设备分组,保持场景中的顺序
+fn group_by_device<T>(items: impl IntoIterator<Item=(i64, T)>) -> BTreeMap<i64, Vec<T>> {
+    let mut map: BTreeMap<i64, Vec<T>> = BTreeMap::new();
+    for (device_id, item) in items {
+        map.entry(device_id).or_default().push(item);
+    }
+    map
+}
+
+/// 执行场景,不同设备并行写入,同一设备的属性合并成一次 set_properties
+pub async fn apply_scene(device_manager: &IotDeviceManager, items: &[SceneItem]) -> Vec<SceneApplyResult> {
+    let groups = group_by_device(items.iter()
+        .map(|i| (i.device_id, (MiotSpecId::new(i.siid, i.piid), i.value.clone()))));
+    let tasks = groups.into_iter().map(|(device_id, props)| async move {
+        let dev = match device_manager.get_device(device_id) {
+            Some(dev) => MiotDeviceArc(dev),
+            None => {
+                let error = Some(format!("设备:{}未运行", device_id));
+                return SceneApplyResult { device_id, count: 0, failed: vec![], error };
+            }
+        };
+        match dev.as_miot_device() {
+            Ok(dev) => apply_device(dev, device_id, props).await,
+            Err(e) => SceneApplyResult { device_id, count: 0, failed: vec![], error: Some(e.to_string()) },
+        }
+    });
+    join_all(tasks).await
+}
+
+pub async fn apply_scene_by_id(conn: &DatabaseConnection, device_manager: &IotDeviceManager, scene_id: i64) -> anyhow::Result<Vec<SceneApplyResult>> {
+    let scene = SceneEntity::find_by_id(scene_id)
+        .one(conn)
+        .await?
+        .ok_or(anyhow!("场景:{}不存在", scene_id))?;
+    let results = apply_scene(device_manager, &scene.items.0).await;
+    info!("执行场景:{},结果:{:?}", scene.name, results);
+    Ok(results)
+}
+
+/// 读取设备当前值生成场景
+pub async fn capture(device_manager: &IotDeviceManager, properties: &[SceneProperty]) -> anyhow::Result<Vec<SceneItem>> {
+    let groups = group_by_device(properties.iter()
+        .map(|p| (p.device_id, MiotSpecId::new(p.siid, p.piid))));
+    let mut items = vec![];
+    for (device_id, ids) in groups {
+        let dev = device_manager.get_device(device_id)
+            .ok_or(anyhow!("设备:{}未运行", device_id))?;
+        let results = MiotDeviceArc(dev).read_properties(ids).await?;
+        for result in results {
+            let value = result.value
+                .ok_or(anyhow!("设备:{}属性{}.{}无返回值", device_id, result.siid, result.piid))?;
+            items.push(SceneItem { device_id, siid: result.siid, piid: result.piid, value });
+        }
+    }
+    Ok(items)
+}
+
+/// 同步场景开关配件,设置了 bridge_id 时创建,清除时删除
+/// 配件在运行中的桥接器上直接添加,替换或移除
+pub async fn sync_accessory(conn: &DatabaseConnection,
+                            hap_manager: &HapManage,
+                            device_manager: &IotDeviceManager,
+                            mqtt_export_manager: &MqttExportManager,
+                            scene: SceneModel) -> anyhow::Result<()> {
+    let accessory = match scene.aid {
+        None => None,
+        Some(aid) => HapAccessoryEntity::find_by_id(aid).one(conn).await?,
+    };
+    let aid = match (scene.bridge_id, accessory) {
+        (None, None) => None,
+        (None, Some(accessory)) => {
+            remove_accessory(conn, hap_manager, device_manager, mqtt_export_manager, accessory).await?;
+            None
+        }
+        (Some(bridge_id), None) => {
+            HapBridgeEntity::find_by_id(bridge_id)
+                .one(conn)
+                .await?
+                .ok_or(anyhow!("桥接器不存在"))?;
+            Some(create_accessory(conn, hap_manager, device_manager, &scene, bridge_id).await?)
+        }
+        (Some(bridge_id), Some(accessory)) => {
+            let aid = accessory.aid;
+            HapAccessoryActiveModel {
+                aid: Set(aid),
+                name: Set(scene.name.clone()),
+                bridge_id: Set(bridge_id),
+                update_at: Set(chrono::Local::now().naive_local()),
+                ..Default::default()
+            }.update(conn).await?;
+            Some(aid)
+        }
+    };
+    if aid != scene.aid {
+        SceneActiveModel {
+            scene_id: Set(scene.scene_id),
+            aid: Set(aid),
+            ..Default::default()
+        }.update(conn).await?;
+    }
+    if let Some(aid) = aid {
+        reload_accessory(conn, hap_manager.clone(), device_manager.clone(), aid).await?;
+        mqtt_export_manager.sync_accessory(aid).await;
+    }
+    Ok(())
+}
+
+/// 创建虚拟设备和开关配件
+async fn create_accessory(conn: &DatabaseConnection, hap_manager: &HapManage, device_manager: &IotDeviceManager, scene: &SceneModel, bridge_id: i64) -> anyhow::Result<i64> {
+    let info = hap_manager.get_hap_default_info(HapType::PowerState)
+        .ok_or(anyhow!("PowerState 默认信息不存在"))?;
+    let device_id = SNOWFLAKE.next_id();
+    let aid = SNOWFLAKE.next_id();
+    let sid = SNOWFLAKE.next_id();
+    let txn = conn.begin().await?;
+    IotDeviceActiveModel {
+        device_id: Set(device_id),
+        tag: Set(None),
+        integration: Set("hl-virtual".to_string()),
+        params: Set(json!({})),
+        gateway_id: Set(None),
+        name: Set(scene.name.clone()),
+        memo: Set(Some("场景开关".to_string())),
+        disabled: Set(false),
+        device_type: Set(DeviceType::Normal),
+        source_platform: Set("hl-virtual".to_string()),
+        source_id: Set(Some(scene.scene_id.to_string())),
+        temp_id: Set(None),
+        temp_version: Set(None),
+        temp_batch_id: Set(None),
+        update_at: Set(chrono::Utc::now()),
+    }.insert(&txn).await?;
+    let delegate = ModelDelegateParam {
+        chars: vec![CharIdentifier::new(SCENE_SWITCH_STAG.to_string(), HapTypeWrapper::PowerState)],
+        model: SCENE_SWITCH_MODEL.to_string(),
+        params: Some(json!({"scene_id": scene.scene_id})),
+        timeout: None,
+    };
+    HapAccessoryActiveModel {
+        aid: Set(aid),
+        name: Set(scene.name.clone()),
+        tag: Set(Some(SCENE_SWITCH_STAG.to_string())),
+        device_id: Set(device_id),
+        bridge_id: Set(bridge_id),
+        disabled: Set(false),
+        category: Set(BridgeCategory::Switch),
+        hap_model_delegates: Set(ModelDelegateParamVec(vec![delegate])),
+        memo: Set(Some("场景开关".to_string())),
+        info: Set(None),
+        temp_id: Set(None),
+        create_at: Set(chrono::Local::now().naive_local()),
+        update_at: Set(chrono::Local::now().naive_local()),
+    }.insert(&txn).await?;
+    HapServiceActiveModel {
+        id: Set(sid),
+        tag: Set(Some(SCENE_SWITCH_STAG.to_string())),
+        accessory_id: Set(aid),
+        configured_name: Set(Some(scene.name.clone())),
+        memo: Set(None),
+        service_type: Set(HapTypeWrapper::Switch.to_string()),
+        disabled: Set(false),
+        primary: Set(true),
+    }.insert(&txn).await?;
+    HapCharacteristicActiveModel {
+        cid: Set(SNOWFLAKE.next_id()),
+        service_id: Set(sid),
+        disabled: Set(false),
+        name: Set(Some("on".to_string())),
+        characteristic_type: Set(HapTypeWrapper::PowerState.to_string()),
+        convertor: Set(None),
+        convertor_param: Set(None),
+        info: Set(HapCharInfoQueryResult(info)),
+        memo: Set(None),
+    }.insert(&txn).await?;
+    txn.commit().await?;
+    device_manager.start_devices(Some(vec![device_id])).await?;
+    Ok(aid)
+}
+
+/// 删除开关配件和虚拟设备,先从运行中的桥接器移除配件
+pub async fn remove_accessory(conn: &DatabaseConnection,
+                              hap_manager: &HapManage,
+                              device_manager: &IotDeviceManager,
+                              mqtt_export_manager: &MqttExportManager,
+                              accessory: HapAccessoryModel) -> anyhow::Result<()> {
+    hap_manager.remove_accessory(accessory.aid as u64).await?;
+    mqtt_export_manager.remove_accessory(accessory.aid).await;
+    let txn = conn.begin().await?;
+    delete_accessory_rows(&txn, accessory.aid).await?;
+    IotDeviceEntity::delete_by_id(accessory.device_id).exec(&txn).await?;
+    txn.commit().await?;
+    device_manager.remove_device(accessory.device_id).await?;
+    Ok(())
+}
+
+async fn delete_accessory_rows<C: ConnectionTrait>(conn: &C, aid: i64) -> anyhow::Result<()> {
+    let svc_ids: Vec<i64> = HapServiceEntity::find()
+        .filter(HapServiceColumn::AccessoryId.eq(aid))
+        .all(conn)
+        .await?
+        .into_iter()
+        .map(|s| s.id)
+        .collect();
+    HapCharacteristicEntity::delete_many()
+        .filter(HapCharacteristicColumn::ServiceId.is_in(svc_ids.clone()))
+        .exec(conn)
+        .await?;
+    HapServiceEntity::delete_many()
+        .filter(HapServiceColumn::Id.is_in(svc_ids))
+        .exec(conn)
+        .await?;
+    HapAccessoryEntity::delete_by_id(aid).exec(conn).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use serde_json::json;
+    use tokio::sync::broadcast;
+
+    use miot_proto::device::miot_spec_device::{BaseMiotSpecDevice, DeviceInfo, MiotSpecDevice};
+    use miot_proto::proto::miio_proto::{MiotSetResult, MiotSpecDTO, MiotSpecId, MiotSpecProtocol, MiotSpecProtocolPointer};
+    use miot_proto::proto::protocol::{ExitError, JsonMessage};
+
+    use crate::service::scene_service::{apply_device, group_by_device, ScenePropertyFailure};
+
+    /// piid 为 2 的属性返回 -4004
+    struct MockProto;
+
+    #[async_trait::async_trait]
+    impl MiotSpecProtocol for MockProto {
+        fn incr_cmd_id(&self) -> u64 {
+            0
+        }
+
+        async fn request<'a>(&'a self, _id: u64, _cmd: &'a str, _timeout_val: Option<Duration>) -> anyhow::Result<JsonMessage> {
+            unimplemented!()
+        }
+
+        async fn send<'a>(&'a self, _cmd: &'a str) -> anyhow::Result<()> {
+            unimplemented!()
+        }
+
+        fn recv(&self) -> broadcast::Receiver<JsonMessage> {
+            unimplemented!()
+        }
+
+        async fn await_result<'a>(&'a self, _id: u64, _timeout_val: Option<Duration>) -> anyhow::Result<JsonMessage> {
+            unimplemented!()
+        }
+
+        async fn start_listen(&self) {}
+
+        async fn set_properties(&self, params: Vec<MiotSpecDTO>, _timeout_val: Option<Duration>) -> anyhow::Result<Vec<MiotSetResult>> {
+            Ok(params.into_iter()
+                .map(|p| MiotSetResult { did: p.did, siid: p.siid, piid: p.piid, code: if p.piid == 2 { -4004 } else { 0 } })
+                .collect())
+        }
+    }
+
+    struct MockDevice {
+        info: DeviceInfo,
+        base: BaseMiotSpecDevice,
+    }
+
+    #[async_trait::async_trait]
+    impl MiotSpecDevice for MockDevice {
+        fn get_info(&self) -> &DeviceInfo {
+            &self.info
+        }
+
+        fn get_base(&self) -> &BaseMiotSpecDevice {
+            &self.base
+        }
+
+        async fn get_proto(&self) -> Result<MiotSpecProtocolPointer, ExitError> {
+            Ok(Arc::new(MockProto))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_apply_device() {
+        let info: DeviceInfo = serde_json::from_value(json!({
+            "did": "1", "token": "", "model": "mock", "name": "mock"
+        })).unwrap();
+        let dev = MockDevice { info, base: BaseMiotSpecDevice::default() };
+        let props = vec![
+            (MiotSpecId::new(2, 1), json!(true)),
+            (MiotSpecId::new(2, 2), json!(50)),
+            (MiotSpecId::new(2, 3), json!(4000)),
+        ];
+        let result = apply_device(&dev, 1, props).await;
+        assert_eq!(result.count, 2);
+        assert_eq!(result.failed, vec![ScenePropertyFailure { siid: 2, piid: 2, code: -4004 }]);
+        assert!(result.error.is_some());
+        // 失败的属性不写入影子
+        assert!(dev.base.shadow.get(&MiotSpecId::new(2, 2)).await.is_none());
+        assert!(dev.base.shadow.get(&MiotSpecId::new(2, 1)).await.is_some());
+    }
+
+    #[test]
+    fn test_group_by_device() {
+        let groups = group_by_device(vec![(2, "a"), (1, "b"), (2, "c")]);
+        assert_eq!(groups.get(&2), Some(&vec!["a", "c"]));
+        assert_eq!(groups.get(&1), Some(&vec!["b"]));
+        assert_eq!(groups.keys().collect::<Vec<_>>(), vec![&1, &2]);
+    }
+}
@@ -0,0 +1,158 @@
+use std::iter::repeat;
+
+use anyhow::anyhow;
+use crypto::digest::Digest;
+use crypto::pbkdf2::{pbkdf2_check, pbkdf2_simple};
+use crypto::sha2::Sha256;
+use log::{info, warn};
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use sea_orm::{ActiveModelBehavior, ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, PaginatorTrait, QueryFilter};
+use sea_orm::ActiveValue::Set;
+use crate::api::auth::Claims;
+use crate::config::cfgs::Auth;
+use crate::db::entity::prelude::{SysUserActiveModel, SysUserColumn, SysUserEntity, SysUserModel};
+use crate::db::SNOWFLAKE;
+
+/// pbkdf2 迭代次数
+const PBKDF2_ROUNDS: u32 = 10000;
+const DEFAULT_ADMIN: &str = "admin";
+
+pub fn hash_password(password: &str) -> anyhow::Result<String> {
+    pbkdf2_simple(password, PBKDF2_ROUNDS)
+        .map_err(|e| anyhow!("密码加密失败:{}", e))
+}
+
+pub fn verify_password(password: &str, hashed: &str) -> bool {
+    pbkdf2_check(password, hashed).unwrap_or(false)
+}
+
+/// token 中的密码版本, 密码hash 的 sha256 前8字节
+pub fn token_version(user: &SysUserModel) -> String {
+    let mut hasher = Sha256::new();
+    hasher.input_str(user.password.as_str());
+    let mut buf: Vec<u8> = repeat(0).take(hasher.output_bytes()).collect();
+    hasher.result(buf.as_mut());
+    hex::encode(&buf[..8])
+}
+
+/// token 对应的用户存在,未禁用,且签发后未修改密码
+pub async fn check_token_user(conn: &DatabaseConnection, claims: &Claims) -> anyhow::Result<SysUserModel> {
+    let user = SysUserEntity::find_by_id(claims.sub)
+        .one(conn)
+        .await?
+        .ok_or(anyhow!("用户不存在"))?;
+    if user.disabled {
+        return Err(anyhow!("用户已被禁用"));
+    }
+    if token_version(&user) != claims.ver {
+        return Err(anyhow!("密码已修改"));
+    }
+    Ok(user)
+}
+
+pub fn random_string(len: usize) -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(len)
+        .collect()
+}
+
+/// 用户表为空时创建默认管理员
+pub async fn init_admin_user(conn: &DatabaseConnection, auth: &Auth) -> anyhow::Result<()> {
+    let count = SysUserEntity::find().count(conn).await?;
+    if count > 0 {
+        return Ok(());
+    }
+    let password = match auth.admin_password() {
+        Some(p) => p,
+        None => {
+            let p = random_string(12);
+            warn!("未配置管理员密码,已随机生成,用户名:{},密码:{},请登入后修改", DEFAULT_ADMIN, p);
+            p
+        }
+    };
+    let model = SysUserActiveModel {
+        user_id: Set(SNOWFLAKE.next_id()),
+        username: Set(DEFAULT_ADMIN.to_string()),
+        password: Set(hash_password(password.as_str())?),
+        name: Set("Home link".to_string()),
+        roles: Set("admin".to_string()),
+        ..SysUserActiveModel::new()
+    };
+    model.insert(conn).await?;
+    info!("创建默认管理员:{}", DEFAULT_ADMIN);
+    Ok(())
+}
+
+/// 校验用户名密码
+pub async fn check_login(conn: &DatabaseConnection, username: &str, password: &str) -> anyhow::Result<SysUserModel> {
+    let user = SysUserEntity::find()
+        .filter(SysUserColumn::Username.eq(username))
+        .one(conn)
+        .await?
+        .ok_or(anyhow!("用户名或密码错误"))?;
+    if !verify_password(password, user.password.as_str()) {
+        return Err(anyhow!("用户名或密码错误"));
+    }
+    if user.disabled {
+        return Err(anyhow!("用户已被禁用"));
+    }
+    let mut model: SysUserActiveModel = user.clone().into();
+    model.last_login_at = Set(Some(chrono::Utc::now()));
+    model.update(conn).await?;
+    Ok(user)
+}
+
+pub async fn change_password(conn: &DatabaseConnection, user_id: i64, old_password: &str, new_password: &str) -> anyhow::Result<()> {
+    let user = SysUserEntity::find_by_id(user_id)
+        .one(conn)
+        .await?
+        .ok_or(anyhow!("用户不存在"))?;
+    if !verify_password(old_password, user.password.as_str()) {
+        return Err(anyhow!("原密码错误"));
+    }
+    if new_password.len() < 6 {
+        return Err(anyhow!("密码长度不能小于6位"));
+    }
+    let mut model: SysUserActiveModel = user.into();
+    model.password = Set(hash_password(new_password)?);
+    model.update_at = Set(chrono::Utc::now());
+    model.update(conn).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::db::entity::prelude::SysUserModel;
+    use crate::service::sys_user_service::{hash_password, token_version, verify_password};
+
+    #[test]
+    fn test_hash_password() {
+        let hashed = hash_password("admin123").unwrap();
+        assert!(verify_password("admin123", hashed.as_str()));
+        assert!(!verify_password("admin1234", hashed.as_str()));
+    }
+
+    #[test]
+    fn test_token_version() {
+        let now = chrono::Utc::now();
+        let mut user = SysUserModel {
+            user_id: 1,
+            username: "admin".to_string(),
+            password: hash_password("admin123").unwrap(),
+            name: "admin".to_string(),
+            avatar: None,
+            roles: "admin".to_string(),
+            disabled: false,
+            last_login_at: None,
+            create_at: now,
+            update_at: now,
+        };
+        let ver = token_version(&user);
+        assert_eq!(ver, token_version(&user));
+        // 修改密码后版本变化
+        user.password = hash_password("admin123").unwrap();
+        assert_ne!(ver, token_version(&user));
+    }
+}
@@ -3,11 +3,14 @@ pub mod task;
 
 use std::sync::Arc;
 use axum::handler::Handler;
-use log::info;
+use log::{info, warn};
 use socketioxide::extract::{Data, State, SocketRef};
 use socketioxide::layer::SocketIoLayer;
 use serde_json::Value;
 use socketioxide::socket::DisconnectReason;
+use socketioxide::SocketIo;
+use tokio::sync::broadcast::error::RecvError;
+use crate::api::auth::verify_token;
 use crate::api::state::AppState;
 use crate::socketio::context::{SocketContext, SocketContextInner};
 use crate::socketio::task::native_blt_log_task;
@@ -16,8 +19,17 @@ use crate::socketio::task::native_blt_log_task::NativeBltLogTask;
 
 async fn on_connect(socket: SocketRef, Data(data): Data<Value>, ctx: State<SocketContext>) {
     info!("socket connected: {}", socket.id);
-    info!("data: {:?}", data);
-    info!("data: {:?}", data);
+    // 与http 接口使用同一个token, 客户端通过 auth: {token} 传入
+    let token = data.get("token").and_then(|t| t.as_str());
+    let claims = match token {
+        Some(token) => verify_token(&ctx.app, token).await,
+        None => None,
+    };
+    if claims.is_none() {
+        warn!("socket 未登入,断开连接: {}", socket.id);
+        let _ = socket.disconnect();
+        return;
+    }
 
     socket.on("native_blt/unsub_log", |socket: SocketRef, context: State<SocketContext>| {
         context.remove_task(socket.id, native_blt_log_task::NAME);
@@ -44,15 +56,50 @@ async fn on_connect(socket: SocketRef, Data(data): Data<Value>, ctx: State<Socke
 
 }
 
+/// 广播设备健康状态变化
+fn broadcast_device_health(app: AppState, io: SocketIo) {
+    let mut rx = app.device_manager.health.subscribe();
+    tokio::spawn(async move {
+        loop {
+            match rx.recv().await {
+                Ok(event) => {
+                    if let Err(e) = io.emit("iot_device/health", event) {
+                        warn!("广播设备健康状态失败: {:?}", e);
+                    }
+                }
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => break,
+            }
+        }
+    });
+}
+
+/// 推送模板文件变化和解析错误
+fn broadcast_template_events(app: AppState, io: SocketIo) {
+    let mut rx = app.template_manager.subscribe_events();
+    tokio::spawn(async move {
+        loop {
+            match rx.recv().await {
+                Ok(event) => {
+                    if let Err(e) = io.emit("template/event", event) {
+                        warn!("推送模板事件失败: {:?}", e);
+                    }
+                }
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => break,
+            }
+        }
+    });
+}
+
 pub fn socket_io_layer(app: AppState) -> SocketIoLayer {
     let (layer, io) = socketioxide::SocketIo::builder()
-        .with_state(Arc::new(SocketContextInner::new(app)))
+        .with_state(Arc::new(SocketContextInner::new(app.clone())))
         .build_layer();
 
-
-    let (layer, io) = socketioxide::SocketIo::new_layer();
-
     io.ns("/", on_connect);
+    broadcast_device_health(app.clone(), io.clone());
+    broadcast_template_events(app, io);
 
     // ServiceBuilder::new()
     //     .layer(CorsLayer::permissive()) // Enable CORS policy
@@ -1,9 +1,346 @@
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+
+use anyhow::anyhow;
+use serde::Serialize;
+use serde_json::Value;
+
+use hap::characteristic::{Format, Perm};
+use hap::HapType;
+use hap_metadata::metadata::{HapCharacteristic, HapMetadata};
+use target_hap::delegate::database::get_hap_model_ext_database;
+use target_hap::delegate::model::check_delegate_chars;
+use target_hap::types::{CharIdentifier, ModelDelegateParam};
+
+use crate::template::hap::accessory::AccessoryTemplate;
+use crate::template::hap::chars::HapCharacteristicTemplate;
+use crate::template::hap::service::ServiceTemplate;
 use crate::template::hl_template::HlDeviceTemplate;
 
-pub fn check_template(temp: &HlDeviceTemplate) -> anyhow::Result<()> {
-    //检测tag 是否重复
-    for x in &temp.devices {
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiagnosticLevel {
+    Error,
+    Warning,
+}
+
+/// 模板检查结果, path 如 devices[0].accessories[0].services[1].chars[2].info.min_value
+#[derive(Debug, Clone, Serialize)]
+pub struct TemplateDiagnostic {
+    pub level: DiagnosticLevel,
+    pub path: String,
+    pub message: String,
+}
+
+/// 元数据中的特征
+struct CharMeta<'a> {
+    meta: &'a HapCharacteristic,
+    format: Option<Format>,
+    perms: Option<Vec<Perm>>,
+}
+
+/// 元数据中服务的特征
+struct ServiceMeta {
+    name: String,
+    required: Vec<HapType>,
+    optional: Vec<HapType>,
+}
+
+fn short_uuid_type(short_uuid: &str) -> Option<HapType> {
+    HapType::from_str(short_uuid.trim_start_matches('0')).ok()
+}
+
+fn parse_str<T: serde::de::DeserializeOwned>(s: &str) -> Option<T> {
+    serde_json::from_value(Value::String(s.to_string())).ok()
+}
+
+/// 根据 hap 元数据和模型扩展检查模板
+pub struct TemplateChecker<'a> {
+    chars: HashMap<HapType, CharMeta<'a>>,
+    services: HashMap<HapType, ServiceMeta>,
+    /// 为 false 时不检查模型扩展是否存在
+    check_model_ext: bool,
+    diagnostics: Vec<TemplateDiagnostic>,
+}
+
+impl<'a> TemplateChecker<'a> {
+    pub fn new(meta: &'a HapMetadata) -> Self {
+        let mut name_types = HashMap::new();
+        let mut chars = HashMap::new();
+        for (name, c) in meta.characteristics.iter() {
+            if let Some(hap_type) = short_uuid_type(c.short_uuid.as_str()) {
+                name_types.insert(name.as_str(), hap_type);
+                let perms = hap_metadata::get_perms(c.properties as u64)
+                    .iter()
+                    .map(|p| parse_str(p.as_str()))
+                    .collect();
+                chars.insert(hap_type, CharMeta {
+                    meta: c,
+                    format: parse_str(c.format.as_str()),
+                    perms,
+                });
+            }
+        }
+        let to_types = |names: &[String]| names.iter()
+            .filter_map(|n| name_types.get(n.as_str()).copied())
+            .collect::<Vec<HapType>>();
+        let mut services = HashMap::new();
+        for s in meta.services.values() {
+            if let Some(hap_type) = short_uuid_type(s.short_uuid.as_str()) {
+                services.insert(hap_type, ServiceMeta {
+                    name: s.name.clone(),
+                    required: to_types(&s.characteristics.required_characteristics),
+                    optional: to_types(s.characteristics.optional_characteristics.as_deref().unwrap_or_default()),
+                });
+            }
+        }
+        Self {
+            chars,
+            services,
+            check_model_ext: true,
+            diagnostics: vec![],
+        }
+    }
+
+    pub fn check_model_ext(mut self, check: bool) -> Self {
+        self.check_model_ext = check;
+        self
+    }
+
+    fn error(&mut self, path: String, message: String) {
+        self.diagnostics.push(TemplateDiagnostic { level: DiagnosticLevel::Error, path, message });
+    }
+
+    fn warn(&mut self, path: String, message: String) {
+        self.diagnostics.push(TemplateDiagnostic { level: DiagnosticLevel::Warning, path, message });
+    }
+
+    pub fn check(mut self, temp: &HlDeviceTemplate) -> Vec<TemplateDiagnostic> {
+        let mut tags = HashSet::new();
+        for (i, device) in temp.devices.iter().enumerate() {
+            let path = format!("devices[{i}]");
+            if !tags.insert(device.tag.as_str()) {
+                self.error(format!("{path}.tag"), format!("设备tag:{}重复", device.tag));
+            }
+            let mut accessory_tags = HashSet::new();
+            for (j, accessory) in device.accessories.iter().enumerate() {
+                let path = format!("{path}.accessories[{j}]");
+                if !accessory_tags.insert(accessory.tag.as_str()) {
+                    self.error(format!("{path}.tag"), format!("配件tag:{}重复", accessory.tag));
+                }
+                self.check_accessory(path.as_str(), accessory);
+            }
+        }
+        self.diagnostics
+    }
+
+    fn check_accessory(&mut self, path: &str, accessory: &AccessoryTemplate) {
+        let mut service_tags = HashSet::new();
+        let mut char_ids = HashSet::new();
+        for (i, service) in accessory.services.iter().enumerate() {
+            let path = format!("{path}.services[{i}]");
+            if !service_tags.insert(service.tag.as_str()) {
+                self.error(format!("{path}.tag"), format!("服务tag:{}重复", service.tag));
+            }
+            for c in service.chars.iter() {
+                char_ids.insert(CharIdentifier::new(service.tag.clone(), c.char_type));
+            }
+            self.check_service(path.as_str(), service);
+        }
+
+        let delegates: Vec<_> = accessory.hap_delegates.iter()
+            .enumerate()
+            .map(|(i, d)| (format!("{path}.hap_delegates[{i}]"), d))
+            .chain(accessory.hap_delegate.iter().map(|d| (format!("{path}.hap_delegate"), d)))
+            .collect();
+        let count = delegates.len();
+        let mut params = vec![];
+        for (path, delegate) in delegates {
+            if self.check_model_ext && get_hap_model_ext_database().get(delegate.model.as_str()).is_none() {
+                self.error(format!("{path}.model"), format!("模型扩展:{}不存在", delegate.model));
+            }
+            // 为空时接管所有特征
+            let chars = match delegate.chars.as_ref() {
+                Some(chars) => chars.clone(),
+                None if count > 1 => {
+                    self.error(format!("{path}.chars"), "多个委托模型时必须指定chars".to_string());
+                    continue;
+                }
+                None => char_ids.iter().cloned().collect(),
+            };
+            for (k, c) in chars.iter().enumerate() {
+                if !char_ids.contains(c) {
+                    self.error(format!("{path}.chars[{k}]"),
+                               format!("配件中不存在特征:{}.{}", c.stag, c.ctag));
+                }
+            }
+            params.push(ModelDelegateParam {
+                chars,
+                model: delegate.model.clone(),
+                params: None,
+                timeout: None,
+            });
+        }
+        if let Err(e) = check_delegate_chars(&params) {
+            self.error(path.to_string(), e.to_string());
+        }
+    }
+
+    fn check_service(&mut self, path: &str, service: &ServiceTemplate) {
+        let svc_type: HapType = service.service_type.into();
+        // 服务名称,必须的特征,允许的特征
+        let meta = self.services.get(&svc_type).map(|m| {
+            let allowed: HashSet<HapType> = m.required.iter()
+                .chain(m.optional.iter())
+                .copied()
+                .collect();
+            (m.name.clone(), m.required.clone(), allowed)
+        });
+        if meta.is_none() {
+            self.warn(format!("{path}.service_type"), format!("服务:{}不在 hap 元数据中", service.service_type));
+        }
+
+        let mut types = HashSet::new();
+        for (i, c) in service.chars.iter().enumerate() {
+            let path = format!("{path}.chars[{i}]");
+            let char_type: HapType = c.char_type.into();
+            if !types.insert(char_type) {
+                self.error(format!("{path}.char_type"), format!("特征:{}重复", c.char_type));
+            }
+            if let Some((name, _, allowed)) = meta.as_ref() {
+                if !allowed.contains(&char_type) {
+                    self.error(format!("{path}.char_type"), format!("服务:{}不支持特征:{}", name, c.char_type));
+                }
+            }
+            self.check_char(path.as_str(), c);
+        }
+        let required = meta.map(|m| m.1).unwrap_or_default();
+        for r in required {
+            if !types.contains(&r) {
+                let char_name = self.chars.get(&r).map(|c| c.meta.name.clone()).unwrap_or_default();
+                self.error(format!("{path}.chars"), format!("缺少必须的特征:{}", char_name));
+            }
+        }
+    }
+
+    fn check_char(&mut self, path: &str, c: &HapCharacteristicTemplate) {
+        let char_type: HapType = c.char_type.into();
+        let (meta, format, perms) = match self.chars.get(&char_type) {
+            None => {
+                self.warn(format!("{path}.char_type"), format!("特征:{}不在 hap 元数据中", c.char_type));
+                return;
+            }
+            Some(m) => (m.meta, m.format, m.perms.clone()),
+        };
+        let info = &c.info;
+        if let (Some(f), Some(expect)) = (info.format, format) {
+            if f != expect {
+                self.error(format!("{path}.info.format"), format!("格式应为:{}", meta.format));
+            }
+        }
+        if let (Some(ps), Some(expect)) = (info.perms.as_ref(), perms.as_ref()) {
+            for (i, p) in ps.iter().enumerate() {
+                if !expect.contains(p) {
+                    self.error(format!("{path}.info.perms[{i}]"), format!("特征不支持权限:{:?}", p));
+                }
+            }
+        }
+        let min = info.min_value.as_ref().and_then(Value::as_f64);
+        let max = info.max_value.as_ref().and_then(Value::as_f64);
+        let spec_min = meta.min_value.as_ref().and_then(Value::as_f64);
+        let spec_max = meta.max_value.as_ref().and_then(Value::as_f64);
+        for (name, value) in [("min_value", min), ("max_value", max)] {
+            let Some(v) = value else { continue };
+            if spec_min.is_some_and(|s| v < s) || spec_max.is_some_and(|s| v > s) {
+                self.error(format!("{path}.info.{name}"),
+                           format!("{}超出范围[{:?},{:?}]", v, spec_min, spec_max));
+            }
+        }
+        if let (Some(min), Some(max)) = (min, max) {
+            if min > max {
+                self.error(format!("{path}.info.min_value"), format!("min_value:{}大于max_value:{}", min, max));
+            }
+        }
+    }
+}
+
+/// 检查模板,返回所有问题
+pub fn check_template(meta: &HapMetadata, temp: &HlDeviceTemplate) -> Vec<TemplateDiagnostic> {
+    TemplateChecker::new(meta).check(temp)
+}
+
+/// 是否包含错误
+pub fn has_error(diagnostics: &[TemplateDiagnostic]) -> bool {
+    diagnostics.iter().any(|d| d.level == DiagnosticLevel::Error)
+}
+
+/// 检查模板,存在错误时返回所有错误信息
+pub fn validate_template(meta: &HapMetadata, temp: &HlDeviceTemplate) -> anyhow::Result<()> {
+    let errors: Vec<String> = check_template(meta, temp).into_iter()
+        .filter(|d| d.level == DiagnosticLevel::Error)
+        .map(|d| format!("{}:{}", d.path, d.message))
+        .collect();
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow!("模板:{}检查失败:{}", temp.id, errors.join("; ")))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use hap_metadata::hap_metadata;
+
+    use crate::template::checker::{DiagnosticLevel, TemplateChecker, validate_template};
+    use crate::template::hl_template::{HlDeviceTemplate, TemplateFormat};
+
+    fn check(text: &str) -> Vec<(DiagnosticLevel, String)> {
+        let meta = hap_metadata().unwrap();
+        let temp: HlDeviceTemplate = TemplateFormat::Yaml.parse(text).unwrap();
+        TemplateChecker::new(&meta)
+            .check_model_ext(false)
+            .check(&temp)
+            .into_iter()
+            .map(|d| (d.level, d.path))
+            .collect()
+    }
+
+    #[test]
+    fn test_check() {
+        let ok = r#"
+id: test
+model_name: test
+version: "1"
+model: test.light
+devices:
+  - integration: xiaomi_wifi
+    accessories:
+      - category: Lightbulb
+        hap_delegates:
+          - model: common.miot_spec_prop_mapping
+            chars: [{ stag: light, ctag: PowerState }]
+        services:
+          - service_type: Lightbulb
+            tag: light
+            chars:
+              - char_type: PowerState
+              - char_type: Brightness
+                info: { min_value: 0, max_value: 100 }
+"#;
+        assert!(check(ok).is_empty());
+
+        let bad = ok
+            .replace("char_type: Brightness", "char_type: CurrentTemperature")
+            .replace("max_value: 100", "max_value: 200")
+            .replace("- char_type: PowerState", "- char_type: Hue");
+        let result = check(bad.as_str());
+        let paths: Vec<&str> = result.iter().map(|(_, p)| p.as_str()).collect();
+        assert!(paths.contains(&"devices[0].accessories[0].services[0].chars[1].char_type"));
+        assert!(paths.contains(&"devices[0].accessories[0].services[0].chars"));
+        assert!(paths.contains(&"devices[0].accessories[0].hap_delegates[0].chars[0]"));
 
+        let meta = hap_metadata().unwrap();
+        let temp: HlDeviceTemplate = TemplateFormat::Yaml.parse(bad.as_str()).unwrap();
+        assert!(validate_template(&meta, &temp).is_err());
     }
-    Ok(())
-}
\ No newline at end of file
+}
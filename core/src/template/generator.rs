@@ -0,0 +1,289 @@
+use std::env;
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+use sea_orm::JsonValue;
+use serde_json::json;
+
+use miot_proto::spec::{MiotSpec, MiotSpecProperty, MiotSpecService};
+use target_hap::hap_type_wrapper::HapTypeWrapper;
+
+use crate::config::context::get_data_dir;
+use crate::db::entity::hap_bridge::BridgeCategory;
+use crate::template::hap::accessory::{AccessoryTemplate, ModelDelegateParamTemplate};
+use crate::template::hap::chars::{HapCharInfoTemp, HapCharacteristicTemplate};
+use crate::template::hap::service::ServiceTemplate;
+use crate::template::hl_template::{default_str, DeviceTemplate, HlDeviceTemplate};
+
+/// spec 缓存目录
+pub fn spec_cache_dir() -> PathBuf {
+    match env::var("MIOT_SPEC_DIR") {
+        Ok(v) => PathBuf::from(v.as_str()),
+        Err(_) => PathBuf::from(format!("{}/miot_spec", get_data_dir())),
+    }
+}
+
+/// 根据spec 生成的服务
+struct GenService {
+    category: BridgeCategory,
+    service: ServiceTemplate,
+    mappings: Vec<JsonValue>,
+    polls: Vec<JsonValue>,
+}
+
+impl GenService {
+    fn new(category: BridgeCategory, service_type: HapTypeWrapper, tag: String, name: &str) -> Self {
+        Self {
+            category,
+            service: ServiceTemplate {
+                service_id: None,
+                accessory_id: None,
+                service_type,
+                chars: vec![],
+                tag,
+                configured_name: Some(name.to_string()),
+                memo: None,
+                primary: None,
+                disabled: None,
+            },
+            mappings: vec![],
+            polls: vec![],
+        }
+    }
+
+    /// 映射spec 属性到特征
+    fn map_char(&mut self, siid: i32, prop: Option<&MiotSpecProperty>, ctag: HapTypeWrapper, info: HapCharInfoTemp, convertor: Option<&str>) -> bool {
+        let prop = match prop {
+            None => return false,
+            Some(p) => p,
+        };
+        self.service.chars.push(HapCharacteristicTemplate {
+            cid: None,
+            service_id: None,
+            disabled: None,
+            char_type: ctag,
+            info,
+            name: Some(prop.name().to_string()),
+            memo: Some(prop.description.clone()),
+            convertor: convertor.map(|c| c.to_string()),
+            convertor_param: None,
+        });
+        self.mappings.push(json!({ "stag": self.service.tag, "ctag": ctag, "siid": siid, "piid": prop.iid }));
+        if prop.readable() {
+            self.polls.push(json!({ "siid": siid, "piid": prop.iid }));
+        }
+        true
+    }
+
+    /// 需要手动映射的必填特征
+    fn manual_char(&mut self, ctag: HapTypeWrapper) {
+        self.service.chars.push(HapCharacteristicTemplate {
+            cid: None,
+            service_id: None,
+            disabled: None,
+            char_type: ctag,
+            info: Default::default(),
+            name: None,
+            memo: Some("todo 需要手动映射".to_string()),
+            convertor: None,
+            convertor_param: None,
+        });
+    }
+}
+
+fn range_info(prop: Option<&MiotSpecProperty>) -> HapCharInfoTemp {
+    HapCharInfoTemp {
+        min_value: prop.and_then(|p| p.min_value()).map(|v| json!(v)),
+        max_value: prop.and_then(|p| p.max_value()).map(|v| json!(v)),
+        ..Default::default()
+    }
+}
+
+/// 开尔文范围转换成米德范围
+fn mired_info(prop: Option<&MiotSpecProperty>) -> HapCharInfoTemp {
+    let to_mired = |k: f64| json!((1_000_000.0 / k).round() as i64);
+    HapCharInfoTemp {
+        min_value: prop.and_then(|p| p.max_value()).filter(|v| *v > 0.0).map(to_mired),
+        max_value: prop.and_then(|p| p.min_value()).filter(|v| *v > 0.0).map(to_mired),
+        ..Default::default()
+    }
+}
+
+fn gen_service(svc: &MiotSpecService) -> Vec<GenService> {
+    let siid = svc.iid;
+    let tag = |name: &str| format!("{}{}", name, siid);
+    let mut list = vec![];
+    match svc.name() {
+        "switch" | "outlet" => {
+            let mut s = GenService::new(BridgeCategory::Switch, HapTypeWrapper::Switch, tag("switch"), svc.description.as_str());
+            if s.map_char(siid, svc.get_property("on"), HapTypeWrapper::PowerState, Default::default(), None) {
+                list.push(s);
+            }
+        }
+        "light" => {
+            let mut s = GenService::new(BridgeCategory::Lightbulb, HapTypeWrapper::Lightbulb, tag("light"), svc.description.as_str());
+            if s.map_char(siid, svc.get_property("on"), HapTypeWrapper::PowerState, Default::default(), None) {
+                let brightness = svc.get_property("brightness");
+                s.map_char(siid, brightness, HapTypeWrapper::Brightness, range_info(brightness), None);
+                let ct = svc.get_property("color-temperature");
+                s.map_char(siid, ct, HapTypeWrapper::ColorTemperature, mired_info(ct), Some("kelvin_to_mired"));
+                list.push(s);
+            }
+        }
+        "fan" => {
+            let mut s = GenService::new(BridgeCategory::Fan, HapTypeWrapper::Fan, tag("fan"), svc.description.as_str());
+            if s.map_char(siid, svc.get_property("on"), HapTypeWrapper::PowerState, Default::default(), None) {
+                // 百分比风速才能直接映射
+                let speed = svc.get_property("speed-level")
+                    .filter(|p| p.value_range.is_some());
+                s.map_char(siid, speed, HapTypeWrapper::RotationSpeed, range_info(speed), None);
+                list.push(s);
+            }
+        }
+        "air-conditioner" => {
+            let mut s = GenService::new(BridgeCategory::AirConditioner, HapTypeWrapper::Thermostat, tag("ac"), svc.description.as_str());
+            let target = svc.get_property("target-temperature");
+            if s.map_char(siid, target, HapTypeWrapper::TargetTemperature, range_info(target), None) {
+                // 模式需要值转换,生成后手动映射
+                s.manual_char(HapTypeWrapper::CurrentHeatingCoolingState);
+                s.manual_char(HapTypeWrapper::TargetHeatingCoolingState);
+                s.manual_char(HapTypeWrapper::CurrentTemperature);
+                s.manual_char(HapTypeWrapper::TemperatureDisplayUnits);
+                list.push(s);
+            }
+        }
+        "temperature-humidity-sensor" | "environment" => {
+            let temp = svc.get_property("temperature");
+            let mut s = GenService::new(BridgeCategory::Sensor, HapTypeWrapper::TemperatureSensor, tag("temperature"), svc.description.as_str());
+            if s.map_char(siid, temp, HapTypeWrapper::CurrentTemperature, range_info(temp), None) {
+                list.push(s);
+            }
+            let mut s = GenService::new(BridgeCategory::Sensor, HapTypeWrapper::HumiditySensor, tag("humidity"), svc.description.as_str());
+            if s.map_char(siid, svc.get_property("relative-humidity"), HapTypeWrapper::CurrentRelativeHumidity, Default::default(), None) {
+                list.push(s);
+            }
+        }
+        _ => {}
+    }
+    list
+}
+
+/// 根据miot spec 生成模板草稿
+/// 识别常用的服务(开关,灯,传感器,风扇,空调),使用 common.miot_spec_prop_mapping 映射
+pub fn generate_template(model: &str, integration: &str, spec: &MiotSpec) -> anyhow::Result<HlDeviceTemplate> {
+    let services: Vec<GenService> = spec.services
+        .iter()
+        .flat_map(gen_service)
+        .collect();
+    if services.is_empty() {
+        return Err(anyhow!("spec:{}中没有可识别的服务", spec.urn));
+    }
+    let category = services[0].category;
+    let mut mappings = vec![];
+    let mut polls = vec![];
+    let mut hap_services = vec![];
+    for (i, s) in services.into_iter().enumerate() {
+        mappings.extend(s.mappings);
+        polls.extend(s.polls);
+        let mut service = s.service;
+        service.primary = Some(i == 0);
+        hap_services.push(service);
+    }
+    let accessory = AccessoryTemplate {
+        aid: None,
+        device_id: None,
+        bridge_id: None,
+        disabled: None,
+        category,
+        tag: default_str(),
+        memo: None,
+        name: None,
+        hap_delegates: vec![],
+        hap_delegate: Some(ModelDelegateParamTemplate {
+            chars: None,
+            model: "common.miot_spec_prop_mapping".to_string(),
+            params: Some(JsonValue::Array(mappings)),
+            timeout: None,
+        }),
+        services: hap_services,
+    };
+    let device = DeviceTemplate {
+        integration: integration.to_string(),
+        tag: default_str(),
+        interval: None,
+        display_name: Some(spec.description.clone()),
+        name: None,
+        device_type: None,
+        disabled: None,
+        timeout: None,
+        poll_properties: vec![],
+        memo: Some(spec.urn.clone()),
+        params: json!({
+            "interval": 60_000,
+            "poll_properties": polls,
+            "timeout": 1000,
+        }),
+        accessories: vec![accessory],
+    };
+    Ok(HlDeviceTemplate {
+        id: model.to_string(),
+        model_name: spec.description.clone(),
+        version: "1.0.0".to_string(),
+        model: model.to_string(),
+        fw_version: None,
+        model_icon: None,
+        devices: vec![device],
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use miot_proto::spec::MiotSpec;
+    use target_hap::hap_type_wrapper::HapTypeWrapper;
+
+    use crate::template::generator::generate_template;
+    use crate::template::hl_template::{HlDeviceTemplate, TemplateFormat};
+
+    const SPEC: &str = r#"{"type":"urn:miot-spec-v2:device:light:0000A001:yeelink-lamp22:1","description":"Light","services":[
+        {"iid":1,"type":"urn:miot-spec-v2:service:device-information:00007801:yeelink-lamp22:1","description":"Device Information","properties":[]},
+        {"iid":2,"type":"urn:miot-spec-v2:service:light:00007802:yeelink-lamp22:1","description":"Light","properties":[
+            {"iid":1,"type":"urn:miot-spec-v2:property:on:00000006:yeelink-lamp22:1","description":"Switch Status","format":"bool","access":["read","write","notify"]},
+            {"iid":2,"type":"urn:miot-spec-v2:property:brightness:0000000D:yeelink-lamp22:1","description":"Brightness","format":"uint8","access":["read","write","notify"],"unit":"percentage","value-range":[1,100,1]},
+            {"iid":3,"type":"urn:miot-spec-v2:property:color-temperature:0000000F:yeelink-lamp22:1","description":"Color Temperature","format":"uint32","access":["read","write","notify"],"unit":"kelvin","value-range":[2700,6500,1]}
+        ]}
+    ]}"#;
+
+    #[test]
+    fn test_generate_round_trip() {
+        let spec: MiotSpec = serde_json::from_str(SPEC).unwrap();
+        let template = generate_template("yeelink.light.lamp22", "xiaomi_wifi", &spec).unwrap();
+        for format in [TemplateFormat::Toml, TemplateFormat::Yaml] {
+            let text = format.format_to_str(&template).unwrap();
+            let parsed: HlDeviceTemplate = format.parse(text.as_str()).unwrap();
+            assert_eq!(parsed.id, "yeelink.light.lamp22");
+            let device = &parsed.devices[0];
+            assert_eq!(device.integration, "xiaomi_wifi");
+            assert_eq!(device.params["poll_properties"].as_array().unwrap().len(), 3);
+            let accessory = &device.accessories[0];
+            assert_eq!(accessory.services.len(), 1);
+            let service = &accessory.services[0];
+            assert_eq!(service.tag, "light2");
+            assert_eq!(service.primary, Some(true));
+            let types: Vec<HapTypeWrapper> = service.chars.iter().map(|c| c.char_type).collect();
+            assert_eq!(types, vec![HapTypeWrapper::PowerState, HapTypeWrapper::Brightness, HapTypeWrapper::ColorTemperature]);
+            // 色温由开尔文转换为米德,范围反转
+            let ct = &service.chars[2];
+            assert_eq!(ct.convertor.as_deref(), Some("kelvin_to_mired"));
+            assert_eq!(ct.info.min_value, Some(json!(154)));
+            assert_eq!(ct.info.max_value, Some(json!(370)));
+            let delegate = accessory.hap_delegate.as_ref().unwrap();
+            assert_eq!(delegate.model, "common.miot_spec_prop_mapping");
+            let mappings = delegate.params.as_ref().unwrap().as_array().unwrap();
+            assert_eq!(mappings.len(), 3);
+            assert_eq!(mappings[1]["piid"], json!(2));
+            assert_eq!(mappings[1]["stag"], json!("light2"));
+        }
+    }
+}
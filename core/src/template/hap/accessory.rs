@@ -2,6 +2,7 @@ use anyhow::anyhow;
 use sea_orm::ActiveValue::Set;
 use sea_orm::{JsonValue, NotSet};
 use serde::{Deserialize, Serialize};
+use target_hap::delegate::model::check_delegate_chars;
 use target_hap::types::{CharIdentifier, ModelDelegateParam};
 use crate::db::entity::hap_accessory::ModelDelegateParamVec;
 use crate::db::entity::hap_bridge::BridgeCategory;
@@ -92,6 +93,11 @@ impl AccessoryTemplate {
 
     pub fn try_into_update_model(self) -> anyhow::Result<HapAccessoryActiveModel> {
         let now = Set(chrono::Local::now().naive_local());
+        let delegates = self.hap_delegates
+            .into_iter()
+            .map(|i| i.try_into())
+            .collect::<anyhow::Result<Vec<ModelDelegateParam>>>()?;
+        check_delegate_chars(&delegates)?;
         Ok(HapAccessoryActiveModel {
             aid: Set(self.aid.ok_or(anyhow!("aid不能为空"))?),
             name: Set(self.name.clone().ok_or(anyhow!("name不能为空"))?),
@@ -100,10 +106,7 @@ impl AccessoryTemplate {
             bridge_id: Set(self.bridge_id.ok_or(anyhow!("bridge_id不能为空"))?),
             disabled: Set(self.disabled.unwrap_or(false)),
             category: Set(self.category),
-            hap_model_delegates: Set(ModelDelegateParamVec(self.hap_delegates
-                .into_iter()
-                .map(|i| i.try_into())
-                .collect::<anyhow::Result<Vec<ModelDelegateParam>>>()?)),
+            hap_model_delegates: Set(ModelDelegateParamVec(delegates)),
             memo: Set(self.memo.clone()),
             info: Default::default(),
             temp_id: Default::default(),
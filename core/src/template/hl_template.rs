@@ -24,6 +24,14 @@ pub enum TemplateFormat {
 }
 
 impl TemplateFormat {
+    /// 根据文件后缀判断格式
+    pub fn from_path(path: &std::path::Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "toml" => Some(TemplateFormat::Toml),
+            "yaml" | "yml" => Some(TemplateFormat::Yaml),
+            _ => None,
+        }
+    }
     pub fn parse<'a, T: DeserializeOwned>(&'a self, text: &'a str) -> anyhow::Result<T> {
         Ok(match self {
             TemplateFormat::Yaml => {
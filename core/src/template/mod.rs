@@ -1,6 +1,7 @@
 pub mod hl_template;
 pub mod checker;
 pub mod hap;
+pub mod generator;
 
 
 // 模型和模板互相转换,但是存在必填的情况
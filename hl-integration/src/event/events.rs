@@ -17,6 +17,12 @@ impl dyn DeviceEvent {
     }
 }
 
+impl dyn DeviceEvent + Send + Sync {
+    pub fn downcast_ref<T: DeviceEvent>(&self) -> Option<&T> {
+        (self as &dyn DeviceEvent).downcast_ref::<T>()
+    }
+}
+
 
 //
 // /// 设备产生的事件
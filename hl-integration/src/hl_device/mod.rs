@@ -1,6 +1,7 @@
 pub mod manager;
 
 use std::cmp::{max, min};
+use std::time::SystemTime;
 use tokio::sync::Mutex;
 use crate::error::DeviceExitError;
 use crate::event::HlDeviceListenable;
@@ -23,6 +24,17 @@ pub trait HlDevice: Send + Sync + HlDeviceListenable {
 }
 
 
+/// 通信状态
+#[derive(Debug, Clone, Default)]
+pub struct ContactInfo {
+    /// 最后一次成功通信时间
+    pub last_contact: Option<SystemTime>,
+    /// 连续通信失败次数
+    pub failures: u32,
+    /// 最后一次失败原因
+    pub reason: Option<String>,
+}
+
 /// 重试信息
 
 pub struct RetryInfo {
@@ -30,6 +42,7 @@ pub struct RetryInfo {
     pub retry_count: Mutex<u32>,
     /// 最大重试间隔 5 分钟,单位毫秒
     pub max_interval: u32,
+    pub contact: std::sync::Mutex<ContactInfo>,
 }
 
 impl Default for RetryInfo {
@@ -37,6 +50,7 @@ impl Default for RetryInfo {
         Self {
             retry_count: Mutex::new(0),
             max_interval: 60_0000,
+            contact: Default::default(),
         }
     }
 }
@@ -51,6 +65,26 @@ impl RetryInfo {
         let mut write = self.retry_count.lock().await;
         *write = 0;
     }
+    /// 与设备通信成功
+    pub fn contact_ok(&self) {
+        let mut contact = self.contact.lock().unwrap();
+        contact.last_contact = Some(SystemTime::now());
+        contact.failures = 0;
+        contact.reason = None;
+    }
+    /// 与设备通信失败
+    pub fn contact_err(&self, reason: String) {
+        let mut contact = self.contact.lock().unwrap();
+        contact.failures += 1;
+        contact.reason = Some(reason);
+    }
+    pub fn contact_info(&self) -> ContactInfo {
+        self.contact.lock().unwrap().clone()
+    }
+    /// 当前连续重试次数
+    pub async fn count(&self) -> u32 {
+        *self.retry_count.lock().await
+    }
     pub async fn get(&self) -> u32 {
         let count_read = self.retry_count.lock().await;
         // 产生1-1000 随机数
@@ -20,6 +20,13 @@ pub enum BleError {
     NotSupportedDeviceType(u16),
     #[error("MiPacketError {0}")]
     MiPacketError(#[from] MiPacketError),
+    /// 加密设备未配置 bindkey, mac 如 A4:C1:38:11:22:33
+    #[error("AesKeyNotFound {0}")]
+    AesKeyNotFound(String),
+    #[error("InvalidAesKey {0}")]
+    InvalidAesKey(&'static str),
+    #[error("DecryptError {0}")]
+    DecryptError(&'static str),
 }
 
 // `From<MiPacketError>`
\ No newline at end of file
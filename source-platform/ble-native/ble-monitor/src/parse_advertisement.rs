@@ -24,15 +24,15 @@ pub struct ServiceDataPacket {
 }
 
 
-/// 解析数据包
-pub fn parse_advertisement(uuid: &Uuid, data: &[u8]) -> BltResult<Option<ServiceDataPacket>> {
+/// 解析数据包, aes_keys 为 mac 与 bindkey 的映射,用于解密
+pub fn parse_advertisement(uuid: &Uuid, data: &[u8], aes_keys: &HashMap<[u8; 6], String>) -> BltResult<Option<ServiceDataPacket>> {
     let uuid_128:&[u8] = uuid.as_ref();
     let uuid_16 = (uuid_128[2] as u16) << 8 | uuid_128[3] as u16;
     match BlePlatform::try_from(uuid_16) {
         Ok(platform) => {
             match platform {
                 BlePlatform::Xiaomi => {
-                    XiaomiParser::new(HashMap::new()).parse(data)
+                    XiaomiParser::new(aes_keys).parse(data)
                 }
             }
         }
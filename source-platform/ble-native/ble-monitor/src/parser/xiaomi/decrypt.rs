@@ -0,0 +1,105 @@
+use aes::Aes128;
+use aes::cipher::{BlockEncrypt, KeyInit};
+use aes::cipher::generic_array::GenericArray;
+use ccm::aead::{Aead, Payload};
+use ccm::Ccm;
+use ccm::consts::{U12, U4};
+
+use crate::BltResult;
+use crate::error::BleError::{DecryptError, InvalidAesKey, UnpackDataError};
+
+/// v4/v5 的 aes-ccm, 4字节 mic, 12字节 nonce
+type MiBeaconCcm = Ccm<Aes128, U4, U12>;
+
+const AAD: [u8; 1] = [0x11];
+/// v2/v3 的 12字节 key 中间插入的固定值
+const LEGACY_KEY_SALT: [u8; 4] = [0x8d, 0x3d, 0x3c, 0x97];
+
+fn decode_key(key: &str, len: usize) -> BltResult<Vec<u8>> {
+    let key = hex::decode(key.trim()).map_err(|_| InvalidAesKey("key 不是16进制"))?;
+    if key.len() != len {
+        return Err(InvalidAesKey("key 长度错误"));
+    }
+    Ok(key)
+}
+
+/// 解密 MiBeacon v4/v5
+/// data: frctrl 开头的服务数据, index: 加密数据的开始, mac: 包中的 mac (小端)
+/// 结构: 加密数据 | 3字节计数 | 4字节 mic
+pub fn decrypt_v4_v5(key: &str, mac: &[u8; 6], index: usize, data: &[u8]) -> BltResult<Vec<u8>> {
+    if data.len() < index + 9 {
+        return Err(UnpackDataError("数据长度错误"));
+    }
+    let key = decode_key(key, 16)?;
+    let len = data.len();
+    let mut nonce = Vec::with_capacity(12);
+    nonce.extend_from_slice(mac);
+    nonce.extend_from_slice(&data[2..5]);
+    nonce.extend_from_slice(&data[len - 7..len - 4]);
+    // 密文后接 mic
+    let mut msg = data[index..len - 7].to_vec();
+    msg.extend_from_slice(&data[len - 4..]);
+    MiBeaconCcm::new(GenericArray::from_slice(key.as_slice()))
+        .decrypt(GenericArray::from_slice(nonce.as_slice()), Payload { msg: msg.as_slice(), aad: &AAD })
+        .map_err(|_| DecryptError("mic 校验失败"))
+}
+
+/// 解密 MiBeacon v2/v3, 不校验 mic
+/// 结构: 加密数据 | 3字节计数 | 1字节
+pub fn decrypt_legacy(key: &str, mac: &[u8; 6], index: usize, data: &[u8]) -> BltResult<Vec<u8>> {
+    if data.len() < index + 7 {
+        return Err(UnpackDataError("数据长度错误"));
+    }
+    let key = decode_key(key, 12)?;
+    let mut full_key = Vec::with_capacity(16);
+    full_key.extend_from_slice(&key[0..6]);
+    full_key.extend_from_slice(&LEGACY_KEY_SALT);
+    full_key.extend_from_slice(&key[6..]);
+    let len = data.len();
+    let mut nonce = Vec::with_capacity(13);
+    nonce.extend_from_slice(&data[0..5]);
+    nonce.extend_from_slice(&data[len - 4..len - 1]);
+    nonce.extend_from_slice(&mac[0..5]);
+    Ok(ccm_ctr(full_key.as_slice(), nonce.as_slice(), &data[index..len - 4]))
+}
+
+/// ccm 的 ctr 部分,计数从1开始
+fn ccm_ctr(key: &[u8], nonce: &[u8], payload: &[u8]) -> Vec<u8> {
+    let cipher = Aes128::new(GenericArray::from_slice(key));
+    // flags = L - 1, L = 15 - nonce 长度
+    let l = 15 - nonce.len();
+    let mut out = Vec::with_capacity(payload.len());
+    for (i, chunk) in payload.chunks(16).enumerate() {
+        let mut block = [0u8; 16];
+        block[0] = (l - 1) as u8;
+        block[1..1 + nonce.len()].copy_from_slice(nonce);
+        let counter = (i + 1) as u64;
+        for j in 0..l {
+            block[15 - j] = (counter >> (8 * j)) as u8;
+        }
+        let mut block = GenericArray::from(block);
+        cipher.encrypt_block(&mut block);
+        out.extend(chunk.iter().zip(block.iter()).map(|(a, b)| a ^ b));
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use crate::parser::xiaomi::decrypt::{decrypt_legacy, decrypt_v4_v5};
+
+    const MAC: [u8; 6] = [0x33, 0x22, 0x11, 0x38, 0xc1, 0xa4];
+
+    #[test]
+    fn test_decrypt() {
+        let data = hex::decode("58585b041033221138c1a4e359ad5d8701020327b37972").unwrap();
+        let payload = decrypt_v4_v5("814aac74c4f17b6c1581e1ab87816b99", &MAC, 11, data.as_slice()).unwrap();
+        assert_eq!(hex::encode(payload), "041002e500");
+        // key 错误时 mic 校验失败
+        assert!(decrypt_v4_v5("00000000000000000000000000000000", &MAC, 11, data.as_slice()).is_err());
+
+        let data = hex::decode("58305b041133221138c1a4c045e2534801020300").unwrap();
+        let payload = decrypt_legacy("b853075158487ca39a5b5ea9", &MAC, 11, data.as_slice()).unwrap();
+        assert_eq!(hex::encode(payload), "041002e500");
+    }
+}
@@ -1,3 +1,4 @@
 pub mod value_object;
 pub mod parser;
-mod packet;
\ No newline at end of file
+mod packet;
+pub mod decrypt;
\ No newline at end of file
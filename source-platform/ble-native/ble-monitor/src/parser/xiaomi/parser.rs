@@ -1,19 +1,23 @@
 use std::collections::HashMap;
-use impl_new::New;
 use log::{debug, error, info};
 use tap::TapFallible;
 use xiaomi_ble_packet::ble_value_type::MiBleValueType;
 use crate::BltResult;
-use crate::error::BleError::{BleValueTypeError, NotSupported, UnpackDataError};
+use crate::error::BleError::{AesKeyNotFound, BleValueTypeError, NotSupported, UnpackDataError};
 use crate::parse_advertisement::ServiceDataPacket;
+use crate::parser::xiaomi::decrypt::{decrypt_legacy, decrypt_v4_v5};
 use crate::parser::xiaomi::packet::Packet;
 
-#[derive(New)]
-pub struct XiaomiParser {
-    aes_keys: HashMap<[u8; 6], String>,
+pub struct XiaomiParser<'a> {
+    /// mac 与 bindkey 的映射
+    aes_keys: &'a HashMap<[u8; 6], String>,
 }
 
-impl XiaomiParser {
+impl<'a> XiaomiParser<'a> {
+    pub fn new(aes_keys: &'a HashMap<[u8; 6], String>) -> Self {
+        Self { aes_keys }
+    }
+
     pub fn parse(&self, data: &[u8]) -> BltResult<Option<ServiceDataPacket>> {
         let mut index = 5;
         let packet = Packet::unpack(data)?;
@@ -38,16 +42,12 @@ impl XiaomiParser {
         if !packet.ctrl.object_include {
             return Ok(None);
         };
-        if packet.ctrl.encrypted {
-            if packet.ctrl.version <= 3 {
-                todo!("解密数据v3")
-            } else {
-                self.decrypt_mibeacon_v4_v5(&packet, index, data)?;
-            }
-        }
-
-
-        let payload = &data[index..];
+        let payload = if packet.ctrl.encrypted {
+            self.decrypt(&packet, index, data)?
+        } else {
+            data[index..].to_vec()
+        };
+        let payload = payload.as_slice();
         let payload_length = payload.len();
 
         let payload_start = 0;
@@ -81,27 +81,38 @@ impl XiaomiParser {
             edata: edata.to_vec(),
         }))
     }
-    fn decrypt_mibeacon_v4_v5(&self, _packet: &Packet, index: usize, data: &[u8]) -> BltResult<Vec<u8>> {
-        if data.len() < index + 9 {
-            return Err(UnpackDataError("数据长度错误"));
-        };
-        debug!("解密数据v4_v5");
-
-        // self.aes_keys.get(&packet.mac)
-        //     .ok_or(UnpackDataError("aes key not found"))
-        //     .tap_err(|_| debug!("mac:{},aes key not found",hex::encode(packet.mac)))?;
-        //
 
-        Ok(vec![])
+    /// 按版本解密, v2/v3 为旧格式
+    fn decrypt(&self, packet: &Packet, index: usize, data: &[u8]) -> BltResult<Vec<u8>> {
+        let mac = packet.mac.ok_or(UnpackDataError("加密数据缺少mac"))?;
+        let key = self.aes_keys.get(&mac)
+            .ok_or_else(|| AesKeyNotFound(mac_str(&mac)))
+            .tap_err(|_| debug!("mac:{},aes key not found", mac_str(&mac)))?;
+        // nonce 中使用包中的字节序
+        let mut raw_mac = mac;
+        raw_mac.reverse();
+        if packet.ctrl.version <= 3 {
+            decrypt_legacy(key, &raw_mac, index, data)
+        } else {
+            decrypt_v4_v5(key, &raw_mac, index, data)
+        }
     }
 }
 
+fn mac_str(mac: &[u8; 6]) -> String {
+    mac.iter()
+        .map(|b| format!("{:02X}", b))
+        .collect::<Vec<String>>()
+        .join(":")
+}
+
 
 #[cfg(test)]
 mod test {
     use std::collections::HashMap;
     use std::env;
     use log::error;
+    use crate::error::BleError;
     
 
     #[test]
@@ -119,7 +130,7 @@ mod test {
         let _aes_key = "";
         let data = hex::decode(hex_str).unwrap();
         let map = HashMap::new();
-        let packet = super::XiaomiParser::new(map)
+        let packet = super::XiaomiParser::new(&map)
             .parse(data.as_slice());
         println!("{:?}", packet);
         match packet {
@@ -129,4 +140,22 @@ mod test {
             }
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_parse_encrypted() {
+        let data = hex::decode("58585b041033221138c1a4e359ad5d8701020327b37972").unwrap();
+        let mac = [0xa4, 0xc1, 0x38, 0x11, 0x22, 0x33];
+        let mut map = HashMap::new();
+        let result = super::XiaomiParser::new(&map).parse(data.as_slice());
+        assert!(matches!(result, Err(BleError::AesKeyNotFound(_))));
+
+        map.insert(mac, "814aac74c4f17b6c1581e1ab87816b99".to_string());
+        let packet = super::XiaomiParser::new(&map)
+            .parse(data.as_slice())
+            .unwrap()
+            .unwrap();
+        assert_eq!(packet.mac, mac);
+        assert_eq!(packet.etype, 0x1004);
+        assert_eq!(packet.edata, vec![0xe5, 0x00]);
+    }
+}
@@ -0,0 +1,381 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use log::{debug, error, info, warn};
+use rand::Rng;
+use rumqttc::{AsyncClient, Event, Incoming, MqttOptions, QoS};
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+
+use hl_integration::error::DeviceExitError;
+use hl_integration::event::{EventListener, HlDeviceListenable};
+use hl_integration::event::emitter::DeviceEventEmitter;
+use hl_integration::event::events::DeviceEvent;
+use hl_integration::hl_device::{HlDevice, RetryInfo};
+use hl_integration::HlSourceDevice;
+use hl_integration::JsonValue;
+use hl_integration::platform::hap::hap_device::{DeviceInfo, HapDevice};
+
+use crate::json_path;
+
+fn default_port() -> u16 {
+    1883
+}
+
+/// 属性映射
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MqttPropertyParam {
+    /// 属性名称
+    pub name: String,
+    /// 状态json 中的路径,如 state, StatusSNS.AM2301.Temperature
+    pub path: String,
+    /// 命令json 中的路径,默认同 path
+    pub set_path: Option<String>,
+    /// 单独的命令主题,payload 为值本身,如 tasmota 的 cmnd/xxx/POWER
+    pub command_topic: Option<String>,
+}
+
+/// mqtt 设备参数
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MqttDeviceParam {
+    pub host: String,
+    #[serde(default = "default_port")]
+    pub port: u16,
+    pub client_id: Option<String>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// 状态主题,payload 为json, 如 zigbee2mqtt/客厅灯
+    pub state_topic: String,
+    /// 命令主题,payload 为json, 如 zigbee2mqtt/客厅灯/set
+    pub command_topic: Option<String>,
+    #[serde(default)]
+    pub properties: Vec<MqttPropertyParam>,
+    pub manufacturer: Option<String>,
+    pub model: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MqttProperty {
+    pub name: String,
+    pub value: JsonValue,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type")]
+pub enum MqttEvent {
+    /// 状态主题中的属性变化
+    PropertiesChanged(Vec<MqttProperty>),
+}
+
+impl DeviceEvent for MqttEvent {}
+
+#[derive(Debug)]
+pub enum MqttExitError {
+    /// 连接断开
+    Disconnect(String),
+}
+
+impl DeviceExitError for MqttExitError {
+    fn retryable(&self) -> bool {
+        true
+    }
+}
+
+/// 通用mqtt 设备
+/// 订阅状态主题,按路径解析属性; 写属性时发布到命令主题
+pub struct MqttDevice {
+    dev_id: String,
+    param: MqttDeviceParam,
+    client: RwLock<Option<AsyncClient>>,
+    /// 属性名 -> 最新值
+    values: RwLock<HashMap<String, JsonValue>>,
+    emitter: DeviceEventEmitter,
+    retry_info: RetryInfo,
+}
+
+impl MqttDevice {
+    pub fn new(dev_id: String, param: MqttDeviceParam) -> Self {
+        Self {
+            dev_id,
+            param,
+            client: RwLock::new(None),
+            values: Default::default(),
+            emitter: Default::default(),
+            retry_info: Default::default(),
+        }
+    }
+
+    fn options(&self) -> MqttOptions {
+        let client_id = self.param.client_id.clone().unwrap_or_else(|| {
+            let random_number: u32 = rand::thread_rng().gen_range(10000..99999);
+            format!("homelink-{}-{random_number}", self.dev_id)
+        });
+        let mut options = MqttOptions::new(client_id, self.param.host.as_str(), self.param.port);
+        options.set_keep_alive(Duration::from_secs(30));
+        if let Some(username) = self.param.username.as_ref() {
+            options.set_credentials(username.as_str(), self.param.password.clone().unwrap_or_default());
+        }
+        options
+    }
+
+    fn get_param(&self, name: &str) -> anyhow::Result<&MqttPropertyParam> {
+        self.param.properties
+            .iter()
+            .find(|p| p.name == name)
+            .ok_or(anyhow!("属性:{}不存在", name))
+    }
+
+    /// 解析状态消息,更新变化的属性
+    async fn on_state(&self, payload: &[u8]) {
+        let state: JsonValue = match serde_json::from_slice(payload) {
+            Ok(v) => v,
+            Err(e) => {
+                debug!("状态消息不是json:{:?}", e);
+                return;
+            }
+        };
+        let mut changes = vec![];
+        {
+            let mut values = self.values.write().await;
+            for prop in self.param.properties.iter() {
+                if let Some(value) = json_path::get(&state, prop.path.as_str()) {
+                    if values.get(prop.name.as_str()) != Some(value) {
+                        values.insert(prop.name.clone(), value.clone());
+                        changes.push(MqttProperty {
+                            name: prop.name.clone(),
+                            value: value.clone(),
+                        });
+                    }
+                }
+            }
+        }
+        if !changes.is_empty() {
+            self.emitter.emit(std::sync::Arc::new(MqttEvent::PropertiesChanged(changes))).await;
+        }
+    }
+
+    /// 读取缓存的属性值
+    pub async fn get_property(&self, name: &str) -> Option<JsonValue> {
+        self.values.read().await.get(name).cloned()
+    }
+
+    pub async fn is_connected(&self) -> bool {
+        self.client.read().await.is_some()
+    }
+
+    /// 设置属性,同一命令主题的属性合并成一条消息
+    pub async fn set_properties(&self, props: Vec<(String, JsonValue)>) -> anyhow::Result<()> {
+        let client = self.client.read().await
+            .clone()
+            .ok_or(anyhow!("设备:{}未连接", self.dev_id))?;
+        let mut command = JsonValue::Object(Default::default());
+        let mut has_command = false;
+        for (name, value) in props {
+            let param = self.get_param(name.as_str())?;
+            match param.command_topic.as_ref() {
+                Some(topic) => {
+                    let payload = match &value {
+                        JsonValue::String(s) => s.clone(),
+                        v => v.to_string(),
+                    };
+                    client.publish(topic.as_str(), QoS::AtLeastOnce, false, payload).await?;
+                }
+                None => {
+                    let path = param.set_path.as_ref().unwrap_or(&param.path);
+                    json_path::set(&mut command, path.as_str(), value);
+                    has_command = true;
+                }
+            }
+        }
+        if has_command {
+            let topic = self.param.command_topic.as_ref()
+                .ok_or(anyhow!("设备:{}未设置命令主题", self.dev_id))?;
+            client.publish(topic.as_str(), QoS::AtLeastOnce, false, command.to_string()).await?;
+        }
+        Ok(())
+    }
+}
+
+impl HlSourceDevice for MqttDevice {}
+
+#[async_trait::async_trait]
+impl HlDeviceListenable for MqttDevice {
+    async fn add_listener(&self, listener: EventListener) -> i64 {
+        self.emitter.add_listener(listener).await
+    }
+
+    fn remove_listener(&self, id: i64) -> i64 {
+        self.emitter.remove_listener(id)
+    }
+}
+
+#[async_trait::async_trait]
+impl HlDevice for MqttDevice {
+    fn dev_id(&self) -> String {
+        self.dev_id.clone()
+    }
+
+    fn device_type(&self) -> &str {
+        "mqtt"
+    }
+
+    async fn run(&self) -> Result<(), Box<dyn DeviceExitError>> {
+        let (client, mut event_loop) = AsyncClient::new(self.options(), 64);
+        let state_topic = self.param.state_topic.as_str();
+        client.subscribe(state_topic, QoS::AtLeastOnce)
+            .await
+            .map_err(|e| Box::new(MqttExitError::Disconnect(e.to_string())) as Box<dyn DeviceExitError>)?;
+        self.client.write().await.replace(client);
+        info!("mqtt 设备:{}开始监听:{}", self.dev_id, state_topic);
+        let err = loop {
+            match event_loop.poll().await {
+                Ok(Event::Incoming(Incoming::ConnAck(_))) => {
+                    self.retry_info.reset().await;
+                }
+                Ok(Event::Incoming(Incoming::Publish(msg))) => {
+                    if msg.topic == state_topic {
+                        self.on_state(msg.payload.as_ref()).await;
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    break e;
+                }
+            }
+        };
+        self.client.write().await.take();
+        error!("mqtt 设备:{}连接断开:{:?}", self.dev_id, err);
+        Err(Box::new(MqttExitError::Disconnect(err.to_string())))
+    }
+
+    async fn enabled(&self) -> bool {
+        true
+    }
+
+    fn retry_info(&self) -> &RetryInfo {
+        &self.retry_info
+    }
+}
+
+impl HapDevice for MqttDevice {
+    fn get_hap_info(&self) -> DeviceInfo {
+        DeviceInfo {
+            manufacturer: self.param.manufacturer.clone().unwrap_or("homelink".to_string()),
+            model: self.param.model.clone().unwrap_or("mqtt".to_string()),
+            serial_number: self.dev_id.clone(),
+            software_revision: None,
+            firmware_revision: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use rumqttc::{AsyncClient, Event, Incoming, MqttOptions, QoS};
+    use serde_json::json;
+    use tokio::sync::mpsc;
+
+    use hl_integration::hl_device::HlDevice;
+
+    use crate::device::{MqttDevice, MqttDeviceParam};
+
+    /// 在进程内启动 rumqttd, 返回监听的端口
+    fn start_broker() -> u16 {
+        let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let config: rumqttd::Config = serde_json::from_value(json!({
+            "id": 0,
+            "router": {
+                "id": 0,
+                "max_connections": 100,
+                "max_outgoing_packet_count": 200,
+                "max_segment_size": 104857600,
+                "max_segment_count": 10,
+            },
+            "v4": {
+                "1": {
+                    "name": "v4-1",
+                    "listen": format!("127.0.0.1:{port}"),
+                    "next_connection_delay_ms": 1,
+                    "connections": {
+                        "connection_timeout_ms": 60000,
+                        "max_payload_size": 20480,
+                        "max_inflight_count": 100,
+                        "dynamic_filters": true,
+                    }
+                }
+            }
+        })).unwrap();
+        std::thread::spawn(move || {
+            let mut broker = rumqttd::Broker::new(config);
+            broker.start().unwrap();
+        });
+        // 等待 broker 开始监听
+        for _ in 0..100 {
+            if TcpStream::connect(("127.0.0.1", port)).is_ok() {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        port
+    }
+
+    #[tokio::test]
+    async fn test_local_broker() {
+        let port = start_broker();
+        let param: MqttDeviceParam = serde_json::from_value(json!({
+            "host": "127.0.0.1",
+            "port": port,
+            "state_topic": "zigbee2mqtt/test_light",
+            "command_topic": "zigbee2mqtt/test_light/set",
+            "properties": [{ "name": "on", "path": "state" }, { "name": "brightness", "path": "brightness" }]
+        })).unwrap();
+        let dev = Arc::new(MqttDevice::new("test".to_string(), param));
+        let dev_c = dev.clone();
+        tokio::spawn(async move {
+            let _ = dev_c.run().await;
+        });
+
+        let (client, mut event_loop) = AsyncClient::new(MqttOptions::new("homelink-test-pub", "127.0.0.1", port), 10);
+        let (tx, mut commands) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            loop {
+                match event_loop.poll().await {
+                    Ok(Event::Incoming(Incoming::Publish(msg))) => {
+                        let _ = tx.send((msg.topic, msg.payload));
+                    }
+                    Ok(_) => {}
+                    // broker 启动前连接失败,重试
+                    Err(_) => tokio::time::sleep(Duration::from_millis(50)).await,
+                }
+            }
+        });
+        client.subscribe("zigbee2mqtt/test_light/set", QoS::AtLeastOnce).await.unwrap();
+
+        // 设备订阅完成前的消息会丢失,重复发布直到收到
+        let received = tokio::time::timeout(Duration::from_secs(10), async {
+            while dev.get_property("brightness").await.is_none() {
+                client.publish("zigbee2mqtt/test_light", QoS::AtLeastOnce, false, r#"{"state":"ON","brightness":120}"#)
+                    .await
+                    .unwrap();
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+        }).await;
+        assert!(received.is_ok(), "未收到状态消息");
+        assert_eq!(dev.get_property("on").await, Some(json!("ON")));
+        assert_eq!(dev.get_property("brightness").await, Some(json!(120)));
+
+        dev.set_properties(vec![("on".to_string(), json!("OFF"))]).await.unwrap();
+        let (topic, payload) = tokio::time::timeout(Duration::from_secs(5), commands.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(topic, "zigbee2mqtt/test_light/set");
+        let command: serde_json::Value = serde_json::from_slice(payload.as_ref()).unwrap();
+        assert_eq!(command, json!({"state": "OFF"}));
+    }
+}
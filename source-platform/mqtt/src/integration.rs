@@ -0,0 +1,20 @@
+use hl_integration::integration::HlSourceIntegrator;
+use target_hap::delegate::database::get_hap_model_ext_database;
+use target_hap::delegate::model::AccessoryModelExtConstructor;
+
+use crate::models;
+
+/// 通用mqtt 接入, 如 zigbee2mqtt, tasmota
+pub struct MqttIntegration {}
+
+impl HlSourceIntegrator for MqttIntegration {
+    fn name(&self) -> &str {
+        "mqtt"
+    }
+
+    fn init(&self) -> anyhow::Result<()> {
+        let database = get_hap_model_ext_database();
+        database.insert("mqtt.prop_mapping".to_string(), models::prop_mapping::ModelExt::new)?;
+        Ok(())
+    }
+}
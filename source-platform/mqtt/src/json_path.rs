@@ -0,0 +1,61 @@
+use serde_json::{Map, Value};
+
+/// 简单的json 路径,以 . 分隔,数字为数组下标
+/// 例如: state, update.installed_version, $.StatusSNS.AM2301.Temperature
+fn split(path: &str) -> impl Iterator<Item=&str> {
+    path.strip_prefix("$.")
+        .unwrap_or(path)
+        .split('.')
+        .filter(|i| !i.is_empty())
+}
+
+/// 读取路径上的值
+pub fn get<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    let mut current = value;
+    for key in split(path) {
+        current = match current {
+            Value::Object(map) => map.get(key)?,
+            Value::Array(list) => list.get(key.parse::<usize>().ok()?)?,
+            _ => return None,
+        };
+    }
+    Some(current)
+}
+
+/// 在路径上写入值,中间的对象不存在时自动创建
+pub fn set(target: &mut Value, path: &str, value: Value) {
+    let keys: Vec<&str> = split(path).collect();
+    let mut current = target;
+    for (i, key) in keys.iter().enumerate() {
+        if !current.is_object() {
+            *current = Value::Object(Map::new());
+        }
+        let map = current.as_object_mut().unwrap();
+        if i == keys.len() - 1 {
+            map.insert(key.to_string(), value);
+            return;
+        }
+        current = map.entry(key.to_string()).or_insert(Value::Null);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use crate::json_path::{get, set};
+
+    #[test]
+    fn test_json_path() {
+        let value = json!({"state":"ON","StatusSNS":{"AM2301":{"Temperature":22.5}},"list":[1,{"a":2}]});
+        assert_eq!(get(&value, "state"), Some(&json!("ON")));
+        assert_eq!(get(&value, "$.StatusSNS.AM2301.Temperature"), Some(&json!(22.5)));
+        assert_eq!(get(&value, "list.1.a"), Some(&json!(2)));
+        assert_eq!(get(&value, "list.x"), None);
+
+        let mut cmd = json!({});
+        set(&mut cmd, "state", json!("OFF"));
+        set(&mut cmd, "color.x", json!(0.3));
+        assert_eq!(cmd, json!({"state":"OFF","color":{"x":0.3}}));
+    }
+}
@@ -0,0 +1,4 @@
+pub mod integration;
+pub mod device;
+pub mod models;
+mod json_path;
@@ -0,0 +1,2 @@
+/// 属性映射
+pub(crate) mod prop_mapping;
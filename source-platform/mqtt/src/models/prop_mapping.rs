@@ -0,0 +1,198 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use log::{debug, warn};
+
+use hl_integration::event::events::DeviceEventPointer;
+use hl_integration::JsonValue;
+use target_hap::delegate::{CharReadParam, CharReadResult, CharUpdateParam, CharUpdateResult};
+use target_hap::delegate::model::{AccessoryModelExtConstructor, ContextPointer, HapModelExt, HapModelExtPointer, ReadValueResult, UpdateValueResult};
+use target_hap::hap_type_wrapper::HapTypeWrapper;
+use target_hap::iot::characteristic_value::CharacteristicValue;
+use target_hap::types::CharIdentifier;
+
+use crate::device::{MqttDevice, MqttEvent};
+
+fn default_str() -> String {
+    "default".to_string()
+}
+
+/// 特征值和设备值的对应, 如 true <-> "ON"
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct ValuePair {
+    hap: JsonValue,
+    dev: JsonValue,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct PropMappingParam {
+    /// stag,ctag,prop
+    #[serde(default = "default_str")]
+    stag: String,
+    ctag: HapTypeWrapper,
+    /// 设备属性名称
+    prop: String,
+    #[serde(default)]
+    value_map: Vec<ValuePair>,
+}
+
+/// mqtt 属性映射模型
+/// 一个属性可以映射到多个特征,如 On 和 Active
+pub struct ModelExt {
+    ctx: ContextPointer,
+    /// 特征 -> 属性
+    mapping: HashMap<CharIdentifier, String>,
+    value_maps: Vec<(CharIdentifier, Vec<ValuePair>)>,
+}
+
+impl AccessoryModelExtConstructor for ModelExt {
+    fn new(ctx: ContextPointer, params: Option<JsonValue>) -> anyhow::Result<HapModelExtPointer> {
+        let params = params.ok_or(anyhow!("mqtt prop mapping params is none"))?;
+        let params: Vec<PropMappingParam> = serde_json::from_value(params)?;
+        ctx.dev.downcast_ref::<MqttDevice>()
+            .ok_or(anyhow!("mqtt.prop_mapping 只支持mqtt设备"))?;
+        let mut mapping = HashMap::new();
+        let mut value_maps = vec![];
+        for param in params {
+            let cid = CharIdentifier::new(param.stag, param.ctag);
+            if !param.value_map.is_empty() {
+                value_maps.push((cid.clone(), param.value_map));
+            }
+            if let Some(prop) = mapping.get(&cid) {
+                return Err(anyhow!("特征重复映射,stag:{},ctag:{:?},prop:{}和{}", cid.stag, cid.ctag, prop, param.prop));
+            }
+            mapping.insert(cid, param.prop);
+        }
+        Ok(Arc::new(Self {
+            ctx,
+            mapping,
+            value_maps,
+        }))
+    }
+}
+
+impl ModelExt {
+    fn dev(&self) -> anyhow::Result<&MqttDevice> {
+        self.ctx.dev.downcast_ref::<MqttDevice>()
+            .ok_or(anyhow!("设备类型错误"))
+    }
+
+    fn value_map(&self, cid: &CharIdentifier) -> Option<&Vec<ValuePair>> {
+        self.value_maps.iter()
+            .find(|(c, _)| c == cid)
+            .map(|(_, v)| v)
+    }
+
+    /// 设备值转特征值
+    fn to_hap(&self, cid: &CharIdentifier, value: JsonValue) -> JsonValue {
+        let value = match self.value_map(cid) {
+            Some(pairs) => pairs.iter()
+                .find(|p| p.dev == value)
+                .map(|p| p.hap.clone())
+                .unwrap_or(value),
+            None => value,
+        };
+        match self.ctx.convertor_map.get(cid) {
+            Some(c) => c.ext.from(value.clone()).unwrap_or(value),
+            None => value,
+        }
+    }
+
+    /// 特征值转设备值
+    fn to_dev(&self, cid: &CharIdentifier, value: JsonValue) -> JsonValue {
+        let value = match self.ctx.convertor_map.get(cid) {
+            Some(c) => c.ext.to(value.clone()).unwrap_or(value),
+            None => value,
+        };
+        match self.value_map(cid) {
+            Some(pairs) => pairs.iter()
+                .find(|p| p.hap == value)
+                .map(|p| p.dev.clone())
+                .unwrap_or(value),
+            None => value,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl HapModelExt for ModelExt {
+    async fn read_chars_value(&self, params: Vec<CharReadParam>) -> ReadValueResult {
+        let dev = self.dev()?;
+        let mut result = vec![];
+        for param in params.into_iter() {
+            let cid = CharIdentifier::new(param.stag.clone(), param.ctag);
+            let prop = match self.mapping.get(&cid) {
+                None => {
+                    warn!("no mapping for stag:{:?},ctag:{:?}", param.stag, param.ctag);
+                    result.push(CharReadResult::success(&param, None));
+                    continue;
+                }
+                Some(p) => p,
+            };
+            match dev.get_property(prop.as_str()).await {
+                None => result.push(CharReadResult::fail(&param)),
+                Some(value) => {
+                    let value = self.to_hap(&cid, value);
+                    let value = CharacteristicValue::format(param.format, value).value;
+                    result.push(CharReadResult::success(&param, Some(value)));
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    async fn update_chars_value(&self, params: Vec<CharUpdateParam>) -> UpdateValueResult {
+        let mut result = vec![];
+        let mut cids = vec![];
+        let mut props = vec![];
+        for param in params.into_iter() {
+            let cid = CharIdentifier::new(param.stag.clone(), param.ctag);
+            match self.mapping.get(&cid) {
+                None => {
+                    warn!("no mapping for stag:{:?},ctag:{:?}", param.stag, param.ctag);
+                    result.push(CharUpdateResult {
+                        cid: param.cid,
+                        success: false,
+                    });
+                }
+                Some(prop) => {
+                    let value = CharacteristicValue::try_format(param.format, param.new_value)?.value;
+                    props.push((prop.clone(), self.to_dev(&cid, value)));
+                    cids.push(param.cid);
+                }
+            }
+        }
+        if !props.is_empty() {
+            self.dev()?.set_properties(props).await?;
+            for cid in cids {
+                result.push(CharUpdateResult {
+                    cid,
+                    success: true,
+                });
+            }
+        }
+        Ok(result)
+    }
+
+    async fn on_event(&self, event: DeviceEventPointer) {
+        let changes = match event.downcast_ref::<MqttEvent>() {
+            Some(MqttEvent::PropertiesChanged(changes)) => changes,
+            None => return,
+        };
+        for change in changes {
+            let cids = self.mapping.iter()
+                .filter(|(_, prop)| **prop == change.name)
+                .map(|(cid, _)| cid);
+            for cid in cids {
+                let value = self.to_hap(cid, change.value.clone());
+                debug!("mqtt 属性:{}变化,更新特征:{:?}", change.name, cid);
+                if let Err(e) = self.ctx.hap_manager
+                    .update_char_value(self.ctx.aid, cid.stag.clone(), cid.ctag.into(), value)
+                    .await {
+                    warn!("更新特征值失败:{:?}", e);
+                }
+            }
+        }
+    }
+}
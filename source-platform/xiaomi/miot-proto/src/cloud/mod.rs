@@ -31,6 +31,7 @@ use serde_json::{Value};
 use tokio::io::AsyncReadExt;
 use crate::cloud::state::CookieState;
 use anyhow::Result;
+use crate::secret::SecretCipher;
 
 use reqwest::header::HeaderMap;
 use tap::TapFallible;
@@ -269,6 +270,8 @@ pub struct MiCloud {
     country: String,
     username: String,
     password: Option<String>,
+    /// 会话文件加密
+    cipher: SecretCipher,
 }
 
 unsafe impl Send for MiCloud {}
@@ -277,7 +280,7 @@ unsafe impl Sync for MiCloud {}
 
 
 impl MiCloud {
-    pub async fn new(session_path: &str, username: String, password: Option<String>) -> anyhow::Result<Self> {
+    pub async fn new(session_path: &str, username: String, password: Option<String>, cipher: SecretCipher) -> anyhow::Result<Self> {
         let agent_id = Utils::get_random_agent_id();
         let useragent = format!("Android-7.1.1-1.0.0-ONEPLUS A3010-136-{} APP/xiaomi_1.smarthome APPV/62830", agent_id);
         let mut headers = header::HeaderMap::new();
@@ -286,16 +289,15 @@ impl MiCloud {
         let path_id = hex::encode(md5::compute(username.as_str()).to_vec());
         let cookie_path = format!("{}/cookie_{}.json", session_path, path_id);
         let info_path = PathBuf::from(format!("{}/info_{}.json", session_path, path_id));
-        let info = match tokio::fs::File::open(&info_path).await {
+        let (info, legacy) = match tokio::fs::File::open(&info_path).await {
             Ok(mut f) => {
                 let mut contents = String::new();
                 f.read_to_string(&mut contents).await?;
-                let info: serde_json::Result<Info> = serde_json::from_str(contents.as_str());
-                info.ok()
+                Self::parse_info(&cipher, contents.as_str())?
             }
-            Err(_) => None
+            Err(_) => (None, false)
         };
-        let state = CookieState::try_new(PathBuf::from(cookie_path))?;
+        let state = CookieState::try_new(PathBuf::from(cookie_path), cipher.clone())?;
         if info.is_none() {
             state.cookie_store.lock().unwrap().clear();
         };
@@ -313,7 +315,7 @@ impl MiCloud {
             .unwrap();
         //登入状态?
 
-        Ok(Self {
+        let cloud = Self {
             client,
             state,
             info_path,
@@ -321,11 +323,33 @@ impl MiCloud {
             country: "cn".to_string(),
             username,
             password,
-        })
+            cipher,
+        };
+        if legacy {
+            // 历史明文会话,重新加密保存
+            cloud.save_info().await?;
+            cloud.state.save()?;
+        }
+        Ok(cloud)
+    }
+
+    /// 解析会话文件,返回(会话,是否为明文)
+    fn parse_info(cipher: &SecretCipher, contents: &str) -> anyhow::Result<(Option<Info>, bool)> {
+        if SecretCipher::is_encrypted(contents) {
+            let plain = cipher.decrypt_str(contents)
+                .map_err(|e| anyhow!("米家会话文件解密失败:{}", e))?;
+            Ok((serde_json::from_str(plain.as_str()).ok(), false))
+        } else {
+            let info: Option<Info> = serde_json::from_str(contents).ok();
+            let legacy = info.is_some();
+            Ok((info, legacy))
+        }
     }
+
     pub async fn save_info(&self) -> anyhow::Result<()> {
         if let Some(info) = self.info.clone() {
             let info = serde_json::to_string(&info)?;
+            let info = self.cipher.encrypt_str(info.as_str());
             //创建文件夹
             let path = self.info_path.parent().unwrap();
             tokio::fs::create_dir_all(path).await?;
@@ -373,6 +397,16 @@ impl MiCloud {
         let str = r#"{"getVirtualModel":true,"getHuamiDevices":1,"get_split_device":false,"support_smart_home":true}"#;
         self.call_api("/home/device_list", str).await
     }
+    /// 获取蓝牙设备的 beaconkey,即加密广播的 bindkey
+    pub async fn get_beacon_key(&self, did: &str) -> anyhow::Result<String> {
+        let param = serde_json::json!({"did": did, "pdid": 1}).to_string();
+        let resp = self.call_api("/v2/device/blt_get_beaconkey", param.as_str()).await?;
+        resp.get("result")
+            .and_then(|r| r.get("beaconkey"))
+            .and_then(|k| k.as_str())
+            .map(|k| k.to_string())
+            .ok_or(anyhow!("获取beaconkey失败:{}", resp))
+    }
     pub async fn call_api(&self, url: &str, param_str: &str) -> anyhow::Result<serde_json::Value> {
         let api_url = self._get_api_url(url);
         let nonce = Utils::gen_nonce()?;
@@ -1,16 +1,18 @@
 use std::fs;
 use std::fs::{create_dir_all, File};
-use std::io::BufReader;
+use std::io::Write;
 use std::path::PathBuf;
 use std::sync::Arc;
 
 use log::{error, warn};
 use reqwest_cookie_store::{CookieStore, CookieStoreMutex};
+use crate::secret::SecretCipher;
 
-#[derive(Debug)]
 pub struct CookieState {
     cookie_store_path: PathBuf,
     pub cookie_store: Arc<CookieStoreMutex>,
+    /// cookie 中包含 serviceToken,加密保存
+    cipher: SecretCipher,
 }
 
 unsafe impl Send for CookieState {}
@@ -19,9 +21,9 @@ unsafe impl Sync for CookieState {}
 
 
 impl CookieState {
-    pub fn try_new(cookie_store_path: PathBuf) -> anyhow::Result<CookieState> {
-        let cookie_store = match File::open(&cookie_store_path) {
-            Ok(f) => CookieStore::load_json(BufReader::new(f)).map_err(|e| {
+    pub fn try_new(cookie_store_path: PathBuf, cipher: SecretCipher) -> anyhow::Result<CookieState> {
+        let cookie_store = match fs::read_to_string(&cookie_store_path) {
+            Ok(f) => Self::load(&cipher, f.as_str()).map_err(|e| {
                 let context = format!(
                     "error when read cookies from {}",
                     cookie_store_path.display()
@@ -42,8 +44,19 @@ impl CookieState {
         Ok(CookieState {
             cookie_store_path,
             cookie_store,
+            cipher,
         })
     }
+
+    fn load(cipher: &SecretCipher, contents: &str) -> anyhow::Result<CookieStore> {
+        let contents = if SecretCipher::is_encrypted(contents) {
+            cipher.decrypt_str(contents)?
+        } else {
+            contents.to_string()
+        };
+        CookieStore::load_json(contents.as_bytes())
+            .map_err(|e| anyhow::anyhow!("{}", e))
+    }
 }
 
 impl CookieState {
@@ -78,16 +91,19 @@ impl CookieState {
         } else { file.unwrap() };
 
 
+        let mut buf = vec![];
         {
             let store = self.cookie_store.lock().unwrap();
-            store.save_json(&mut file).map_err(|e| {
+            store.save_json(&mut buf).map_err(|e| {
                 let context = format!(
                     "error when save cookies to {}",
                     self.cookie_store_path.display()
                 );
                 anyhow::anyhow!("{}", e).context(context)
-            })
+            })?;
         }
+        file.write_all(self.cipher.encrypt(buf.as_slice()).as_bytes())?;
+        Ok(())
     }
 }
 
@@ -0,0 +1,246 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::anyhow;
+use log::debug;
+use tokio::sync::oneshot;
+
+use crate::proto::miio_proto::{MiotSpecDTO, MiotSpecId, MiotSpecProtocolPointer};
+
+/// 默认合并窗口
+pub const DEFAULT_BATCH_WINDOW: Duration = Duration::from_millis(10);
+
+type BatchResult = Result<Vec<MiotSpecDTO>, String>;
+
+struct Waiter {
+    ids: Vec<MiotSpecId>,
+    tx: oneshot::Sender<BatchResult>,
+}
+
+#[derive(Default)]
+struct PendingBatch {
+    ids: Vec<MiotSpecId>,
+    waiters: Vec<Waiter>,
+}
+
+impl PendingBatch {
+    fn push(&mut self, ids: Vec<MiotSpecId>, tx: oneshot::Sender<BatchResult>) {
+        for id in ids.iter() {
+            if !self.ids.contains(id) {
+                self.ids.push(*id);
+            }
+        }
+        self.waiters.push(Waiter { ids, tx });
+    }
+}
+
+/// 属性读取合并
+/// 窗口内同一设备的并发读取合并成一次 get_properties,
+/// 按协议的 max_properties 分批请求,结果分发给各个调用者
+pub struct PropertyBatcher {
+    window: Duration,
+    pending: Arc<Mutex<Option<PendingBatch>>>,
+}
+
+impl Default for PropertyBatcher {
+    fn default() -> Self {
+        Self::new(DEFAULT_BATCH_WINDOW)
+    }
+}
+
+impl PropertyBatcher {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            pending: Default::default(),
+        }
+    }
+
+    /// 读取属性,返回值与 ids 顺序一致
+    pub async fn get_properties(&self, proto: MiotSpecProtocolPointer, did: &str, ids: Vec<MiotSpecId>) -> anyhow::Result<Vec<MiotSpecDTO>> {
+        if ids.is_empty() {
+            return Ok(vec![]);
+        }
+        let (tx, rx) = oneshot::channel();
+        let first = {
+            let mut pending = self.pending.lock().unwrap();
+            let first = pending.is_none();
+            pending.get_or_insert_with(Default::default).push(ids, tx);
+            first
+        };
+        if first {
+            // 在独立任务中执行,调用者取消不影响其他等待者
+            let pending = self.pending.clone();
+            let window = self.window;
+            let did = did.to_string();
+            tokio::spawn(async move {
+                tokio::time::sleep(window).await;
+                let batch = pending.lock().unwrap().take();
+                if let Some(batch) = batch {
+                    execute(proto, did, batch).await;
+                }
+            });
+        }
+        rx.await
+            .map_err(|_| anyhow!("读取属性任务已取消"))?
+            .map_err(|e| anyhow!(e))
+    }
+}
+
+async fn execute(proto: MiotSpecProtocolPointer, did: String, batch: PendingBatch) {
+    let max = proto.max_properties().max(1);
+    debug!("合并读取属性 did:{},数量:{},调用者:{}", did, batch.ids.len(), batch.waiters.len());
+    let mut values = HashMap::new();
+    let mut errors = HashMap::new();
+    for chunk in batch.ids.chunks(max) {
+        let params = chunk.iter()
+            .map(|id| MiotSpecDTO { did: did.clone(), siid: id.siid, piid: id.piid, value: None })
+            .collect();
+        match proto.get_properties(params, None).await {
+            Ok(results) => {
+                for result in results {
+                    values.insert(MiotSpecId::new(result.siid, result.piid), result);
+                }
+            }
+            Err(e) => {
+                let msg = e.to_string();
+                for id in chunk {
+                    errors.insert(*id, msg.clone());
+                }
+            }
+        }
+    }
+    for waiter in batch.waiters {
+        let result = waiter.ids.iter()
+            .map(|id| match values.get(id) {
+                Some(v) => Ok(v.clone()),
+                None => Err(errors.get(id)
+                    .cloned()
+                    .unwrap_or("读取属性失败,返回值数量不匹配".to_string())),
+            })
+            .collect::<BatchResult>();
+        let _ = waiter.tx.send(result);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use anyhow::anyhow;
+    use serde_json::json;
+    use tokio::sync::broadcast;
+
+    use crate::device::common::batch::PropertyBatcher;
+    use crate::proto::miio_proto::{MiotSpecDTO, MiotSpecId, MiotSpecProtocol, MiotSpecProtocolPointer};
+    use crate::proto::protocol::JsonMessage;
+
+    /// 记录每次 get_properties 的参数, value 为 siid*100+piid
+    struct MockProto {
+        max: usize,
+        fail: bool,
+        calls: Mutex<Vec<Vec<MiotSpecId>>>,
+    }
+
+    #[async_trait::async_trait]
+    impl MiotSpecProtocol for MockProto {
+        fn incr_cmd_id(&self) -> u64 {
+            0
+        }
+
+        async fn request<'a>(&'a self, _id: u64, _cmd: &'a str, _timeout_val: Option<Duration>) -> anyhow::Result<JsonMessage> {
+            unimplemented!()
+        }
+
+        async fn send<'a>(&'a self, _cmd: &'a str) -> anyhow::Result<()> {
+            unimplemented!()
+        }
+
+        fn recv(&self) -> broadcast::Receiver<JsonMessage> {
+            unimplemented!()
+        }
+
+        async fn await_result<'a>(&'a self, _id: u64, _timeout_val: Option<Duration>) -> anyhow::Result<JsonMessage> {
+            unimplemented!()
+        }
+
+        async fn start_listen(&self) {}
+
+        fn max_properties(&self) -> usize {
+            self.max
+        }
+
+        async fn get_properties(&self, params: Vec<MiotSpecDTO>, _timeout_val: Option<Duration>) -> anyhow::Result<Vec<MiotSpecDTO>> {
+            self.calls.lock().unwrap().push(params.iter().map(|p| MiotSpecId::new(p.siid, p.piid)).collect());
+            if self.fail {
+                return Err(anyhow!("mock error"));
+            }
+            Ok(params.into_iter()
+                .map(|p| MiotSpecDTO { value: Some(json!(p.siid * 100 + p.piid)), ..p })
+                .collect())
+        }
+    }
+
+    fn mock(max: usize, fail: bool) -> (Arc<MockProto>, MiotSpecProtocolPointer) {
+        let proto = Arc::new(MockProto { max, fail, calls: Default::default() });
+        (proto.clone(), proto)
+    }
+
+    fn ids(list: &[(i32, i32)]) -> Vec<MiotSpecId> {
+        list.iter().map(|(s, p)| MiotSpecId::new(*s, *p)).collect()
+    }
+
+    fn values(results: &[MiotSpecDTO]) -> Vec<i32> {
+        results.iter().map(|r| r.value.as_ref().unwrap().as_i64().unwrap() as i32).collect()
+    }
+
+    #[tokio::test]
+    async fn test_coalesce() {
+        let (mock, proto) = mock(15, false);
+        let batcher = PropertyBatcher::default();
+        let (a, b, c) = tokio::join!(
+            batcher.get_properties(proto.clone(), "1", ids(&[(2, 1), (2, 2)])),
+            batcher.get_properties(proto.clone(), "1", ids(&[(2, 2), (3, 1)])),
+            batcher.get_properties(proto.clone(), "1", ids(&[(4, 1)])),
+        );
+        assert_eq!(values(&a.unwrap()), vec![201, 202]);
+        assert_eq!(values(&b.unwrap()), vec![202, 301]);
+        assert_eq!(values(&c.unwrap()), vec![401]);
+        // 合并成一次请求,重复的属性只读一次
+        let calls = mock.calls.lock().unwrap().clone();
+        assert_eq!(calls, vec![ids(&[(2, 1), (2, 2), (3, 1), (4, 1)])]);
+
+        // 窗口结束后的读取是新的请求
+        batcher.get_properties(proto, "1", ids(&[(2, 1)])).await.unwrap();
+        assert_eq!(mock.calls.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_split() {
+        let (mock, proto) = mock(2, false);
+        let batcher = PropertyBatcher::default();
+        let (a, b) = tokio::join!(
+            batcher.get_properties(proto.clone(), "1", ids(&[(2, 1), (2, 2), (2, 3)])),
+            batcher.get_properties(proto.clone(), "1", ids(&[(3, 1), (3, 2)])),
+        );
+        assert_eq!(values(&a.unwrap()), vec![201, 202, 203]);
+        assert_eq!(values(&b.unwrap()), vec![301, 302]);
+        let sizes: Vec<usize> = mock.calls.lock().unwrap().iter().map(|c| c.len()).collect();
+        assert_eq!(sizes, vec![2, 2, 1]);
+    }
+
+    #[tokio::test]
+    async fn test_error() {
+        let (mock, proto) = mock(15, true);
+        let batcher = PropertyBatcher::default();
+        let (a, b) = tokio::join!(
+            batcher.get_properties(proto.clone(), "1", ids(&[(2, 1)])),
+            batcher.get_properties(proto.clone(), "1", ids(&[(3, 1)])),
+        );
+        assert!(a.unwrap_err().to_string().contains("mock error"));
+        assert!(b.is_err());
+        assert_eq!(mock.calls.lock().unwrap().len(), 1);
+    }
+}
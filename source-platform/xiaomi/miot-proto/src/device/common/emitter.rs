@@ -29,6 +29,12 @@ pub enum MijiaEvent {
     SetProperty(MiotSpecDTO),
     /// 网关消息
     GatewayMsg(JsonMessage),
+    /// 设备事件上报(按键,门铃等)
+    EventOccurred {
+        siid: i32,
+        eiid: i32,
+        arguments: Vec<serde_json::Value>,
+    },
 }
 impl DeviceEvent for MijiaEvent {}
 
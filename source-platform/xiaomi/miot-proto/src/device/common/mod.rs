@@ -1,2 +1,4 @@
 pub mod utils;
 pub mod emitter;
+pub mod shadow;
+pub mod batch;
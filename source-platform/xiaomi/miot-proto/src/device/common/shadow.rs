@@ -0,0 +1,147 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+use serde_json::Value;
+use tokio::sync::RwLock;
+
+use crate::proto::miio_proto::{MiotSpecDTO, MiotSpecId};
+
+/// 影子中的属性值
+#[derive(Debug, Clone)]
+pub struct ShadowValue {
+    pub value: Value,
+    /// 最后一次从设备得到该值的时间
+    pub updated_at: Instant,
+}
+
+impl ShadowValue {
+    pub fn age(&self) -> Duration {
+        self.updated_at.elapsed()
+    }
+}
+
+/// 影子参数,设备参数中的 max_age(ms)
+#[derive(Debug, Default, Deserialize)]
+pub struct ShadowParam {
+    pub max_age: Option<u64>,
+}
+
+impl ShadowParam {
+    /// 读取参数中的 max_age, 不存在则使用默认值
+    pub fn max_age(params: &Value, default: Duration) -> Duration {
+        serde_json::from_value::<ShadowParam>(params.clone())
+            .ok()
+            .and_then(|p| p.max_age)
+            .map(Duration::from_millis)
+            .unwrap_or(default)
+    }
+}
+
+/// 设备影子
+/// 缓存设备属性,由轮询,上报,读取,写入更新
+/// hap 读取时 max_age 内的值直接返回,不再请求设备
+pub struct DeviceShadow {
+    values: RwLock<HashMap<MiotSpecId, ShadowValue>>,
+    max_age: Duration,
+}
+
+impl Default for DeviceShadow {
+    fn default() -> Self {
+        Self::new(Duration::ZERO)
+    }
+}
+
+impl DeviceShadow {
+    pub fn new(max_age: Duration) -> Self {
+        Self {
+            values: Default::default(),
+            max_age,
+        }
+    }
+
+    pub fn max_age(&self) -> Duration {
+        self.max_age
+    }
+
+    pub async fn get(&self, id: &MiotSpecId) -> Option<ShadowValue> {
+        self.values.read().await.get(id).cloned()
+    }
+
+    /// 未过期的值
+    pub async fn get_fresh(&self, id: &MiotSpecId) -> Option<Value> {
+        if self.max_age.is_zero() {
+            return None;
+        }
+        self.values.read().await
+            .get(id)
+            .filter(|v| v.age() <= self.max_age)
+            .map(|v| v.value.clone())
+    }
+
+    /// 更新属性,返回值发生变化的属性
+    pub async fn update(&self, results: Vec<MiotSpecDTO>) -> Vec<MiotSpecDTO> {
+        let now = Instant::now();
+        let mut changes = vec![];
+        let mut values = self.values.write().await;
+        for result in results {
+            let value = match result.value.as_ref() {
+                None => continue,
+                Some(v) => v.clone(),
+            };
+            let id = MiotSpecId::new(result.siid, result.piid);
+            let old = values.insert(id, ShadowValue {
+                value: value.clone(),
+                updated_at: now,
+            });
+            if old.map(|o| o.value) != Some(value) {
+                changes.push(result);
+            }
+        }
+        changes
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use serde_json::json;
+
+    use crate::device::common::shadow::{DeviceShadow, ShadowParam};
+    use crate::proto::miio_proto::{MiotSpecDTO, MiotSpecId};
+
+    fn dto(piid: i32, value: serde_json::Value) -> MiotSpecDTO {
+        MiotSpecDTO { did: "1".to_string(), siid: 2, piid, value: Some(value) }
+    }
+
+    #[tokio::test]
+    async fn test_shadow() {
+        let shadow = DeviceShadow::new(Duration::from_millis(100));
+        let id = MiotSpecId::new(2, 1);
+        assert!(shadow.get_fresh(&id).await.is_none());
+
+        // 首次写入算变化
+        let changes = shadow.update(vec![dto(1, json!(true)), dto(2, json!(50))]).await;
+        assert_eq!(changes.len(), 2);
+        assert_eq!(shadow.get_fresh(&id).await, Some(json!(true)));
+
+        // 值不变只刷新时间
+        let changes = shadow.update(vec![dto(1, json!(true)), dto(2, json!(60))]).await;
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].piid, 2);
+
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        assert!(shadow.get_fresh(&id).await.is_none());
+        assert_eq!(shadow.get(&id).await.map(|v| v.value), Some(json!(true)));
+
+        // max_age 为0 时不使用缓存
+        let shadow = DeviceShadow::default();
+        shadow.update(vec![dto(1, json!(true))]).await;
+        assert!(shadow.get_fresh(&id).await.is_none());
+
+        let default = Duration::from_secs(1);
+        assert_eq!(ShadowParam::max_age(&json!({"max_age": 500}), default), Duration::from_millis(500));
+        assert_eq!(ShadowParam::max_age(&json!(null), default), default);
+    }
+}
@@ -8,59 +8,38 @@ use hl_integration::event::events::DeviceEvent;
 use hl_integration::platform::hap::hap_device;
 use crate::device::common::emitter::MijiaEvent;
 use crate::device::miot_spec_device::{DeviceInfo, MiotSpecDevice};
-use crate::proto::miio_proto::{MiotSpecDTO, MiotSpecId};
+use crate::proto::miio_proto::MiotSpecId;
 use crate::proto::protocol::ExitError;
 
 
 pub async fn poll0<'a, T: MiotSpecDevice + Sync + Send>(dev: &'a T, did: &'a str) -> Result<(), ExitError> {
-    debug!("pool dev:{:?}",did);
-    let base = dev.get_base();
-    if base.emitter.is_empty() {
+    if dev.get_base().emitter.is_empty() {
         return Ok(());
     };
+    refresh_shadow(dev, did).await
+}
+
+/// 读取注册的属性更新影子,值变化时发布 PropertiesChanged 事件
+pub async fn refresh_shadow<'a, T: MiotSpecDevice + Sync + Send>(dev: &'a T, did: &'a str) -> Result<(), ExitError> {
+    debug!("pool dev:{:?}",did);
+    let base = dev.get_base();
     let proto = dev.get_proto().await?;
 
-    let params = base
+    let ids = base
         .poll_properties
         .read()
         .await.iter()
-        .map(|id| MiotSpecDTO {
-            did: did.to_string(),
-            siid: id.siid,
-            piid: id.piid,
-            value: None,
-        }).collect::<Vec<MiotSpecDTO>>();
-    if params.is_empty() {
+        .copied()
+        .collect::<Vec<MiotSpecId>>();
+    if ids.is_empty() {
         return Ok(());
     }
 
-    if let Ok(results) = proto.get_properties(params, None).await {
-        let mut updates = vec![];
-        for result in results {
-            //新旧值比较
-            let id = MiotSpecId::new(result.siid, result.piid);
-
-            if let Some(old_val) = base.value_map.read().await.get(&id) {
-                if let Some(val) = &result.value {
-                    if val == old_val {
-                        continue;
-                    } else {
-                        base.value_map.write().await.insert(id, val.clone());
-                        //发布单次更新事件
-                        // base.emitter.write().await.emit(EventType::UpdateProperty(result.clone())).await;
-                        updates.push(result);
-                    }
-                }
-            }
-        }
-        //发布批量更新事件
-        if !updates.is_empty() {
-            let event = Arc::new(MijiaEvent::PropertiesChanged(updates));
-            base.emitter.emit(event).await;
-        }
+    // 与 hap 读取合并,超过协议限制时分批读取
+    match base.batcher.get_properties(proto, did, ids).await {
+        Ok(results) => base.update_shadow(results).await,
+        Err(e) => base.retry_info.contact_err(e.to_string()),
     }
-
-
     Ok(())
 }
 
@@ -70,12 +49,15 @@ pub fn get_poll_func<'a, T: MiotSpecDevice + Sync + Send>(dev: &'a T, did: &'a s
 
 
     async move {
+        // 连接后先填充影子
+        if let Err(e) = refresh_shadow(dev, did).await {
+            error!("读取设备属性失败:{:?}",e);
+        }
         loop {
+            time::sleep(interval).await;
             if let Err(e) = poll0(dev, did).await {
                 error!("轮询数据失败:{:?}",e);
             }
-            // interval.tick().await;
-            time::sleep(interval).await;
         }
     }.boxed()
 }
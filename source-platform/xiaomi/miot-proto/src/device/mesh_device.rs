@@ -1,8 +1,11 @@
 use std::sync::Arc;
+use std::time::Duration;
+use serde_json::{Map, Value};
 use crate::device::common::emitter::MijiaEvent;
+use crate::device::common::shadow::DeviceShadow;
 
 use crate::device::miot_spec_device::{AsMiotDevice,  BaseMiotSpecDevice, DeviceInfo, MiotDeviceType, MiotSpecDevice, MiotSpecDeviceWrapper};
-use crate::proto::miio_proto::{MiotSpecDTO, MiotSpecId, MiotSpecProtocolPointer};
+use crate::proto::miio_proto::{MiotSpecDTO, MiotSpecProtocolPointer};
 use crate::proto::protocol::{ExitError};
 
 
@@ -34,55 +37,94 @@ impl<T: AsMiotDevice> MiotSpecDevice for MeshDeviceInner<T> {
             .as_miot_device()?
             .get_event_recv().await;
         //{"id":1996772508,"method":"properties_changed","params":[{"did":"1023054714","siid":2,"piid":1,"value":false}],"type":16}
+        //{"id":1996772509,"method":"event_occured","params":{"did":"1023054714","siid":3,"eiid":1,"arguments":[]},"type":16}
         while let Ok(MijiaEvent::GatewayMsg(msg)) = recv.recv().await {
             let mut data = msg.data;
-            if let Some(true) = data
-                .remove("method")
-                .map(|i| i.as_str() == Some("properties_changed")) {
-                if let Some(mut params) = data.remove("params") {
-                    if let Some(params) = params.as_array_mut() {
-                        let mut updates = vec![];
-                        for param in params {
-                            if let Some(param) = param.as_object_mut() {
-                                if Some(true) == param.remove("did")
-                                    .map(|f| f.as_str() == Some(self.info.did.as_str())) {
-                                    //判断属性
-                                    let siid = param.remove("siid").and_then(|f| f.as_i64());
-                                    let piid = param.remove("piid").and_then(|f| f.as_i64());
-                                    let value = param.remove("value");
-                                    if let (Some(siid), Some(piid), Some(value)) = (siid, piid, value) {
-                                        let id = MiotSpecId::new(siid as i32, piid as i32);
-                                        //更新属性
-                                        self.base.value_map.write().await.insert(id, value.clone());
-                                        let result = MiotSpecDTO {
-                                            did: self.info.did.clone(),
-                                            siid: siid as i32,
-                                            piid: piid as i32,
-                                            value: Some(value),
-                                        };
-                                        // self.base.emitter.write().await.emit(MijiaEvent::UpdateProperty(result.clone())).await;
-                                        updates.push(result);
-                                    }
-                                }
-                            }
-                        }
-                        if !updates.is_empty() {
-                            let event =Arc::new( MijiaEvent::PropertiesChanged(updates));
-                            self.base.emitter.emit(event).await;
-                        }
-                    };
-                }
+            let method = data.remove("method");
+            let params = data.remove("params");
+            match (method.as_ref().and_then(|m| m.as_str()), params) {
+                (Some("properties_changed"), Some(params)) => self.on_properties_changed(params).await,
+                (Some("event_occured"), Some(params)) => self.on_event_occurred(params).await,
+                _ => {}
             }
         }
         Ok(())
     }
 }
 
+impl<T: AsMiotDevice> MeshDeviceInner<T> {
+    fn is_self(&self, param: &mut Map<String, Value>) -> bool {
+        Some(true) == param.remove("did")
+            .map(|f| f.as_str() == Some(self.info.did.as_str()))
+    }
+
+    async fn on_properties_changed(&self, mut params: Value) {
+        let mut updates = vec![];
+        if let Some(params) = params.as_array_mut() {
+            for param in params {
+                if let Some(param) = param.as_object_mut() {
+                    if !self.is_self(param) {
+                        continue;
+                    }
+                    //判断属性
+                    let siid = param.remove("siid").and_then(|f| f.as_i64());
+                    let piid = param.remove("piid").and_then(|f| f.as_i64());
+                    let value = param.remove("value");
+                    if let (Some(siid), Some(piid), Some(value)) = (siid, piid, value) {
+                        updates.push(MiotSpecDTO {
+                            did: self.info.did.clone(),
+                            siid: siid as i32,
+                            piid: piid as i32,
+                            value: Some(value),
+                        });
+                    }
+                }
+            }
+        }
+        //更新影子,只发布变化的属性
+        self.base.update_shadow(updates).await;
+    }
+
+    /// 事件上报,params 可能是对象或数组
+    async fn on_event_occurred(&self, params: Value) {
+        let params = match params {
+            Value::Array(list) => list,
+            Value::Object(_) => vec![params],
+            _ => return,
+        };
+        for mut param in params {
+            if let Some(param) = param.as_object_mut() {
+                if !self.is_self(param) {
+                    continue;
+                }
+                let siid = param.remove("siid").and_then(|f| f.as_i64());
+                let eiid = param.remove("eiid").and_then(|f| f.as_i64());
+                let arguments = match param.remove("arguments") {
+                    Some(Value::Array(args)) => args,
+                    _ => vec![],
+                };
+                if let (Some(siid), Some(eiid)) = (siid, eiid) {
+                    let event = Arc::new(MijiaEvent::EventOccurred {
+                        siid: siid as i32,
+                        eiid: eiid as i32,
+                        arguments,
+                    });
+                    self.base.emitter.emit(event).await;
+                }
+            }
+        }
+    }
+}
+
 impl MeshDevice {
-    pub fn new_mesh_device<T: AsMiotDevice+ 'static>(info: DeviceInfo, gateway: T) -> MeshDevice {
+    /// max_age: 影子有效期,属性由网关上报更新
+    pub fn new_mesh_device<T: AsMiotDevice+ 'static>(info: DeviceInfo, gateway: T, max_age: Duration) -> MeshDevice {
         let inner = MeshDeviceInner {
             info,
-            base: Default::default(),
+            base: BaseMiotSpecDevice {
+                shadow: DeviceShadow::new(max_age),
+                ..Default::default()
+            },
             gateway,
         };
         MiotSpecDeviceWrapper(Box::new(inner), MiotDeviceType::Mesh)
@@ -1,11 +1,13 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::HashSet;
 use std::fmt::{Debug, Display, Formatter};
 use std::sync::Arc;
 
 use anyhow::anyhow;
+use log::debug;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use strum_macros::EnumString;
+use tap::TapFallible;
 use tokio::sync::{broadcast, RwLock};
 use hl_integration::error::DeviceExitError;
 
@@ -18,9 +20,11 @@ use hl_integration::platform::hap::hap_device;
 use hl_integration::platform::hap::hap_device::HapDevice;
 // use hl_integration::platform::hap::hap_device_ext::{AsHapDeviceExt, HapDeviceExt};
 
+use crate::device::common::batch::PropertyBatcher;
 use crate::device::common::emitter::{DataEmitter, DataListener, MijiaEvent};
+use crate::device::common::shadow::DeviceShadow;
 use crate::device::common::utils::get_hap_device_info;
-use crate::proto::miio_proto::{MiotSpecDTO, MiotSpecId, MiotSpecProtocolPointer};
+use crate::proto::miio_proto::{MiotActionDTO, MiotActionResult, MiotSetResult, MiotSpecDTO, MiotSpecId, MiotSpecProtocolPointer};
 use crate::proto::protocol::ExitError;
 
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, )]
@@ -68,8 +72,10 @@ pub struct BaseMiotSpecDevice {
     pub status: RwLock<DeviceStatus>,
     /// 注册轮询的属性
     pub poll_properties: Arc<RwLock<HashSet<MiotSpecId>>>,
-    /// 存储属性数据
-    pub value_map: Arc<RwLock<HashMap<MiotSpecId, serde_json::Value>>>,
+    /// 设备影子,存储属性数据
+    pub shadow: DeviceShadow,
+    /// 合并并发的属性读取
+    pub batcher: PropertyBatcher,
 
     pub tx: broadcast::Sender<MijiaEvent>,
     // Arc<RwLock<DataEmitter<EventType>>>
@@ -84,7 +90,8 @@ impl Default for BaseMiotSpecDevice {
         Self {
             status: RwLock::new(DeviceStatus::Running),
             poll_properties: Arc::new(RwLock::new(HashSet::new())),
-            value_map: Arc::new(Default::default()),
+            shadow: Default::default(),
+            batcher: Default::default(),
             emitter: DeviceEventEmitter::default(),
             tx,
             retry_info: Default::default(),
@@ -92,6 +99,36 @@ impl Default for BaseMiotSpecDevice {
     }
 }
 
+impl BaseMiotSpecDevice {
+    /// 更新影子,值变化时发布 PropertiesChanged 事件
+    /// 收到设备数据即视为通信成功
+    pub async fn update_shadow(&self, results: Vec<MiotSpecDTO>) {
+        self.retry_info.contact_ok();
+        let changes = self.shadow.update(results).await;
+        if !changes.is_empty() {
+            self.emitter.emit(Arc::new(MijiaEvent::PropertiesChanged(changes))).await;
+        }
+    }
+}
+
+/// 按设置结果拆分成功的属性和失败的属性及 code, 没有结果的属性视为失败
+fn split_set_results(params: Vec<MiotSpecDTO>, results: &[MiotSetResult]) -> (Vec<MiotSpecDTO>, Vec<(MiotSpecId, i32)>) {
+    let mut success = vec![];
+    let mut failed = vec![];
+    for param in params {
+        let code = results.iter()
+            .find(|r| r.siid == param.siid && r.piid == param.piid)
+            .map(|r| r.code)
+            .unwrap_or(-1);
+        if code == 0 {
+            success.push(param);
+        } else {
+            failed.push((MiotSpecId::new(param.siid, param.piid), code));
+        }
+    }
+    (success, failed)
+}
+
 #[derive(strum_macros::AsRefStr, EnumString, Debug, Clone, Copy)]
 pub enum MiotDeviceType {
     #[strum(serialize = "xiaomi_wifi")]
@@ -111,7 +148,26 @@ pub enum MiotDeviceType {
 #[cfg(test)]
 mod test {
     use std::str::FromStr;
-    use crate::device::miot_spec_device::MiotDeviceType;
+    use serde_json::json;
+    use crate::device::miot_spec_device::{MiotDeviceType, split_set_results};
+    use crate::proto::miio_proto::{MiotSetResult, MiotSpecDTO, MiotSpecId};
+
+    #[test]
+    pub fn test_split_set_results() {
+        let params = vec![
+            MiotSpecDTO::new("1".to_string(), 2, 1, Some(json!(true))),
+            MiotSpecDTO::new("1".to_string(), 2, 2, Some(json!(50))),
+            MiotSpecDTO::new("1".to_string(), 2, 3, Some(json!(1))),
+        ];
+        let results = vec![
+            MiotSetResult { did: "1".to_string(), siid: 2, piid: 1, code: 0 },
+            MiotSetResult { did: "1".to_string(), siid: 2, piid: 2, code: -4004 },
+        ];
+        let (success, failed) = split_set_results(params, &results);
+        assert_eq!(success.len(), 1);
+        assert_eq!(success[0].piid, 1);
+        assert_eq!(failed, vec![(MiotSpecId::new(2, 2), -4004), (MiotSpecId::new(2, 3), -1)]);
+    }
 
     #[test]
     pub fn test_strum() {
@@ -142,16 +198,38 @@ pub trait MiotSpecDevice: Sync + Send {
         let proto = self.get_proto()
             .await
             .map_err(Into::<anyhow::Error>::into)?;
-        proto.set_property(MiotSpecDTO { did, siid: spec_id.siid, piid: spec_id.piid, value: Some(value) }).await?;
+        let dto = MiotSpecDTO { did, siid: spec_id.siid, piid: spec_id.piid, value: Some(value) };
+        proto.set_property(dto.clone())
+            .await
+            .tap_err(|e| self.get_base().retry_info.contact_err(e.to_string()))?;
+        self.get_base().update_shadow(vec![dto]).await;
         Ok(())
     }
-    async fn set_properties(&self, params: Vec<(MiotSpecId, Value)>) -> anyhow::Result<Vec<MiotSpecDTO>> {
+    /// 批量设置属性,返回设置成功的属性和失败的属性及 code
+    async fn try_set_properties(&self, params: Vec<(MiotSpecId, Value)>) -> anyhow::Result<(Vec<MiotSpecDTO>, Vec<(MiotSpecId, i32)>)> {
         let did = self.get_info().did.clone();
         let proto = self.get_proto()
             .await
             .map_err(Into::<anyhow::Error>::into)?;
-        let params = params.into_iter().map(|id| MiotSpecDTO { did: did.clone(), siid: id.0.siid, piid: id.0.piid, value: Some(id.1) }).collect();
-        proto.set_properties(params, None).await
+        let params: Vec<MiotSpecDTO> = params.into_iter().map(|id| MiotSpecDTO { did: did.clone(), siid: id.0.siid, piid: id.0.piid, value: Some(id.1) }).collect();
+        let results = proto.set_properties(params.clone(), None)
+            .await
+            .tap_err(|e| self.get_base().retry_info.contact_err(e.to_string()))?;
+        let (success, failed) = split_set_results(params, &results);
+        // 只更新设置成功的属性
+        self.get_base().update_shadow(success.clone()).await;
+        Ok((success, failed))
+    }
+    async fn set_properties(&self, params: Vec<(MiotSpecId, Value)>) -> anyhow::Result<Vec<MiotSpecDTO>> {
+        let (success, failed) = self.try_set_properties(params).await?;
+        if !failed.is_empty() {
+            let msg = failed.iter()
+                .map(|(id, code)| format!("{}.{}:{}", id.siid, id.piid, code))
+                .collect::<Vec<String>>()
+                .join(",");
+            return Err(anyhow!("设置属性失败,siid.piid:code {}", msg));
+        }
+        Ok(success)
     }
     /// 读取设备属性
     async fn read_property(&self, siid: i32, piid: i32) -> anyhow::Result<Option<Value>> {
@@ -159,27 +237,75 @@ pub trait MiotSpecDevice: Sync + Send {
         let proto = self.get_proto()
             .await
             .map_err(Into::<anyhow::Error>::into)?;
-        let value = proto.get_property(MiotSpecDTO { did, siid, piid, value: None }).await?;
-        self.get_base().retry_info.reset().await;
-        Ok(value)
+        let mut results = self.get_base().batcher
+            .get_properties(proto, did.as_str(), vec![MiotSpecId::new(siid, piid)])
+            .await
+            .tap_err(|e| self.get_base().retry_info.contact_err(e.to_string()))?;
+        self.get_base().update_shadow(results.clone()).await;
+        Ok(results.remove(0).value)
     }
 
     async fn read_properties(&self, props: Vec<MiotSpecId>) -> anyhow::Result<Vec<MiotSpecDTO>> {
         let did = self.get_info().did.clone();
-        let props: Vec<MiotSpecDTO> = props.into_iter()
-            .map(|id| MiotSpecDTO { did: did.clone(), siid: id.siid, piid: id.piid, value: None }).collect();
-        let len = props.len();
         let proto = self.get_proto()
             .await
             .map_err(Into::<anyhow::Error>::into)?;
-        let value = proto.get_properties(props, None).await?;
-        if value.len() != len {
-            return Err(anyhow!("读取属性失败,返回值数量不匹配"));
-        }
-        self.get_base().retry_info.reset().await;
+        let value = self.get_base().batcher
+            .get_properties(proto, did.as_str(), props)
+            .await
+            .tap_err(|e| self.get_base().retry_info.contact_err(e.to_string()))?;
+        self.get_base().update_shadow(value.clone()).await;
         Ok(value)
     }
 
+    /// 优先从影子读取属性,过期的属性从设备读取
+    /// 设备读取失败时返回影子中过期的值
+    async fn read_properties_cached(&self, props: Vec<MiotSpecId>) -> anyhow::Result<Vec<MiotSpecDTO>> {
+        let did = self.get_info().did.clone();
+        let shadow = &self.get_base().shadow;
+        let mut results: Vec<Option<MiotSpecDTO>> = vec![None; props.len()];
+        let mut stale = vec![];
+        for (i, id) in props.iter().enumerate() {
+            match shadow.get_fresh(id).await {
+                Some(value) => {
+                    results[i] = Some(MiotSpecDTO { did: did.clone(), siid: id.siid, piid: id.piid, value: Some(value) });
+                }
+                None => stale.push((i, *id)),
+            }
+        }
+        if !stale.is_empty() {
+            match self.read_properties(stale.iter().map(|(_, id)| *id).collect()).await {
+                Ok(values) => {
+                    for ((i, _), value) in stale.into_iter().zip(values.into_iter()) {
+                        results[i] = Some(value);
+                    }
+                }
+                Err(e) => {
+                    for (i, id) in stale.into_iter() {
+                        let value = shadow.get(&id).await
+                            .ok_or(anyhow!("读取属性失败:{:?}", e))?;
+                        debug!("读取属性失败,使用{}ms前的值:{:?}", value.age().as_millis(), id);
+                        results[i] = Some(MiotSpecDTO { did: did.clone(), siid: id.siid, piid: id.piid, value: Some(value.value) });
+                    }
+                }
+            }
+        }
+        Ok(results.into_iter().flatten().collect())
+    }
+
+    /// 调用设备动作
+    async fn call_action(&self, siid: i32, aiid: i32, ins: Vec<Value>) -> anyhow::Result<MiotActionResult> {
+        let did = self.get_info().did.clone();
+        let proto = self.get_proto()
+            .await
+            .map_err(Into::<anyhow::Error>::into)?;
+        let result = proto.action(MiotActionDTO { did, siid, aiid, ins }, None)
+            .await
+            .tap_err(|e| self.get_base().retry_info.contact_err(e.to_string()))?;
+        self.get_base().retry_info.contact_ok();
+        Ok(result)
+    }
+
     async fn get_event_recv(&self) -> broadcast::Receiver<MijiaEvent> {
         self.get_base().tx.subscribe()
     }
@@ -301,12 +427,18 @@ impl MiotDeviceArc {
     pub async fn read_properties(&self, props: Vec<MiotSpecId>) -> anyhow::Result<Vec<MiotSpecDTO>> {
         self.as_miot_device()?.read_properties(props).await
     }
+    pub async fn read_properties_cached(&self, props: Vec<MiotSpecId>) -> anyhow::Result<Vec<MiotSpecDTO>> {
+        self.as_miot_device()?.read_properties_cached(props).await
+    }
     pub async fn read_property(&self, siid: i32, piid: i32) -> anyhow::Result<Option<Value>> {
         self.as_miot_device()?.read_property(siid, piid).await
     }
     pub async fn set_property(&self, spec_id: MiotSpecId, value: Value) -> anyhow::Result<()> {
         self.as_miot_device()?.set_property(spec_id, value).await
     }
+    pub async fn call_action(&self, siid: i32, aiid: i32, ins: Vec<Value>) -> anyhow::Result<MiotActionResult> {
+        self.as_miot_device()?.call_action(siid, aiid, ins).await
+    }
 }
 
 pub struct MiotDeviceBox {
@@ -11,6 +11,7 @@ use tokio::select;
 use tokio::sync::RwLock;
 use hl_integration::JsonValue;
 
+use crate::device::common::shadow::DeviceShadow;
 use crate::device::common::utils::get_poll_func;
 use crate::device::miot_spec_device::{BaseMiotSpecDevice, DeviceInfo, DeviceStatus, MiotDeviceType, MiotSpecDevice, MiotSpecDeviceWrapper};
 use crate::proto::miio_proto::{MiotSpecId, MiotSpecProtocolPointer};
@@ -105,6 +106,8 @@ pub struct WifiParam {
     pub interval: Option<u64>,
     pub timeout: Option<u64>,
     pub poll_properties: Vec<MiotSpecId>,
+    /// 影子有效期(ms),默认为轮询间隔
+    pub max_age: Option<u64>,
 }
 
 impl WifiDevice {
@@ -114,16 +117,21 @@ impl WifiDevice {
         let poll_properties = wifi_param.poll_properties
             .into_iter()
             .collect::<HashSet<MiotSpecId>>();
+        let interval = Duration::from_millis(wifi_param.interval
+            .map(|i| max(i, 200))
+            .unwrap_or(2000));
+        let max_age = wifi_param.max_age
+            .map(Duration::from_millis)
+            .unwrap_or(interval);
         let inner = WifiDeviceInner {
             base: BaseMiotSpecDevice {
                 poll_properties: Arc::new(RwLock::new(poll_properties)),
+                shadow: DeviceShadow::new(max_age),
                 ..std::default::Default::default()
             },
             info,
             proto: Arc::new(RwLock::new(None)),
-            interval: Duration::from_millis(wifi_param.interval
-                .map(|i| max(i, 200))
-                .unwrap_or(2000)),
+            interval,
 
             timeout: Duration::from_millis(wifi_param.timeout.unwrap_or(2_000)),
         };
@@ -2,4 +2,6 @@ pub mod proto;
 pub mod errors;
 pub mod device;
 pub mod cloud;
+pub mod secret;
+pub mod spec;
 mod utils;
@@ -0,0 +1,26 @@
+use std::time::Duration;
+
+use lazy_static::lazy_static;
+use prometheus::{HistogramVec, IntCounterVec, register_histogram_vec, register_int_counter_vec};
+
+lazy_static! {
+    /// rpc 调用次数
+    static ref RPC_REQUESTS: IntCounterVec = register_int_counter_vec!(
+        "homelink_miot_rpc_requests_total",
+        "miot rpc 调用次数",
+        &["transport", "method", "result"]
+    ).unwrap();
+    /// rpc 调用耗时
+    static ref RPC_DURATION: HistogramVec = register_histogram_vec!(
+        "homelink_miot_rpc_duration_seconds",
+        "miot rpc 调用耗时",
+        &["transport", "method"]
+    ).unwrap();
+}
+
+/// 记录一次rpc 调用
+pub fn observe_rpc(transport: &str, method: &str, elapsed: Duration, success: bool) {
+    let result = if success { "success" } else { "error" };
+    RPC_REQUESTS.with_label_values(&[transport, method, result]).inc();
+    RPC_DURATION.with_label_values(&[transport, method]).observe(elapsed.as_secs_f64());
+}
@@ -1,5 +1,5 @@
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use futures_util::future::join;
 use impl_new::New;
@@ -7,6 +7,7 @@ use log::{debug, info};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use tokio::sync::broadcast;
+use crate::proto::metrics::observe_rpc;
 use crate::proto::protocol::JsonMessage;
 
 // pub type MiotSpecProtocolPointer = Arc<Box<dyn MiotSpecProtocol + Send + Sync + 'static>>;
@@ -15,6 +16,9 @@ pub type MsgCallback = Box<dyn Fn(JsonMessage) + Send + Sync>;
 
 pub const METHOD_GET_PROPERTIES: &str = "get_properties";
 pub const METHOD_SET_PROPERTIES: &str = "set_properties";
+pub const METHOD_ACTION: &str = "action";
+/// 单次 get_properties 默认最多读取的属性数量
+pub const DEFAULT_MAX_PROPERTIES: usize = 15;
 
 /// 米家协议 发送和接收miio 指令
 #[async_trait::async_trait]
@@ -35,6 +39,16 @@ pub trait MiotSpecProtocol {
     /// 开始监听
     async fn start_listen(&self);
 
+    /// 传输方式名称,用于统计
+    fn transport_name(&self) -> &'static str {
+        "unknown"
+    }
+
+    /// 单次 get_properties 最多读取的属性数量,超出需分批
+    fn max_properties(&self) -> usize {
+        DEFAULT_MAX_PROPERTIES
+    }
+
 
     async fn get_property_timeout(&self, param: MiotSpecDTO, timeout_val: Option<Duration>) -> anyhow::Result<Option<Value>> {
         let mut values = self.get_properties(vec![param], timeout_val).await?;
@@ -51,13 +65,20 @@ pub trait MiotSpecProtocol {
         self.set_property_timeout(param, None).await
     }
     async fn set_property_timeout(&self, param: MiotSpecDTO, timeout_val: Option<Duration>) -> anyhow::Result<()> {
-        let mut values = self.set_properties(vec![param], timeout_val).await?;
-        values.remove(0);
-        Ok(())
+        let values = self.set_properties(vec![param], timeout_val).await?;
+        match values.first() {
+            Some(v) if v.code != 0 => Err(anyhow::anyhow!("设置属性失败,code:{}", v.code)),
+            _ => Ok(()),
+        }
     }
 
     /// 调用rpc
     async fn call_rpc(&self, method: &str, params: Vec<MiotSpecDTO>, timeout: Option<Duration>) -> anyhow::Result<JsonMessage> {
+        self.call_rpc_value(method, serde_json::to_value(params)?, timeout).await
+    }
+
+    /// 调用rpc,params 为任意json
+    async fn call_rpc_value(&self, method: &str, params: Value, timeout: Option<Duration>) -> anyhow::Result<JsonMessage> {
         let id = self.incr_cmd_id();
         let param = serde_json::json![{
             "id":id,
@@ -66,7 +87,10 @@ pub trait MiotSpecProtocol {
         }];
         let str = param.to_string();
         debug!("call_rpc:{}", str);
-        self.request(id, str.as_str(), timeout).await
+        let start = Instant::now();
+        let result = self.request(id, str.as_str(), timeout).await;
+        observe_rpc(self.transport_name(), method, start.elapsed(), result.is_ok());
+        result
         /*let sender = self.send(str.as_str());
         let recv = self.await_result(id, timeout);
         //同时进行
@@ -75,13 +99,13 @@ pub trait MiotSpecProtocol {
         r1*/
     }
 
-    async fn set_properties(&self, params: Vec<MiotSpecDTO>, timeout_val: Option<Duration>) -> anyhow::Result<Vec<MiotSpecDTO>> {
+    async fn set_properties(&self, params: Vec<MiotSpecDTO>, timeout_val: Option<Duration>) -> anyhow::Result<Vec<MiotSetResult>> {
         info!("set_properties value:{:?}", params);
         let mut result = self.call_rpc("set_properties", params, timeout_val).await?;
         let value = result.data
             .remove("result")
             .ok_or(anyhow::anyhow!("无result 节点"))?;
-        let miot_specs: Vec<MiotSpecDTO> = serde_json::from_value(value)?;
+        let miot_specs: Vec<MiotSetResult> = serde_json::from_value(value)?;
         //value 转成 MiotSpecDTO
 
         Ok(miot_specs)
@@ -95,6 +119,19 @@ pub trait MiotSpecProtocol {
         debug!("get_properties result:{:?}", miot_specs);
         Ok(miot_specs)
     }
+
+    /// 调用设备动作 siid,aiid
+    async fn action(&self, param: MiotActionDTO, timeout_val: Option<Duration>) -> anyhow::Result<MiotActionResult> {
+        info!("action param:{:?}", param);
+        let mut result = self.call_rpc_value(METHOD_ACTION, serde_json::to_value(param)?, timeout_val).await?;
+        let value = result.data.remove("result")
+            .ok_or(anyhow::anyhow!("action 无result"))?;
+        let result: MiotActionResult = serde_json::from_value(value)?;
+        if result.code != 0 {
+            return Err(anyhow::anyhow!("调用动作失败,code:{}", result.code));
+        }
+        Ok(result)
+    }
 }
 
 #[derive(Debug, Copy, Clone, Serialize, Deserialize, PartialEq, Eq, Hash, New)]
@@ -110,3 +147,33 @@ pub struct MiotSpecDTO {
     pub piid: i32,
     pub value: Option<Value>,
 }
+
+#[derive(Debug, Clone, Serialize, Deserialize, New)]
+pub struct MiotActionDTO {
+    pub did: String,
+    pub siid: i32,
+    pub aiid: i32,
+    /// 输入参数
+    #[serde(rename = "in", default)]
+    pub ins: Vec<Value>,
+}
+
+/// 设置属性结果, code 不为0时设置失败
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MiotSetResult {
+    pub did: String,
+    pub siid: i32,
+    pub piid: i32,
+    #[serde(default)]
+    pub code: i32,
+}
+
+/// 动作调用结果
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct MiotActionResult {
+    #[serde(default)]
+    pub code: i32,
+    /// 输出参数
+    #[serde(default)]
+    pub out: Vec<Value>,
+}
@@ -1,3 +1,4 @@
 pub mod miio_proto;
 pub mod protocol;
 pub mod transport;
+pub mod metrics;
@@ -1,14 +1,18 @@
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use anyhow::anyhow;
 use impl_new::New;
 use tokio::sync::broadcast::Receiver;
 use tokio::sync::RwLock;
 use tokio::time::timeout;
 use crate::cloud::MiCloud;
-use crate::proto::miio_proto::{METHOD_GET_PROPERTIES, METHOD_SET_PROPERTIES, MiotSpecDTO, MiotSpecProtocol};
+use serde_json::Value;
+use crate::proto::miio_proto::{METHOD_ACTION, METHOD_GET_PROPERTIES, METHOD_SET_PROPERTIES, MiotSpecProtocol};
+use crate::proto::metrics::observe_rpc;
 use crate::proto::protocol::JsonMessage;
 
+/// 云端 /miotspec/prop/get 单次读取的属性数量
+const CLOUD_MAX_PROPERTIES: usize = 50;
 
 #[derive(New)]
 pub struct CloudMiioProto {
@@ -42,12 +46,30 @@ impl MiotSpecProtocol for CloudMiioProto {
         todo!("can not start_listen")
     }
 
-    async fn call_rpc(&self, method: &str, params: Vec<MiotSpecDTO>, duration: Option<Duration>) -> anyhow::Result<JsonMessage> {
+    fn transport_name(&self) -> &'static str {
+        "CloudMiioProto"
+    }
+
+    /// 云端接口一次可读取更多属性
+    fn max_properties(&self) -> usize {
+        CLOUD_MAX_PROPERTIES
+    }
+
+    async fn call_rpc_value(&self, method: &str, params: Value, duration: Option<Duration>) -> anyhow::Result<JsonMessage> {
+        let start = Instant::now();
+        let result = self.call_cloud(method, params, duration).await;
+        observe_rpc(self.transport_name(), method, start.elapsed(), result.is_ok());
+        result
+    }
+}
+
+impl CloudMiioProto {
+    async fn call_cloud(&self, method: &str, params: Value, duration: Option<Duration>) -> anyhow::Result<JsonMessage> {
         timeout(duration.unwrap_or(self.timeout), async {
             let url = match method {
                 METHOD_GET_PROPERTIES => "/miotspec/prop/get",
                 METHOD_SET_PROPERTIES => "/miotspec/prop/set",
-                // /miotspec/action
+                METHOD_ACTION => "/miotspec/action",
                 _ => {
                     return Err(anyhow!("不支持的方法:{}", method));
                 }
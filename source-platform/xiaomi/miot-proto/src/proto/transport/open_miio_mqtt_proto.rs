@@ -66,6 +66,10 @@ impl MiotSpecProtocol for OpenMiIOMqttSpecProtocol {
         self.id.fetch_add(1, Ordering::SeqCst)
     }
 
+    fn transport_name(&self) -> &'static str {
+        "OpenMiIOMqttSpecProtocol"
+    }
+
     async fn request<'a>(&'a self, id: u64, cmd: &'a str, timeout_val: Option<Duration>) -> anyhow::Result<JsonMessage> {
         let mut rx = self.msg_sender.subscribe();
         self.send(cmd).await?;
@@ -1,3 +1,4 @@
+use std::net::Ipv4Addr;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
@@ -8,7 +9,7 @@ use async_trait::async_trait;
 use block_modes::{BlockMode, Cbc};
 use block_modes::block_padding::Pkcs7;
 use futures_util::{FutureExt, TryStreamExt};
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
 use serde_json::{Map, Value};
 use tap::TapFallible;
 use tokio::net::UdpSocket;
@@ -19,6 +20,9 @@ use crate::proto::miio_proto::MiotSpecProtocol;
 use crate::proto::protocol::{JsonMessage, Message, MessageHeader};
 use crate::utils::timestamp;
 
+const HELLO_PACKET: &str = "21310020ffffffffffffffffffffffffffffffffffffffffffffffffffffffff";
+pub const MIIO_PORT: u16 = 54321;
+
 /// udp 传输协议
 pub struct UdpMiotSpecProtocol {
     pub socket: Arc<UdpSocket>,
@@ -62,6 +66,29 @@ impl UdpMiotSpecProtocol {
         })
     }
 
+    pub fn device_id(&self) -> u32 {
+        self.device_id
+    }
+
+    /// 握手并调用 miIO.info, 用于手动添加设备时校验ip和token
+    /// 返回设备id 和 miIO.info 结果
+    pub async fn probe(ip: &str, token: [u8; 16], timeout_val: Duration) -> anyhow::Result<(u32, Value)> {
+        let proto = timeout(timeout_val, Self::new(ip, MIIO_PORT as u32, token, timeout_val))
+            .await
+            .map_err(|_| anyhow!("设备:{}握手超时", ip))??;
+        let proto = Arc::new(proto);
+        let listener = proto.clone();
+        let handle = tokio::spawn(async move {
+            listener.start_listen().await;
+        });
+        let res = proto.call_rpc_value("miIO.info", Value::Array(vec![]), None).await;
+        handle.abort();
+        let mut msg = res.map_err(|e| anyhow!("miIO.info 调用失败,请检查token:{}", e))?;
+        let info = msg.data.remove("result")
+            .ok_or(anyhow!("miIO.info 返回数据错误:{:?}", msg.data))?;
+        Ok((proto.device_id, info))
+    }
+
     pub(crate) async fn build_message(&self, cmd: &str) -> anyhow::Result<Message> {
         let data = if cmd.is_empty() {
             vec![]
@@ -83,7 +110,7 @@ impl UdpMiotSpecProtocol {
     /// 握手
     /// 扫描设备
     pub async fn discover(socket: &UdpSocket, timeout: Duration) -> anyhow::Result<Message> {
-        let helle_bytes = hex::decode("21310020ffffffffffffffffffffffffffffffffffffffffffffffffffffffff").unwrap();
+        let helle_bytes = hex::decode(HELLO_PACKET).unwrap();
         //todo 广播
         for _ in 0..1 {
             // socket.send_to(helle_bytes.as_slice(), &addr).await?;
@@ -97,6 +124,80 @@ impl UdpMiotSpecProtocol {
         let msg = Message::parse(&buf[..size]).unwrap();
         Ok(msg)
     }
+
+    /// 局域网广播hello 包,收集响应的设备
+    pub async fn scan(timeout: Duration) -> anyhow::Result<Vec<DiscoveredDevice>> {
+        let helle_bytes = hex::decode(HELLO_PACKET).unwrap();
+        let socket = UdpSocket::bind("0.0.0.0:0").await?;
+        socket.set_broadcast(true)?;
+        let addrs = broadcast_addrs();
+        // udp 可能丢包,多发几次
+        for _ in 0..3 {
+            for addr in addrs.iter() {
+                if let Err(e) = socket.send_to(helle_bytes.as_slice(), (*addr, MIIO_PORT)).await {
+                    debug!("广播hello 包到:{}失败:{:?}", addr, e);
+                }
+            }
+        }
+        let mut devices: Vec<DiscoveredDevice> = vec![];
+        let mut buf = [0u8; 1024];
+        let deadline = tokio::time::Instant::now() + timeout;
+        while let Ok(res) = tokio::time::timeout_at(deadline, socket.recv_from(&mut buf)).await {
+            let (size, from) = res?;
+            if size < 32 {
+                continue;
+            }
+            let msg = match Message::parse(&buf[..size]) {
+                Ok(msg) => msg,
+                Err(e) => {
+                    debug!("hello 包解析失败:{:?},{:?}", from, e);
+                    continue;
+                }
+            };
+            let did = msg.header.device_id;
+            if did == u32::MAX || devices.iter().any(|d| d.did == did) {
+                continue;
+            }
+            devices.push(DiscoveredDevice {
+                did,
+                ip: from.ip().to_string(),
+                stamp: msg.header.stamp,
+            });
+        }
+        info!("局域网扫描到设备:{}个", devices.len());
+        Ok(devices)
+    }
+}
+
+/// 受限广播和每个网卡的定向广播地址,多网卡时受限广播只会从默认网卡发出
+fn broadcast_addrs() -> Vec<Ipv4Addr> {
+    let mut addrs = vec![Ipv4Addr::BROADCAST];
+    let interfaces = if_addrs::get_if_addrs()
+        .tap_err(|e| warn!("获取网卡地址失败:{:?}", e))
+        .unwrap_or_default();
+    for interface in interfaces.into_iter().filter(|i| !i.is_loopback()) {
+        if let if_addrs::IfAddr::V4(v4) = interface.addr {
+            let addr = v4.broadcast.unwrap_or(directed_broadcast(v4.ip, v4.netmask));
+            if !addrs.contains(&addr) {
+                addrs.push(addr);
+            }
+        }
+    }
+    addrs
+}
+
+/// 定向广播地址, 主机位全为1
+fn directed_broadcast(ip: Ipv4Addr, netmask: Ipv4Addr) -> Ipv4Addr {
+    Ipv4Addr::from(u32::from(ip) | !u32::from(netmask))
+}
+
+/// 局域网扫描到的设备
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct DiscoveredDevice {
+    pub did: u32,
+    pub ip: String,
+    /// 设备运行时间戳
+    pub stamp: u32,
 }
 
 #[async_trait]
@@ -158,6 +259,10 @@ impl MiotSpecProtocol for UdpMiotSpecProtocol {
         res
     }
 
+    fn transport_name(&self) -> &'static str {
+        "UdpMiotSpecProtocol"
+    }
+
     async fn start_listen(&self) {
         let mut buf = [0u8; 65535];
         let sender = self.msg_sender.clone();
@@ -217,3 +322,18 @@ impl Utils {
         cipher.encrypt_vec(payload)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::net::Ipv4Addr;
+
+    use crate::proto::transport::udp_iot_spec_proto::directed_broadcast;
+
+    #[test]
+    fn test_directed_broadcast() {
+        let ip = Ipv4Addr::new(192, 168, 31, 20);
+        assert_eq!(directed_broadcast(ip, Ipv4Addr::new(255, 255, 255, 0)), Ipv4Addr::new(192, 168, 31, 255));
+        assert_eq!(directed_broadcast(ip, Ipv4Addr::new(255, 255, 240, 0)), Ipv4Addr::new(192, 168, 31, 255));
+        assert_eq!(directed_broadcast(Ipv4Addr::new(10, 1, 2, 3), Ipv4Addr::new(255, 0, 0, 0)), Ipv4Addr::new(10, 255, 255, 255));
+    }
+}
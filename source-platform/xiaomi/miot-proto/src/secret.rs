@@ -0,0 +1,109 @@
+use aes::Aes256;
+use anyhow::anyhow;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use block_modes::{BlockMode, Cbc};
+use block_modes::block_padding::Pkcs7;
+use crypto::digest::Digest;
+use crypto::hmac::Hmac;
+use crypto::mac::{Mac, MacResult};
+use crypto::sha2::Sha256;
+use rand::Rng;
+
+/// 加密数据前缀,用于区分历史明文数据
+const ENC_PREFIX: &str = "enc1:";
+const IV_LEN: usize = 16;
+const TAG_LEN: usize = 32;
+
+/// 使用主密钥加密落盘的敏感数据(账号密码,米家会话)
+/// aes-256-cbc + hmac-sha256, 格式: enc1:base64(iv|密文|tag)
+#[derive(Clone)]
+pub struct SecretCipher {
+    enc_key: [u8; 32],
+    mac_key: [u8; 32],
+}
+
+fn sha256(parts: &[&[u8]]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    for p in parts {
+        hasher.input(p);
+    }
+    let mut buf = [0u8; 32];
+    hasher.result(&mut buf);
+    buf
+}
+
+impl SecretCipher {
+    pub fn new(master_key: &str) -> anyhow::Result<Self> {
+        if master_key.len() < 8 {
+            return Err(anyhow!("主密钥长度不能小于8位"));
+        }
+        Ok(Self {
+            enc_key: sha256(&[b"homelink-enc:", master_key.as_bytes()]),
+            mac_key: sha256(&[b"homelink-mac:", master_key.as_bytes()]),
+        })
+    }
+
+    /// 是否是已加密的数据
+    pub fn is_encrypted(data: &str) -> bool {
+        data.starts_with(ENC_PREFIX)
+    }
+
+    fn tag(&self, data: &[u8]) -> MacResult {
+        let mut hmac = Hmac::new(Sha256::new(), &self.mac_key);
+        hmac.input(data);
+        hmac.result()
+    }
+
+    pub fn encrypt(&self, plain: &[u8]) -> String {
+        let iv: [u8; IV_LEN] = rand::thread_rng().gen();
+        let cipher = Cbc::<Aes256, Pkcs7>::new_from_slices(&self.enc_key, &iv).unwrap();
+        let mut buf = iv.to_vec();
+        buf.extend(cipher.encrypt_vec(plain));
+        let tag = self.tag(buf.as_slice());
+        buf.extend_from_slice(tag.code());
+        format!("{}{}", ENC_PREFIX, STANDARD.encode(buf))
+    }
+
+    pub fn decrypt(&self, data: &str) -> anyhow::Result<Vec<u8>> {
+        let data = data.strip_prefix(ENC_PREFIX)
+            .ok_or(anyhow!("数据未加密"))?;
+        let buf = STANDARD.decode(data.trim())?;
+        if buf.len() < IV_LEN + TAG_LEN {
+            return Err(anyhow!("加密数据长度错误"));
+        }
+        let (body, tag) = buf.split_at(buf.len() - TAG_LEN);
+        if self.tag(body) != MacResult::new(tag) {
+            return Err(anyhow!("解密失败,主密钥错误或数据被篡改"));
+        }
+        let (iv, ciphertext) = body.split_at(IV_LEN);
+        let cipher = Cbc::<Aes256, Pkcs7>::new_from_slices(&self.enc_key, iv)?;
+        let plain = cipher.decrypt_vec(ciphertext)
+            .map_err(|_| anyhow!("解密失败,主密钥错误"))?;
+        Ok(plain)
+    }
+
+    pub fn encrypt_str(&self, plain: &str) -> String {
+        self.encrypt(plain.as_bytes())
+    }
+
+    pub fn decrypt_str(&self, data: &str) -> anyhow::Result<String> {
+        Ok(String::from_utf8(self.decrypt(data)?)?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::secret::SecretCipher;
+
+    #[test]
+    fn test_encrypt_decrypt() {
+        let cipher = SecretCipher::new("master-key-for-test").unwrap();
+        let enc = cipher.encrypt_str("password");
+        assert!(SecretCipher::is_encrypted(enc.as_str()));
+        assert_eq!(cipher.decrypt_str(enc.as_str()).unwrap(), "password");
+
+        let other = SecretCipher::new("another-master-key").unwrap();
+        assert!(other.decrypt_str(enc.as_str()).is_err());
+    }
+}
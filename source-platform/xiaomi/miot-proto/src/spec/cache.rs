@@ -0,0 +1,91 @@
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+use log::info;
+use serde::Deserialize;
+
+use crate::spec::MiotSpec;
+
+const SPEC_INSTANCES_URL: &str = "https://miot-spec.org/miot-spec-v2/instances?status=all";
+const SPEC_INSTANCE_URL: &str = "https://miot-spec.org/miot-spec-v2/instance";
+
+#[derive(Debug, Deserialize)]
+struct SpecInstance {
+    model: String,
+    #[serde(rename = "type")]
+    urn: String,
+    #[serde(default)]
+    version: i32,
+    #[serde(default)]
+    status: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct SpecInstances {
+    instances: Vec<SpecInstance>,
+}
+
+/// spec 本地缓存,缓存目录下 {model}.json
+/// 缓存不存在时从 miot-spec.org 下载
+pub struct MiotSpecCache {
+    dir: PathBuf,
+    client: reqwest::Client,
+}
+
+impl MiotSpecCache {
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    fn file_path(&self, model: &str) -> anyhow::Result<PathBuf> {
+        if model.is_empty() || model.contains(['/', '\\']) || model.starts_with('.') {
+            return Err(anyhow!("model:{}格式错误", model));
+        }
+        Ok(self.dir.join(format!("{}.json", model)))
+    }
+
+    /// 读取缓存
+    pub async fn load(&self, model: &str) -> anyhow::Result<Option<MiotSpec>> {
+        let path = self.file_path(model)?;
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let text = tokio::fs::read_to_string(path).await?;
+        Ok(Some(serde_json::from_str(text.as_str())?))
+    }
+
+    /// 读取缓存,不存在则下载
+    pub async fn get(&self, model: &str) -> anyhow::Result<MiotSpec> {
+        if let Some(spec) = self.load(model).await? {
+            return Ok(spec);
+        }
+        let text = self.download(model).await?;
+        let spec: MiotSpec = serde_json::from_str(text.as_str())?;
+        tokio::fs::create_dir_all(self.dir.as_path()).await?;
+        tokio::fs::write(self.file_path(model)?, text).await?;
+        Ok(spec)
+    }
+
+    async fn download(&self, model: &str) -> anyhow::Result<String> {
+        let instances: SpecInstances = self.client.get(SPEC_INSTANCES_URL)
+            .send().await?
+            .error_for_status()?
+            .json().await?;
+        // 优先已发布的最新版本
+        let instance = instances.instances
+            .into_iter()
+            .filter(|i| i.model == model)
+            .max_by_key(|i| (i.status == "released", i.version))
+            .ok_or(anyhow!("model:{}未找到spec", model))?;
+        info!("下载spec:{}", instance.urn);
+        let text = self.client.get(SPEC_INSTANCE_URL)
+            .query(&[("type", instance.urn.as_str())])
+            .send().await?
+            .error_for_status()?
+            .text().await?;
+        Ok(text)
+    }
+}
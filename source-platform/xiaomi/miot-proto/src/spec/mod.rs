@@ -0,0 +1,150 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+pub mod cache;
+
+/// 从 urn 中取出类型名称
+/// urn:miot-spec-v2:property:on:00000006:yeelink-lamp22:1 -> on
+pub fn urn_name(urn: &str) -> &str {
+    urn.split(':').nth(3).unwrap_or("")
+}
+
+/// miot spec 设备描述
+/// https://miot-spec.org/miot-spec-v2/instance?type=
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MiotSpec {
+    #[serde(rename = "type")]
+    pub urn: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub services: Vec<MiotSpecService>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MiotSpecService {
+    pub iid: i32,
+    #[serde(rename = "type")]
+    pub urn: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub properties: Vec<MiotSpecProperty>,
+    #[serde(default)]
+    pub actions: Vec<MiotSpecAction>,
+    #[serde(default)]
+    pub events: Vec<MiotSpecEvent>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MiotSpecValueItem {
+    pub value: Value,
+    #[serde(default)]
+    pub description: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MiotSpecProperty {
+    pub iid: i32,
+    #[serde(rename = "type")]
+    pub urn: String,
+    #[serde(default)]
+    pub description: String,
+    /// bool,uint8,int32,float,string...
+    pub format: String,
+    /// read,write,notify
+    #[serde(default)]
+    pub access: Vec<String>,
+    pub unit: Option<String>,
+    /// [min,max,step]
+    #[serde(rename = "value-range")]
+    pub value_range: Option<Vec<f64>>,
+    #[serde(rename = "value-list", default)]
+    pub value_list: Vec<MiotSpecValueItem>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MiotSpecAction {
+    pub iid: i32,
+    #[serde(rename = "type")]
+    pub urn: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(rename = "in", default)]
+    pub ins: Vec<i32>,
+    #[serde(default)]
+    pub out: Vec<i32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MiotSpecEvent {
+    pub iid: i32,
+    #[serde(rename = "type")]
+    pub urn: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub arguments: Vec<i32>,
+}
+
+impl MiotSpec {
+    pub fn name(&self) -> &str {
+        urn_name(self.urn.as_str())
+    }
+}
+
+impl MiotSpecService {
+    pub fn name(&self) -> &str {
+        urn_name(self.urn.as_str())
+    }
+
+    pub fn get_property(&self, name: &str) -> Option<&MiotSpecProperty> {
+        self.properties.iter().find(|p| p.name() == name)
+    }
+}
+
+impl MiotSpecProperty {
+    pub fn name(&self) -> &str {
+        urn_name(self.urn.as_str())
+    }
+    pub fn readable(&self) -> bool {
+        self.access.iter().any(|a| a == "read")
+    }
+    pub fn writable(&self) -> bool {
+        self.access.iter().any(|a| a == "write")
+    }
+    pub fn notify(&self) -> bool {
+        self.access.iter().any(|a| a == "notify")
+    }
+    pub fn min_value(&self) -> Option<f64> {
+        self.value_range.as_ref().and_then(|r| r.first().copied())
+    }
+    pub fn max_value(&self) -> Option<f64> {
+        self.value_range.as_ref().and_then(|r| r.get(1).copied())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::spec::MiotSpec;
+
+    #[test]
+    fn test_parse_spec() {
+        let text = r#"{"type":"urn:miot-spec-v2:device:light:0000A001:yeelink-lamp22:1","description":"Light","services":[
+            {"iid":2,"type":"urn:miot-spec-v2:service:light:00007802:yeelink-lamp22:1","description":"Light","properties":[
+                {"iid":1,"type":"urn:miot-spec-v2:property:on:00000006:yeelink-lamp22:1","description":"Switch Status","format":"bool","access":["read","write","notify"]},
+                {"iid":2,"type":"urn:miot-spec-v2:property:brightness:0000000D:yeelink-lamp22:1","description":"Brightness","format":"uint8","access":["read","write","notify"],"unit":"percentage","value-range":[1,100,1]},
+                {"iid":5,"type":"urn:miot-spec-v2:property:mode:00000008:yeelink-lamp22:1","description":"Mode","format":"uint8","access":["read","write"],"value-list":[{"value":5,"description":"Reading"}]}
+            ]}
+        ]}"#;
+        let spec: MiotSpec = serde_json::from_str(text).unwrap();
+        assert_eq!(spec.name(), "light");
+        let svc = &spec.services[0];
+        assert_eq!(svc.name(), "light");
+        let brightness = svc.get_property("brightness").unwrap();
+        assert_eq!(brightness.iid, 2);
+        assert_eq!(brightness.max_value(), Some(100.0));
+        assert!(brightness.writable());
+        assert_eq!(svc.get_property("mode").unwrap().value_list.len(), 1);
+    }
+}
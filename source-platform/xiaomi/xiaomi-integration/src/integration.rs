@@ -19,6 +19,8 @@ impl HlSourceIntegrator for XiaomiIntegration {
         database.insert("common.mode_switch".to_string(), models::common::mode_switch::ModelExt::new)?;
         database.insert("common.virtual".to_string(), models::common::hl_virtual::ModelExt::new)?;
         database.insert("common.miot_spec_prop_mapping".to_string(), models::common::miot_spec_prop_mapping::ModelExt::new)?;
+        database.insert("common.miot_spec_action_mapping".to_string(), models::common::miot_spec_action_mapping::ModelExt::new)?;
+        database.insert("common.miot_spec_event_switch".to_string(), models::common::miot_spec_event_switch::ModelExt::new)?;
         database.insert("common.ble_value_mapping".to_string(), models::common::ble_value_mapping::ModelExt::new)?;
         // model_map.insert("common.native_ble".to_string(), common::native_ble::ModelExt::new);
         database.insert("lumi.acpartner.mcn02".to_string(), models::lumi::lumi_acpartner_mcn02::ModelExt::new)?;
@@ -0,0 +1,110 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use log::{info, warn};
+
+use hl_integration::JsonValue;
+use miot_proto::device::miot_spec_device::MiotDeviceArc;
+use target_hap::delegate::{CharReadParam, CharReadResult, CharUpdateParam, CharUpdateResult};
+use target_hap::delegate::model::{AccessoryModelExtConstructor, ContextPointer, HapModelExt, HapModelExtPointer, ReadValueResult, UpdateValueResult};
+use target_hap::hap_type_wrapper::HapTypeWrapper;
+use target_hap::types::CharIdentifier;
+
+fn default_str() -> String {
+    "default".to_string()
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct ActionMappingParam {
+    #[serde(default = "default_str")]
+    stag: String,
+    ctag: HapTypeWrapper,
+    siid: i32,
+    aiid: i32,
+    /// 动作输入参数
+    #[serde(rename = "in", default)]
+    ins: Vec<JsonValue>,
+    /// 触发值,为空时任意写入都会触发
+    trigger: Option<JsonValue>,
+    /// 读取时返回的值,例如开关返回false
+    idle: Option<JsonValue>,
+}
+
+/// 动作映射模型
+/// 写入特征值时调用设备的动作,用于无状态开关等
+pub struct ModelExt {
+    dev: MiotDeviceArc,
+    mapping: HashMap<CharIdentifier, ActionMappingParam>,
+}
+
+
+impl AccessoryModelExtConstructor for ModelExt {
+    fn new(ctx: ContextPointer, params: Option<JsonValue>) -> anyhow::Result<HapModelExtPointer> {
+        let params = params.ok_or(anyhow!("action mapping params is none"))?;
+        let params: Vec<ActionMappingParam> = serde_json::from_value(params)?;
+        let mut mapping = HashMap::new();
+        for param in params {
+            let cid = CharIdentifier::new(param.stag.clone(), param.ctag);
+            mapping.insert(cid, param);
+        }
+        let dev = MiotDeviceArc(ctx.dev.clone());
+        Ok(Arc::new(Self {
+            dev,
+            mapping,
+        }))
+    }
+}
+
+
+#[async_trait::async_trait]
+impl HapModelExt for ModelExt {
+    async fn read_chars_value(&self, params: Vec<CharReadParam>) -> ReadValueResult {
+        let mut result = vec![];
+        for param in params.into_iter() {
+            let cid = CharIdentifier::new(param.stag.clone(), param.ctag);
+            let value = self.mapping.get(&cid)
+                .and_then(|m| m.idle.clone());
+            result.push(CharReadResult::success(&param, value));
+        }
+        Ok(result)
+    }
+
+    async fn update_chars_value(&self, params: Vec<CharUpdateParam>) -> UpdateValueResult {
+        let mut result = vec![];
+        for param in params.into_iter() {
+            let cid = CharIdentifier::new(param.stag.clone(), param.ctag);
+            let mapping = match self.mapping.get(&cid) {
+                None => {
+                    warn!("no action mapping for stag:{:?},ctag:{:?}", param.stag, param.ctag);
+                    result.push(CharUpdateResult {
+                        cid: param.cid,
+                        success: false,
+                    });
+                    continue;
+                }
+                Some(m) => m,
+            };
+            let triggered = match &mapping.trigger {
+                None => true,
+                Some(v) => *v == param.new_value,
+            };
+            // 单个动作失败只影响对应的特征
+            let success = !triggered || match self.dev.call_action(mapping.siid, mapping.aiid, mapping.ins.clone()).await {
+                Ok(res) => {
+                    info!("call action siid:{},aiid:{},result:{:?}", mapping.siid, mapping.aiid, res);
+                    res.code == 0
+                }
+                Err(e) => {
+                    warn!("call action siid:{},aiid:{} error:{:?}", mapping.siid, mapping.aiid, e);
+                    false
+                }
+            };
+            result.push(CharUpdateResult {
+                cid: param.cid,
+                success,
+            });
+        }
+        Ok(result)
+    }
+}
@@ -0,0 +1,97 @@
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use log::{debug, warn};
+use serde_json::json;
+
+use hl_integration::event::events::DeviceEventPointer;
+use hl_integration::JsonValue;
+use miot_proto::device::common::emitter::MijiaEvent;
+use target_hap::delegate::{CharReadParam, CharReadResult, CharUpdateParam, CharUpdateResult};
+use target_hap::delegate::model::{AccessoryModelExtConstructor, ContextPointer, HapModelExt, HapModelExtPointer, ReadValueResult, UpdateValueResult};
+use target_hap::hap::HapType;
+
+fn default_str() -> String {
+    "default".to_string()
+}
+
+/// 按键类型,对应 ProgrammableSwitchEvent 的值
+#[derive(Debug, Clone, Copy, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PressType {
+    Single,
+    Double,
+    Long,
+}
+
+impl PressType {
+    fn value(&self) -> u8 {
+        match self {
+            PressType::Single => 0,
+            PressType::Double => 1,
+            PressType::Long => 2,
+        }
+    }
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct EventMappingParam {
+    /// 无状态开关服务的tag
+    #[serde(default = "default_str")]
+    stag: String,
+    siid: i32,
+    eiid: i32,
+    press: PressType,
+}
+
+/// 事件映射模型
+/// 将设备上报的事件(event_occured)映射为 StatelessProgrammableSwitch 的单击/双击/长按
+pub struct ModelExt {
+    ctx: ContextPointer,
+    mapping: Vec<EventMappingParam>,
+}
+
+impl AccessoryModelExtConstructor for ModelExt {
+    fn new(ctx: ContextPointer, params: Option<JsonValue>) -> anyhow::Result<HapModelExtPointer> {
+        let params = params.ok_or(anyhow!("event switch params is none"))?;
+        let mapping: Vec<EventMappingParam> = serde_json::from_value(params)?;
+        Ok(Arc::new(Self {
+            ctx,
+            mapping,
+        }))
+    }
+}
+
+#[async_trait::async_trait]
+impl HapModelExt for ModelExt {
+    async fn read_chars_value(&self, params: Vec<CharReadParam>) -> ReadValueResult {
+        // 无状态开关读取时返回空
+        Ok(params.iter()
+            .map(|param| CharReadResult::success(param, None))
+            .collect())
+    }
+
+    async fn update_chars_value(&self, params: Vec<CharUpdateParam>) -> UpdateValueResult {
+        Ok(params.into_iter()
+            .map(|param| CharUpdateResult {
+                cid: param.cid,
+                success: false,
+            })
+            .collect())
+    }
+
+    async fn on_event(&self, event: DeviceEventPointer) {
+        let (siid, eiid) = match event.downcast_ref::<MijiaEvent>() {
+            Some(MijiaEvent::EventOccurred { siid, eiid, .. }) => (*siid, *eiid),
+            _ => return,
+        };
+        for mapping in self.mapping.iter().filter(|m| m.siid == siid && m.eiid == eiid) {
+            debug!("event siid:{},eiid:{} -> {:?}", siid, eiid, mapping.press);
+            if let Err(e) = self.ctx.hap_manager
+                .update_char_value(self.ctx.aid, mapping.stag.clone(), HapType::ProgrammableSwitchEvent, json!(mapping.press.value()))
+                .await {
+                warn!("设置按键事件失败:{:?}", e);
+            }
+        }
+    }
+}
@@ -5,7 +5,9 @@ use anyhow::anyhow;
 use bimap::BiMap;
 use log::{debug, info, warn};
 
+use hl_integration::event::events::DeviceEventPointer;
 use hl_integration::JsonValue;
+use miot_proto::device::common::emitter::MijiaEvent;
 use miot_proto::device::miot_spec_device::{AsMiotDevice, MiotDeviceArc};
 use miot_proto::proto::miio_proto::MiotSpecId;
 use target_hap::delegate::{CharReadParam, CharReadResult, CharUpdateParam, CharUpdateResult};
@@ -100,9 +102,10 @@ impl HapModelExt for ModelExt {
                 return Ok(result);
             }
 
+            // 影子中未过期的值直接返回
             let results = self
                 .dev
-                .read_properties(ids).await?;
+                .read_properties_cached(ids).await?;
 
             if results.len() != read_params.len() {
                 return Err(anyhow!("update result length not equal to cids length"));
@@ -184,4 +187,25 @@ impl HapModelExt for ModelExt {
 
         Ok(result)
     }
-}
\ No newline at end of file
+    async fn on_event(&self, event: DeviceEventPointer) {
+        let changes = match event.downcast_ref::<MijiaEvent>() {
+            Some(MijiaEvent::PropertiesChanged(changes)) => changes.clone(),
+            Some(MijiaEvent::UpdateProperty(change)) => vec![change.clone()],
+            _ => return,
+        };
+        for change in changes {
+            let value = match change.value {
+                None => continue,
+                Some(v) => v,
+            };
+            let cid = match self.mapping.get_by_right(&MiotSpecId::new(change.siid, change.piid)) {
+                None => continue,
+                Some(cid) => cid,
+            };
+            debug!("属性:{}.{}变化,更新特征:{:?}", change.siid, change.piid, cid);
+            if let Err(e) = self.ctx.set_char_value(cid, value).await {
+                warn!("更新特征值失败:{:?}", e);
+            }
+        }
+    }
+}
@@ -1,6 +1,8 @@
 /// 模式开关
 pub mod mode_switch;
 pub(crate) mod miot_spec_prop_mapping;
+pub(crate) mod miot_spec_action_mapping;
+pub(crate) mod miot_spec_event_switch;
 pub(crate) mod ble_value_mapping;
 pub(crate) mod hl_virtual;
 // pub mod native_ble;
\ No newline at end of file
@@ -1,6 +1,5 @@
 use std::any::Any;
 use std::collections::HashMap;
-use std::ops::Deref;
 use std::sync::Arc;
 use std::time::Duration;
 use anyhow::anyhow;
@@ -33,75 +32,89 @@ pub struct AccessoryModelContext {
 }
 
 impl AccessoryModelContext {
-    pub async fn set_char_value(&self, cid: &CharIdentifier, value: Value) {
-        // let _ = self.hap_manager.update_char_value(self.aid, cid.stag.clone(), cid.ctag.into(), value).await;
+    /// 设备值经过单位转换后设置到特征,通知已订阅的控制器
+    pub async fn set_char_value(&self, cid: &CharIdentifier, value: Value) -> anyhow::Result<()> {
+        let value = match self.convertor_map.get(cid) {
+            Some(c) => c.ext.from(value.clone()).unwrap_or(value),
+            None => value,
+        };
+        self.hap_manager.update_char_value(self.aid, cid.stag.clone(), cid.ctag.into(), value).await
     }
 }
 
 
 /// 配件模型
+/// 一个配件可以有多个委托模型,每个模型接管自己 chars 中的特征
 pub struct HapAccessoryDelegateModel {
-    pub model_ext: HapModelExtPointer,
+    pub model_exts: Vec<HapModelExtPointer>,
     pub dev: SourceDevicePointer,
-    pub listener_id: Option<i64>,
+    pub listener_ids: Vec<i64>,
     pub delegate: ModelDelegates,
 }
 
 impl Drop for HapAccessoryDelegateModel {
     fn drop(&mut self) {
-        if let Some(id) = self.listener_id {
-            self.dev.remove_listener(id);
+        for id in self.listener_ids.iter() {
+            self.dev.remove_listener(*id);
         }
     }
 }
 
 impl HapAccessoryDelegateModel {
-    pub async fn new(ctx: AccessoryModelContext, mut delegate_param: Vec<ModelDelegateParam>) -> anyhow::Result<Self> {
-        let delegate_param = delegate_param
-            .remove(0);
-
-        let name = delegate_param.model.as_str();
-        let params = delegate_param.params.clone();
-
+    pub async fn new(ctx: AccessoryModelContext, delegate_params: Vec<ModelDelegateParam>) -> anyhow::Result<Self> {
+        check_delegate_chars(&delegate_params)?;
         let ctx = Arc::new(ctx);
-        let ext = get_hap_model_ext_database().get(name);
-        let model_ext_new_func = ext
-            .ok_or(anyhow!("AccessoryModelExt {} not found",name))?;
         let dev = ctx.dev.clone();
-        let model_ext = model_ext_new_func(ctx, params)
-            .tap_err(|e| error!("创建模型扩展失败{:?}",e))?;
-        //订阅设备事件
-        let mut listener_id = None;
-        if model_ext.is_subscribe_event() {
-            let model_ext_c = model_ext.clone();
-
-            listener_id = Some(dev.add_listener(Box::new(move |data| {
-                let model_ext = model_ext_c.clone();
-                Box::pin(async move {
-                    model_ext.on_event(data).await;
-                    ()
-                })
-            })).await);
-        }
-
-        let delegate = ModelDelegates {
-            delegates: Arc::new(vec![ModelDelegate {
+        let mut model_exts = vec![];
+        let mut listener_ids = vec![];
+        let mut delegates = vec![];
+        for delegate_param in delegate_params.into_iter() {
+            let name = delegate_param.model.as_str();
+            let ext = get_hap_model_ext_database().get(name);
+            let model_ext_new_func = ext
+                .ok_or(anyhow!("AccessoryModelExt {} not found",name))?;
+            let model_ext = model_ext_new_func(ctx.clone(), delegate_param.params.clone())
+                .tap_err(|e| error!("创建模型扩展:{}失败{:?}", name, e))?;
+            //订阅设备事件
+            if model_ext.is_subscribe_event() {
+                let model_ext_c = model_ext.clone();
+                listener_ids.push(dev.add_listener(Box::new(move |data| {
+                    let model_ext = model_ext_c.clone();
+                    Box::pin(async move {
+                        model_ext.on_event(data).await;
+                        ()
+                    })
+                })).await);
+            }
+            delegates.push(ModelDelegate {
                 chars: delegate_param.chars.into_iter().map(|i| i.into()).collect(),
                 ext: model_ext.clone(),
                 timeout: delegate_param.timeout
                     .map(|i| Duration::from_millis(i))
                     .unwrap_or_else(|| Duration::from_secs(2)),
-            }]),
-        };
+            });
+            model_exts.push(model_ext);
+        }
 
         Ok(Self {
-            model_ext,
+            model_exts,
             dev,
-            listener_id,
-            delegate,
+            listener_ids,
+            delegate: ModelDelegates {
+                aid: ctx.aid,
+                hap_manager: ctx.hap_manager.clone(),
+                delegates: Arc::new(delegates),
+            },
         })
     }
 
+    /// 初始化所有模型
+    pub async fn init(&self) -> anyhow::Result<()> {
+        for ext in self.model_exts.iter() {
+            ext.init().await?;
+        }
+        Ok(())
+    }
 
     pub fn get_on_read_delegate(&self) -> Option<Box<dyn CharReadsDelegate>> {
         let delegate = self.delegate.clone();
@@ -115,14 +128,23 @@ impl HapAccessoryDelegateModel {
     }
 }
 
-
-impl Deref for HapAccessoryDelegateModel {
-    type Target = HapModelExtPointer;
-    fn deref(&self) -> &Self::Target {
-        &self.model_ext
+/// 检查委托的特征,多个委托时必须指定chars,且同一特征只能由一个委托接管
+pub fn check_delegate_chars(delegates: &[ModelDelegateParam]) -> anyhow::Result<()> {
+    let mut owners: HashMap<&CharIdentifier, &str> = HashMap::new();
+    for delegate in delegates.iter() {
+        if delegates.len() > 1 && delegate.chars.is_empty() {
+            return Err(anyhow!("多个委托模型时,委托:{}必须指定chars", delegate.model));
+        }
+        for cid in delegate.chars.iter() {
+            if let Some(owner) = owners.insert(cid, delegate.model.as_str()) {
+                return Err(anyhow!("特征:{}.{}同时被委托:{}和{}接管", cid.stag, cid.ctag, owner, delegate.model));
+            }
+        }
     }
+    Ok(())
 }
 
+
 pub type ReadValueResult = anyhow::Result<Vec<CharReadResult>>;
 pub type UpdateValueResult = anyhow::Result<Vec<CharUpdateResult>>;
 
@@ -147,3 +169,33 @@ pub trait HapModelExt {
     /// 是否订阅设备的事件
     fn is_subscribe_event(&self) -> bool { true }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::delegate::model::check_delegate_chars;
+    use crate::hap_type_wrapper::HapTypeWrapper;
+    use crate::types::{CharIdentifier, ModelDelegateParam};
+
+    fn param(model: &str, chars: Vec<(&str, HapTypeWrapper)>) -> ModelDelegateParam {
+        ModelDelegateParam {
+            chars: chars.into_iter().map(|(s, c)| CharIdentifier::new(s.to_string(), c)).collect(),
+            model: model.to_string(),
+            params: None,
+            timeout: None,
+        }
+    }
+
+    #[test]
+    fn test_check_delegate_chars() {
+        let mode = param("common.mode_switch", vec![("mode", HapTypeWrapper::PowerState)]);
+        let temp = param("common.miot_spec_prop_mapping", vec![("temp", HapTypeWrapper::CurrentTemperature)]);
+        assert!(check_delegate_chars(&[mode.clone(), temp.clone()]).is_ok());
+
+        let conflict = param("common.miot_spec_prop_mapping", vec![("mode", HapTypeWrapper::PowerState)]);
+        assert!(check_delegate_chars(&[mode.clone(), conflict]).is_err());
+
+        let empty = param("common.miot_spec_prop_mapping", vec![]);
+        assert!(check_delegate_chars(&[empty.clone()]).is_ok());
+        assert!(check_delegate_chars(&[mode, empty]).is_err());
+    }
+}
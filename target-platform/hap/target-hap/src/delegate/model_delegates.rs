@@ -2,14 +2,19 @@ use std::collections::HashSet;
 use std::sync::Arc;
 use std::time::Duration;
 use anyhow::anyhow;
+use futures_util::future::join_all;
+use log::warn;
 use tap::TapFallible;
 use tokio::time::timeout;
-use hap::characteristic::delegate::{CharReadParam, CharReadsDelegate, CharUpdateDelegate, CharUpdateParam, ReadCharResults, UpdateCharResults};
+use hap::characteristic::delegate::{CharReadParam, CharReadResult, CharReadsDelegate, CharUpdateDelegate, CharUpdateParam, CharUpdateResult, ReadCharResults, UpdateCharResults};
 use crate::delegate::model::HapModelExtPointer;
-use crate::types::CharIdentifier;
+use crate::hap_manager::HapManage;
+use crate::types::{CharIdentifier, HapStatusError};
 
 #[derive(Clone)]
 pub struct ModelDelegates {
+    pub aid: u64,
+    pub hap_manager: HapManage,
     pub delegates: Arc<Vec<ModelDelegate>>,
 }
 
@@ -20,10 +25,41 @@ impl CharReadsDelegate for ModelDelegates {
     }
 
     async fn reads_value(&self, params: Vec<CharReadParam>) -> ReadCharResults {
+        // 设备离线时不再读取,避免控制器显示过期的值
+        if !self.hap_manager.is_accessory_online(self.aid) {
+            return Err(HapStatusError::ServiceCommunicationFailure.into());
+        }
         if self.delegates.len() == 1 {
             return self.delegates.get(0).unwrap().reads_value(params).await;
         }
-        todo!("未实现多委托");
+        // 按特征所属的委托分组,并发读取
+        let mut rest = params;
+        let mut tasks = vec![];
+        for delegate in self.delegates.iter() {
+            let (owned, others): (Vec<CharReadParam>, Vec<CharReadParam>) = rest.into_iter()
+                .partition(|p| CharReadsDelegate::is_delegate(delegate, p));
+            rest = others;
+            if !owned.is_empty() {
+                // 委托失败时只有它接管的特征返回错误
+                let fails: Vec<CharReadResult> = owned.iter().map(CharReadResult::fail).collect();
+                tasks.push(async move {
+                    delegate.reads_value(owned)
+                        .await
+                        .tap_err(|e| warn!("委托读取特征失败:{:?}", e))
+                        .unwrap_or(fails)
+                });
+            }
+        }
+        let mut results: Vec<CharReadResult> = rest.iter()
+            .map(|p| {
+                warn!("特征未被委托接管:{}.{:?}", p.stag, p.ctag);
+                CharReadResult::fail(p)
+            })
+            .collect();
+        for result in join_all(tasks).await {
+            results.extend(result);
+        }
+        Ok(results)
     }
 }
 
@@ -37,7 +73,39 @@ impl CharUpdateDelegate for ModelDelegates {
         if self.delegates.len() == 1 {
             return self.delegates.get(0).unwrap().on_updates(param).await;
         }
-        todo!("未实现多委托");
+        let mut rest = param;
+        let mut tasks = vec![];
+        for delegate in self.delegates.iter() {
+            let (owned, others): (Vec<CharUpdateParam>, Vec<CharUpdateParam>) = rest.into_iter()
+                .partition(|p| CharUpdateDelegate::is_delegate(delegate, p));
+            rest = others;
+            if !owned.is_empty() {
+                let fails: Vec<CharUpdateResult> = owned.iter().map(update_fail).collect();
+                tasks.push(async move {
+                    delegate.on_updates(owned)
+                        .await
+                        .tap_err(|e| warn!("委托写入特征失败:{:?}", e))
+                        .unwrap_or(fails)
+                });
+            }
+        }
+        let mut results: Vec<CharUpdateResult> = rest.iter()
+            .map(|p| {
+                warn!("特征未被委托接管:{}.{:?}", p.stag, p.ctag);
+                update_fail(p)
+            })
+            .collect();
+        for result in join_all(tasks).await {
+            results.extend(result);
+        }
+        Ok(results)
+    }
+}
+
+fn update_fail(param: &CharUpdateParam) -> CharUpdateResult {
+    CharUpdateResult {
+        cid: param.cid,
+        success: false,
     }
 }
 
@@ -3,12 +3,13 @@ mod method;
 mod default_char_info;
 
 use std::collections::HashMap;
-use std::net::{IpAddr, Ipv4Addr};
+use std::net::{IpAddr, SocketAddr};
 use std::ops::Deref;
 use std::sync::Arc;
 use log::error;
 use tap::TapFallible;
 use tokio::sync::Mutex;
+use serde::Serialize;
 use hap::{HapType, MdnsResponder, pointer, RawMdnsResponder};
 use hap::pointer::MdnsResponderPtr;
 use hap::server::IpServer;
@@ -24,6 +25,12 @@ pub struct HapTask {
     pub server: IpServer,
 }
 
+/// 控制器会话, hap-rs 只提供连接地址,不提供连接对应的控制器
+#[derive(Debug, Clone, Serialize)]
+pub struct HapSession {
+    pub addr: SocketAddr,
+}
+
 pub struct AccessoryRelation {
     pub aid: u64,
     pub device_id: i64,
@@ -57,9 +64,12 @@ pub struct HapManageInner {
     // aid_ch_map: dashmap::DashMap<u64, ChannelInfo>,
     /// 配件与设备的关系
     aid_dev_map: dashmap::DashMap<u64, i64>,
+    /// 离线的设备id
+    offline_devices: dashmap::DashSet<i64>,
     default_type_info_map: HashMap<HapType, HapCharInfo>,
 
-    mdns_responder: Mutex<Option<Arc<Mutex<RawMdnsResponder>>>>,
+    /// 按广播地址共享的 mdns, 地址为空时广播所有网卡
+    mdns_responders: Mutex<HashMap<Vec<IpAddr>, Arc<Mutex<RawMdnsResponder>>>>,
 }
 
 
@@ -69,26 +79,20 @@ pub struct HapManage {
 }
 
 impl HapManage {
-    pub async fn get_mdns(&self) -> anyhow::Result<Arc<Mutex<RawMdnsResponder>>> {
-        let mut prt = self.mdns_responder.lock().await;
-        if prt.is_some() {
-            return Ok(prt.as_ref().unwrap().clone());
+    /// 获取广播到指定地址的 mdns, 相同地址的桥接器共用
+    pub async fn get_mdns(&self, ips: Vec<IpAddr>) -> anyhow::Result<Arc<Mutex<RawMdnsResponder>>> {
+        let mut map = self.mdns_responders.lock().await;
+        if let Some(raw) = map.get(&ips) {
+            return Ok(raw.clone());
         }
-        let raw = match prt.as_ref() {
-            Some(s) => {
-                s.clone()
-            }
-            None => {
-                // let ip = IpAddr::V4(Ipv4Addr::new(192, 168, 3, 180));
-                // let raw = RawMdnsResponder::new_with_ip_list(vec![ip])
-                //     .tap_err(|e| error!("mdns 启动错误:{e:?}"))?;
-                let raw=RawMdnsResponder::new().tap_err(|e| error!("mdns 启动错误:{e:?}"))?;
-                let arc = Arc::new(Mutex::new(raw));
-                *prt = Some(arc.clone());
-                arc
-            }
-        };
-        Ok(raw)
+        let raw = if ips.is_empty() {
+            RawMdnsResponder::new()
+        } else {
+            RawMdnsResponder::new_with_ip_list(ips.clone())
+        }.tap_err(|e| error!("mdns 启动错误:{e:?}"))?;
+        let arc = Arc::new(Mutex::new(raw));
+        map.insert(ips, arc.clone());
+        Ok(arc)
     }
 }
 
@@ -103,8 +107,9 @@ impl HapManage {
                 server_map: Default::default(),
                 accessory_map: Default::default(),
                 aid_dev_map: Default::default(),
+                offline_devices: Default::default(),
                 default_type_info_map,
-                mdns_responder: Default::default(),
+                mdns_responders: Default::default(),
             })
         }
     }
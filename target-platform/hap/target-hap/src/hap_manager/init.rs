@@ -6,29 +6,78 @@ use serde_json::Value;
 use tap::TapFallible;
 use hap::{Config, HapType, pointer};
 use hap::server::{IpServer, Server};
-use crate::hap_manager::{AccessoryInfo, AccessoryRelation, HapManageInner, HapTask};
+use crate::hap_manager::{AccessoryInfo, AccessoryRelation, HapManageInner, HapSession, HapTask};
 use crate::HapAccessoryPointer;
+use crate::iot::characteristic_value::CharacteristicValue;
 use crate::types::HapCharInfo;
 
 impl HapManageInner {
-    /// 移除配件
+    fn get_server(&self, bid: i64) -> Option<IpServer> {
+        self.server_map.get(&bid).map(|i| i.server.clone())
+    }
+
+    /// 移除配件,配置号加一并重新广播 mdns,不影响桥接器上的其他会话
     pub async fn remove_accessory(&self, aid: u64) -> anyhow::Result<()> {
         let info = self.accessory_map.remove(&aid);
+        self.aid_dev_map.remove(&aid);
         if let Some((_, info)) = info {
             let bid = info.bid;
-            if let Some(server) = self.server_map.get_mut(&bid) {
-                server.server.remove_accessory(aid).await?;
+            if let Some(server) = self.get_server(bid) {
+                server.remove_accessory(aid).await?;
+                server.configuration_number_incr().await;
             };
             info!("移除配件:{},bid:{}", aid, bid);
         };
         Ok(())
     }
 
+    /// 运行中的桥接器添加或替换配件,配置号加一并重新广播 mdns
+    pub async fn put_accessory(&self, bid: i64, rel: AccessoryRelation) -> anyhow::Result<()> {
+        let server = self.get_server(bid)
+            .ok_or(anyhow!("桥接器:{}未运行", bid))?;
+        let aid = rel.aid;
+        // 替换或移动到其他桥接器时先移除旧配件
+        if let Some((_, old)) = self.accessory_map.remove(&aid) {
+            if let Some(old_server) = self.get_server(old.bid) {
+                old_server.remove_accessory(aid).await?;
+                if old.bid != bid {
+                    old_server.configuration_number_incr().await;
+                }
+            }
+        }
+        server.add_arc_accessory(rel.accessory.clone()).await?;
+        self.aid_dev_map.insert(aid, rel.device_id);
+        self.accessory_map.insert(aid, AccessoryInfo {
+            aid,
+            device_id: rel.device_id,
+            bid,
+            accessory: rel.accessory,
+        });
+        server.configuration_number_incr().await;
+        info!("更新配件:{},bid:{}", aid, bid);
+        Ok(())
+    }
+
+    /// 桥接器是否在运行
+    pub fn is_server_running(&self, bid: i64) -> bool {
+        self.server_map.contains_key(&bid)
+    }
+
     /// 获取连接
     pub fn get_bridge_server_peer(&self, bid: i64) -> Option<pointer::Peers> {
         self.server_map.get(&bid)
             .map(|i| i.server.peers_pointer().clone())
     }
+    /// 桥接器当前连接的控制器会话
+    pub async fn get_bridge_sessions(&self, bid: i64) -> Vec<HapSession> {
+        match self.get_bridge_server_peer(bid) {
+            None => vec![],
+            Some(peers) => peers.read().await.iter()
+                .map(|addr| HapSession { addr: *addr })
+                .collect(),
+        }
+    }
+
     pub fn get_bridge_server_config(&self, bid: i64) -> Option<Arc<Mutex<Config>>> {
         self.server_map.get(&bid)
             .map(|i| i.server.config_pointer().clone())
@@ -85,7 +134,7 @@ impl HapManageInner {
         Ok(())
     }
 
-    pub(crate) async fn update_char_value(&self, aid: u64, service_tag: String, char_tag: HapType, value: Value) -> anyhow::Result<()> {
+    pub async fn update_char_value(&self, aid: u64, service_tag: String, char_tag: HapType, value: Value) -> anyhow::Result<()> {
         let accessory = self.accessory_map.get(&aid)
             .ok_or(anyhow!("设备:{}不存在",aid))
             .tap_err(|e| error!("{}",e))?
@@ -100,7 +149,9 @@ impl HapManageInner {
             for svc in services {
                 let ch = svc.get_mut_characteristic(char_tag);
                 if let Some(ch) = ch {
-                    if let Err(e) = ch.set_value(value.clone()).await {
+                    // 按特征的类型转换, 与读取时一致
+                    let value = CharacteristicValue::format(ch.get_format(), value.clone()).value;
+                    if let Err(e) = ch.set_value(value).await {
                         warn!("设置特征值失败:{:?}",e);
                     }
                 }
@@ -1,6 +1,12 @@
 use anyhow::anyhow;
+use log::warn;
+use serde_json::{json, Value};
+use hap::characteristic::delegate::{CharReadParam, CharUpdateParam};
+use hap::HapType;
 use hap::server::Server;
 use crate::hap_manager::HapManageInner;
+use crate::HapAccessoryPointer;
+use crate::iot::characteristic_value::CharacteristicValue;
 
 impl HapManageInner {
     pub async fn close(&self) {}
@@ -20,4 +26,134 @@ impl HapManageInner {
             }
         }
     }
-}
\ No newline at end of file
+
+    /// 设备在线状态变化,更新设备所有配件的 StatusFault/StatusActive 特征
+    pub async fn set_device_online(&self, device_id: i64, online: bool) {
+        if online {
+            self.offline_devices.remove(&device_id);
+        } else {
+            self.offline_devices.insert(device_id);
+        }
+        let accessories: Vec<HapAccessoryPointer> = self.accessory_map.iter()
+            .filter(|i| i.device_id == device_id)
+            .map(|i| i.accessory.clone())
+            .collect();
+        // 0: 正常, 1: 故障
+        let fault = json!(if online { 0 } else { 1 });
+        for accessory in accessories {
+            let mut lock = accessory.write().await;
+            let aid = lock.get_id();
+            let mut found = false;
+            for svc in lock.get_mut_services() {
+                if let Some(c) = svc.get_mut_characteristic(HapType::StatusFault) {
+                    found = true;
+                    if let Err(e) = c.set_value(fault.clone()).await {
+                        warn!("设置StatusFault失败:{:?}", e);
+                    }
+                }
+                if let Some(c) = svc.get_mut_characteristic(HapType::StatusActive) {
+                    found = true;
+                    if let Err(e) = c.set_value(json!(online)).await {
+                        warn!("设置StatusActive失败:{:?}", e);
+                    }
+                }
+            }
+            if !found {
+                warn!("配件:{aid}没有StatusFault/StatusActive特征,无法标记在线状态,请检查模板是否设置了主服务");
+            }
+        }
+    }
+
+    /// 配件的设备是否在线,未知的配件视为在线
+    pub fn is_accessory_online(&self, aid: u64) -> bool {
+        self.accessory_map.get(&aid)
+            .map(|i| !self.offline_devices.contains(&i.device_id))
+            .unwrap_or(true)
+    }
+
+    fn get_accessory(&self, aid: u64) -> anyhow::Result<HapAccessoryPointer> {
+        self.accessory_map.get(&aid)
+            .map(|i| i.accessory.clone())
+            .ok_or(anyhow!("配件:{}不存在", aid))
+    }
+
+    /// 特征当前值,不触发读取
+    pub async fn get_char_value(&self, aid: u64, stag: &str, ctag: HapType) -> anyhow::Result<Option<Value>> {
+        let accessory = self.get_accessory(aid)?;
+        let mut lock = accessory.write().await;
+        let value = lock.get_mut_services_by_tag(stag)
+            .into_iter()
+            .find_map(|s| s.get_mut_characteristic(ctag).map(|c| c.get_raw_value()));
+        Ok(value)
+    }
+
+    /// 读取特征值,特征被模型接管时走委托读取,否则返回当前值
+    pub async fn read_char_value(&self, aid: u64, stag: &str, ctag: HapType) -> anyhow::Result<Option<Value>> {
+        let accessory = self.get_accessory(aid)?;
+        let (param, current, delegate) = {
+            let mut lock = accessory.write().await;
+            let delegate = lock.get_on_read_delegate();
+            let (sid, ch) = lock.get_mut_services_by_tag(stag)
+                .into_iter()
+                .find_map(|s| {
+                    let sid = s.get_id();
+                    s.get_mut_characteristic(ctag).map(|c| (sid, c))
+                })
+                .ok_or(anyhow!("特征:{}.{:?}不存在", stag, ctag))?;
+            let param = CharReadParam {
+                sid,
+                cid: ch.get_id(),
+                stag: stag.to_string(),
+                ctag,
+                format: ch.get_format(),
+            };
+            (param, ch.get_raw_value(), delegate)
+        };
+        match delegate.filter(|d| d.is_delegate(&param)) {
+            None => Ok(Some(current)),
+            Some(delegate) => {
+                let result = delegate.reads_value(vec![param]).await?;
+                Ok(result.into_iter()
+                    .find(|r| r.success)
+                    .and_then(|r| r.value))
+            }
+        }
+    }
+
+    /// 写入特征值,与hap 控制器写入相同,交给模型委托处理
+    /// 成功后更新特征当前值,通知已订阅的控制器
+    pub async fn write_char_value(&self, aid: u64, stag: &str, ctag: HapType, value: Value) -> anyhow::Result<()> {
+        let accessory = self.get_accessory(aid)?;
+        let (param, delegate) = {
+            let mut lock = accessory.write().await;
+            let delegate = lock.get_on_updates_delegate();
+            let (sid, ch) = lock.get_mut_services_by_tag(stag)
+                .into_iter()
+                .find_map(|s| {
+                    let sid = s.get_id();
+                    s.get_mut_characteristic(ctag).map(|c| (sid, c))
+                })
+                .ok_or(anyhow!("特征:{}.{:?}不存在", stag, ctag))?;
+            let format = ch.get_format();
+            let param = CharUpdateParam {
+                sid,
+                cid: ch.get_id(),
+                stag: stag.to_string(),
+                ctag,
+                format,
+                old_value: ch.get_raw_value(),
+                new_value: CharacteristicValue::try_format(format, value)?.value,
+            };
+            (param, delegate)
+        };
+        let delegate = delegate
+            .filter(|d| d.is_delegate(&param))
+            .ok_or(anyhow!("特征:{}.{:?}未被模型接管", stag, ctag))?;
+        let new_value = param.new_value.clone();
+        let results = delegate.on_updates(vec![param]).await?;
+        if results.iter().any(|r| !r.success) {
+            return Err(anyhow!("写入特征:{}.{:?}失败", stag, ctag));
+        }
+        self.update_char_value(aid, stag.to_string(), ctag, new_value).await
+    }
+}
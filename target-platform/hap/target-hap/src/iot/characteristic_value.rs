@@ -52,6 +52,8 @@ impl CharacteristicValue {
         } else if format == Format::Bool && value.is_number() {
             let val = value.as_u64() == Some(1);
             json!(val)
+        } else if value.is_number() || value.is_boolean() {
+            Self::format_number(format, value)?
         } else { value };
 
         Ok(Self {
@@ -61,6 +63,26 @@ impl CharacteristicValue {
 }
 
 
+impl CharacteristicValue {
+    /// 数字和布尔值转换成特征的数字类型,如 float -> int, bool -> uint8
+    fn format_number(format: Format, value: serde_json::Value) -> anyhow::Result<serde_json::Value> {
+        let num = match value.as_bool() {
+            Some(b) => if b { 1.0 } else { 0.0 },
+            None => value.as_f64().ok_or(anyhow::anyhow!("值:{}不是数字", value))?,
+        };
+        let int = num.round() as i64;
+        Ok(match format {
+            Format::Int32 => json!(i32::try_from(int)?),
+            Format::UInt8 => json!(u8::try_from(int)?),
+            Format::UInt16 => json!(u16::try_from(int)?),
+            Format::UInt32 => json!(u32::try_from(int)?),
+            Format::UInt64 => json!(u64::try_from(int)?),
+            Format::Float => json!(num),
+            _ => value,
+        })
+    }
+}
+
 impl Into<serde_json::Value> for CharacteristicValue {
     fn into(self) -> serde_json::Value {
         self.value
@@ -79,3 +101,21 @@ impl Serialize for CharacteristicValue {
         self.value.serialize(serializer)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use hap::characteristic::Format;
+    use serde_json::json;
+
+    use crate::iot::characteristic_value::CharacteristicValue;
+
+    #[test]
+    fn test_format_number() {
+        assert_eq!(CharacteristicValue::format(Format::Int32, json!(21.6)).value, json!(22));
+        assert_eq!(CharacteristicValue::format(Format::UInt8, json!(true)).value, json!(1));
+        assert_eq!(CharacteristicValue::format(Format::Float, json!(20)).value, json!(20.0));
+        assert_eq!(CharacteristicValue::format(Format::Bool, json!(1)).value, json!(true));
+        assert!(CharacteristicValue::try_format(Format::UInt8, json!(300)).is_err());
+        assert_eq!(CharacteristicValue::format(Format::String, json!(1)).value, json!(1));
+    }
+}
@@ -61,5 +61,10 @@ pub struct HapCharInfo {
     pub pid: Option<u64>,
 }
 
-
-
+/// 返回给控制器的 hap 状态错误
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum HapStatusError {
+    /// 设备离线,与配件通信失败 (-70402)
+    #[error("与配件通信失败")]
+    ServiceCommunicationFailure = -70402,
+}
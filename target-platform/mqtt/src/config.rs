@@ -0,0 +1,36 @@
+use serde::Deserialize;
+
+fn default_port() -> u16 {
+    1883
+}
+
+fn default_base_topic() -> String {
+    "homelink".to_string()
+}
+
+fn default_discovery() -> bool {
+    true
+}
+
+fn default_discovery_prefix() -> String {
+    "homeassistant".to_string()
+}
+
+/// mqtt 导出配置
+#[derive(Debug, Clone, Deserialize)]
+pub struct MqttExportConfig {
+    pub host: String,
+    #[serde(default = "default_port")]
+    pub port: u16,
+    pub client_id: Option<String>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// 主题前缀, 状态: {base_topic}/{aid}/{stag}/{ctag}, 命令: .../set
+    #[serde(default = "default_base_topic")]
+    pub base_topic: String,
+    /// 是否发布 home assistant 自动发现配置
+    #[serde(default = "default_discovery")]
+    pub discovery: bool,
+    #[serde(default = "default_discovery_prefix")]
+    pub discovery_prefix: String,
+}
@@ -0,0 +1,314 @@
+use serde_json::{json, Map, Value};
+
+use target_hap::hap::characteristic::{Format, Perm};
+use target_hap::hap_type_wrapper::HapTypeWrapper;
+use target_hap::types::HapCharInfo;
+
+use crate::topic::{object_id, set_topic, state_topic, status_topic};
+
+/// 导出的特征,来自 hap_characteristic 表
+#[derive(Debug, Clone)]
+pub struct ExportChar {
+    pub ctag: HapTypeWrapper,
+    pub name: Option<String>,
+    pub info: HapCharInfo,
+}
+
+impl ExportChar {
+    pub fn writable(&self) -> bool {
+        self.info.perms.contains(&Perm::PairedWrite)
+    }
+}
+
+/// 导出的服务,来自 hap_service 表
+#[derive(Debug, Clone)]
+pub struct ExportService {
+    pub stag: String,
+    pub service_type: HapTypeWrapper,
+    pub name: Option<String>,
+    pub chars: Vec<ExportChar>,
+}
+
+impl ExportService {
+    fn get_char(&self, ctag: HapTypeWrapper) -> Option<&ExportChar> {
+        self.chars.iter().find(|c| c.ctag == ctag)
+    }
+}
+
+/// 导出的配件,来自 hap_accessory 表
+#[derive(Debug, Clone)]
+pub struct ExportAccessory {
+    pub aid: u64,
+    pub device_id: i64,
+    pub name: String,
+    pub manufacturer: Option<String>,
+    pub model: Option<String>,
+    pub services: Vec<ExportService>,
+}
+
+/// 自动发现配置
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiscoveryConfig {
+    pub topic: String,
+    pub payload: Value,
+}
+
+/// bool 类型的值发布为 true/false, 其他为数字
+fn on_off(c: &ExportChar, on: &str) -> (String, String) {
+    match c.info.format {
+        Format::Bool => ("true".to_string(), "false".to_string()),
+        _ => (on.to_string(), "0".to_string()),
+    }
+}
+
+struct DiscoveryBuilder<'a> {
+    base: &'a str,
+    prefix: &'a str,
+    accessory: &'a ExportAccessory,
+    list: Vec<DiscoveryConfig>,
+}
+
+impl<'a> DiscoveryBuilder<'a> {
+    fn push(&mut self, component: &str, svc: &ExportService, c: &ExportChar, mut payload: Map<String, Value>) {
+        let aid = self.accessory.aid;
+        let node_id = format!("homelink_{}", aid);
+        let obj_id = object_id(format!("{}_{}", svc.stag, c.ctag).as_str());
+        let name = c.name.clone()
+            .or(svc.name.clone())
+            .unwrap_or(format!("{} {}", svc.stag, c.ctag));
+        payload.insert("name".to_string(), json!(name));
+        payload.insert("unique_id".to_string(), json!(format!("{}_{}", node_id, obj_id)));
+        payload.insert("object_id".to_string(), json!(format!("{}_{}", node_id, obj_id)));
+        payload.insert("availability_topic".to_string(), json!(status_topic(self.base)));
+        payload.insert("device".to_string(), json!({
+            "identifiers": [format!("homelink_{}", self.accessory.device_id)],
+            "name": self.accessory.name,
+            "manufacturer": self.accessory.manufacturer,
+            "model": self.accessory.model,
+        }));
+        self.list.push(DiscoveryConfig {
+            topic: format!("{}/{}/{}/{}/config", self.prefix, component, node_id, obj_id),
+            payload: Value::Object(payload),
+        });
+    }
+
+    fn state(&self, svc: &ExportService, c: &ExportChar) -> Value {
+        json!(state_topic(self.base, self.accessory.aid, svc.stag.as_str(), c.ctag))
+    }
+
+    fn command(&self, svc: &ExportService, c: &ExportChar) -> Value {
+        json!(set_topic(self.base, self.accessory.aid, svc.stag.as_str(), c.ctag))
+    }
+
+    /// 灯泡服务合并成一个 light
+    fn light(&mut self, svc: &ExportService, power: &ExportChar) {
+        let (on, off) = on_off(power, "1");
+        let mut payload = Map::new();
+        payload.insert("state_topic".to_string(), self.state(svc, power));
+        payload.insert("command_topic".to_string(), self.command(svc, power));
+        payload.insert("payload_on".to_string(), json!(on));
+        payload.insert("payload_off".to_string(), json!(off));
+        if let Some(c) = svc.get_char(HapTypeWrapper::Brightness) {
+            payload.insert("brightness_state_topic".to_string(), self.state(svc, c));
+            payload.insert("brightness_command_topic".to_string(), self.command(svc, c));
+            payload.insert("brightness_scale".to_string(), json!(100));
+        }
+        if let Some(c) = svc.get_char(HapTypeWrapper::ColorTemperature) {
+            // hap 与 home assistant 都使用米德
+            payload.insert("color_temp_state_topic".to_string(), self.state(svc, c));
+            payload.insert("color_temp_command_topic".to_string(), self.command(svc, c));
+            if let Some(v) = c.info.min_value.as_ref() {
+                payload.insert("min_mireds".to_string(), v.clone());
+            }
+            if let Some(v) = c.info.max_value.as_ref() {
+                payload.insert("max_mireds".to_string(), v.clone());
+            }
+        }
+        self.push("light", svc, power, payload);
+    }
+
+    fn char(&mut self, svc: &ExportService, c: &ExportChar) {
+        let mut payload = Map::new();
+        payload.insert("state_topic".to_string(), self.state(svc, c));
+        let binary = |device_class: &str, on: &str| (device_class.to_string(), on_off(c, on));
+        let binary_sensor = match c.ctag {
+            HapTypeWrapper::ContactSensorState => Some(binary("door", "1")),
+            HapTypeWrapper::MotionDetected => Some(binary("motion", "1")),
+            HapTypeWrapper::OccupancyDetected => Some(binary("occupancy", "1")),
+            HapTypeWrapper::LeakDetected => Some(binary("moisture", "1")),
+            HapTypeWrapper::SmokeDetected => Some(binary("smoke", "1")),
+            HapTypeWrapper::StatusLowBattery => Some(binary("battery", "1")),
+            _ => None,
+        };
+        if let Some((device_class, (on, off))) = binary_sensor {
+            payload.insert("device_class".to_string(), json!(device_class));
+            payload.insert("payload_on".to_string(), json!(on));
+            payload.insert("payload_off".to_string(), json!(off));
+            self.push("binary_sensor", svc, c, payload);
+            return;
+        }
+        let sensor = match c.ctag {
+            HapTypeWrapper::CurrentTemperature => Some(("temperature", "°C")),
+            HapTypeWrapper::CurrentRelativeHumidity => Some(("humidity", "%")),
+            HapTypeWrapper::CurrentLightLevel => Some(("illuminance", "lx")),
+            HapTypeWrapper::BatteryLevel => Some(("battery", "%")),
+            HapTypeWrapper::CarbonDioxideLevel => Some(("carbon_dioxide", "ppm")),
+            HapTypeWrapper::Pm2_5Density => Some(("pm25", "µg/m³")),
+            _ => None,
+        };
+        if let Some((device_class, unit)) = sensor {
+            payload.insert("device_class".to_string(), json!(device_class));
+            payload.insert("unit_of_measurement".to_string(), json!(unit));
+            payload.insert("state_class".to_string(), json!("measurement"));
+            self.push("sensor", svc, c, payload);
+            return;
+        }
+        if !c.writable() {
+            self.push("sensor", svc, c, payload);
+            return;
+        }
+        payload.insert("command_topic".to_string(), self.command(svc, c));
+        match c.info.format {
+            Format::Bool => {
+                payload.insert("payload_on".to_string(), json!("true"));
+                payload.insert("payload_off".to_string(), json!("false"));
+                self.push("switch", svc, c, payload);
+            }
+            Format::String | Format::Tlv8 | Format::Data => {
+                self.push("text", svc, c, payload);
+            }
+            _ if c.ctag == HapTypeWrapper::Active => {
+                payload.insert("payload_on".to_string(), json!("1"));
+                payload.insert("payload_off".to_string(), json!("0"));
+                self.push("switch", svc, c, payload);
+            }
+            _ => {
+                let info = &c.info;
+                if let Some(v) = info.min_value.as_ref() {
+                    payload.insert("min".to_string(), v.clone());
+                }
+                if let Some(v) = info.max_value.as_ref() {
+                    payload.insert("max".to_string(), v.clone());
+                }
+                if let Some(v) = info.step_value.as_ref() {
+                    payload.insert("step".to_string(), v.clone());
+                }
+                self.push("number", svc, c, payload);
+            }
+        }
+    }
+}
+
+/// 根据配件生成 home assistant 自动发现配置
+/// 灯泡服务合并成 light, 其他特征按类型生成 binary_sensor/sensor/switch/number/text
+pub fn discovery_configs(base: &str, prefix: &str, accessory: &ExportAccessory) -> Vec<DiscoveryConfig> {
+    let mut builder = DiscoveryBuilder {
+        base,
+        prefix,
+        accessory,
+        list: vec![],
+    };
+    for svc in accessory.services.iter() {
+        let mut chars: Vec<&ExportChar> = svc.chars.iter()
+            .filter(|c| !matches!(c.ctag, HapTypeWrapper::Name
+                | HapTypeWrapper::ConfiguredName
+                // 无状态事件不能作为实体状态
+                | HapTypeWrapper::ProgrammableSwitchEvent))
+            .collect();
+        if svc.service_type == HapTypeWrapper::Lightbulb {
+            if let Some(power) = svc.get_char(HapTypeWrapper::PowerState) {
+                builder.light(svc, power);
+                chars.retain(|c| !matches!(c.ctag, HapTypeWrapper::PowerState
+                    | HapTypeWrapper::Brightness
+                    | HapTypeWrapper::ColorTemperature));
+            }
+        }
+        for c in chars {
+            builder.char(svc, c);
+        }
+    }
+    builder.list
+}
+
+/// 移除配件时,发布空配置删除实体
+pub fn remove_configs(base: &str, prefix: &str, accessory: &ExportAccessory) -> Vec<DiscoveryConfig> {
+    discovery_configs(base, prefix, accessory)
+        .into_iter()
+        .map(|c| DiscoveryConfig {
+            topic: c.topic,
+            payload: Value::Null,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use target_hap::hap::characteristic::{Format, Perm};
+    use target_hap::hap_type_wrapper::HapTypeWrapper;
+    use target_hap::types::HapCharInfo;
+
+    use crate::discovery::{discovery_configs, ExportAccessory, ExportChar, ExportService};
+
+    fn char(ctag: HapTypeWrapper, format: Format, perms: Vec<Perm>) -> ExportChar {
+        ExportChar {
+            ctag,
+            name: None,
+            info: HapCharInfo {
+                format,
+                unit: None,
+                min_value: Some(json!(0)),
+                max_value: Some(json!(100)),
+                step_value: None,
+                max_len: None,
+                max_data_len: None,
+                valid_values: None,
+                valid_values_range: None,
+                ttl: None,
+                perms,
+                pid: None,
+            },
+        }
+    }
+
+    #[test]
+    fn test_discovery() {
+        let rw = vec![Perm::PairedRead, Perm::PairedWrite, Perm::Events];
+        let accessory = ExportAccessory {
+            aid: 3,
+            device_id: 7,
+            name: "客厅".to_string(),
+            manufacturer: None,
+            model: None,
+            services: vec![
+                ExportService {
+                    stag: "light".to_string(),
+                    service_type: HapTypeWrapper::Lightbulb,
+                    name: Some("灯".to_string()),
+                    chars: vec![
+                        char(HapTypeWrapper::PowerState, Format::Bool, rw.clone()),
+                        char(HapTypeWrapper::Brightness, Format::Int32, rw.clone()),
+                    ],
+                },
+                ExportService {
+                    stag: "temp".to_string(),
+                    service_type: HapTypeWrapper::TemperatureSensor,
+                    name: None,
+                    chars: vec![char(HapTypeWrapper::CurrentTemperature, Format::Float, vec![Perm::PairedRead])],
+                },
+            ],
+        };
+        let configs = discovery_configs("homelink", "homeassistant", &accessory);
+        assert_eq!(configs.len(), 2);
+        let light = &configs[0];
+        assert_eq!(light.topic, "homeassistant/light/homelink_3/light_powerstate/config");
+        assert_eq!(light.payload["command_topic"], json!("homelink/3/light/PowerState/set"));
+        assert_eq!(light.payload["brightness_state_topic"], json!("homelink/3/light/Brightness"));
+        assert_eq!(light.payload["payload_on"], json!("true"));
+        let sensor = &configs[1];
+        assert_eq!(sensor.topic, "homeassistant/sensor/homelink_3/temp_currenttemperature/config");
+        assert_eq!(sensor.payload["device_class"], json!("temperature"));
+        assert!(sensor.payload.get("command_topic").is_none());
+    }
+}
@@ -0,0 +1,265 @@
+use std::ops::Deref;
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+
+use dashmap::{DashMap, DashSet};
+use log::{debug, error, info, warn};
+use rand::Rng;
+use rumqttc::{AsyncClient, Event, Incoming, LastWill, MqttOptions, QoS};
+use serde_json::Value;
+use tokio::sync::RwLock;
+
+use hl_integration::SourceDevicePointer;
+use target_hap::hap_manager::HapManage;
+
+use crate::config::MqttExportConfig;
+use crate::discovery::{discovery_configs, DiscoveryConfig, ExportAccessory, remove_configs};
+use crate::topic::{parse_set_topic, set_filter, state_topic, status_topic};
+
+/// 设备事件后延迟发布,合并同一配件的多次事件
+const PUBLISH_DELAY: Duration = Duration::from_millis(200);
+
+pub struct MqttExporterInner {
+    config: MqttExportConfig,
+    hap_manager: HapManage,
+    client: RwLock<Option<AsyncClient>>,
+    accessories: DashMap<u64, ExportAccessory>,
+    /// 配件的设备及监听id
+    listeners: DashMap<u64, (SourceDevicePointer, i64)>,
+    /// 最后发布的值, 主题 -> 值
+    last_values: DashMap<String, Value>,
+    /// 等待发布的配件
+    pending: DashSet<u64>,
+}
+
+/// mqtt 导出
+/// 监听设备事件,把配件的特征值发布到 {base_topic}/{aid}/{stag}/{ctag}
+/// 订阅 .../set 命令,通过配件的模型委托写入
+#[derive(Clone)]
+pub struct MqttExporter {
+    inner: Arc<MqttExporterInner>,
+}
+
+impl Deref for MqttExporter {
+    type Target = MqttExporterInner;
+    fn deref(&self) -> &Self::Target {
+        self.inner.as_ref()
+    }
+}
+
+fn to_payload(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        v => v.to_string(),
+    }
+}
+
+impl MqttExporter {
+    pub fn new(config: MqttExportConfig, hap_manager: HapManage) -> Self {
+        Self {
+            inner: Arc::new(MqttExporterInner {
+                config,
+                hap_manager,
+                client: RwLock::new(None),
+                accessories: Default::default(),
+                listeners: Default::default(),
+                last_values: Default::default(),
+                pending: Default::default(),
+            }),
+        }
+    }
+
+    fn options(&self) -> MqttOptions {
+        let config = &self.config;
+        let client_id = config.client_id.clone().unwrap_or_else(|| {
+            let random_number: u32 = rand::thread_rng().gen_range(10000..99999);
+            format!("homelink-export-{random_number}")
+        });
+        let mut options = MqttOptions::new(client_id, config.host.as_str(), config.port);
+        options.set_keep_alive(Duration::from_secs(30));
+        options.set_last_will(LastWill::new(status_topic(config.base_topic.as_str()), "offline", QoS::AtLeastOnce, true));
+        if let Some(username) = config.username.as_ref() {
+            options.set_credentials(username.as_str(), config.password.clone().unwrap_or_default());
+        }
+        options
+    }
+
+    /// 添加配件,监听设备事件
+    pub async fn add_accessory(&self, accessory: ExportAccessory, dev: SourceDevicePointer) {
+        let aid = accessory.aid;
+        self.remove_listener(aid);
+        self.accessories.insert(aid, accessory);
+        // 弱引用,避免设备与导出器循环引用
+        let inner = Arc::downgrade(&self.inner);
+        let id = dev.add_listener(Box::new(move |_| {
+            let inner = inner.clone();
+            Box::pin(async move {
+                if let Some(inner) = Weak::upgrade(&inner) {
+                    MqttExporter { inner }.schedule_publish(aid);
+                }
+            })
+        })).await;
+        self.listeners.insert(aid, (dev, id));
+        if self.client.read().await.is_some() {
+            self.publish_accessory(aid).await;
+        }
+    }
+
+    /// 移除配件,删除自动发现的实体
+    pub async fn remove_accessory(&self, aid: u64) {
+        self.remove_listener(aid);
+        if let Some((_, accessory)) = self.accessories.remove(&aid) {
+            if self.config.discovery {
+                let configs = remove_configs(self.config.base_topic.as_str(), self.config.discovery_prefix.as_str(), &accessory);
+                self.publish_discovery(configs).await;
+            }
+        }
+    }
+
+    fn remove_listener(&self, aid: u64) {
+        if let Some((_, (dev, id))) = self.listeners.remove(&aid) {
+            dev.remove_listener(id);
+        }
+    }
+
+    async fn publish(&self, topic: String, payload: String, retain: bool) {
+        let client = match self.client.read().await.clone() {
+            None => return,
+            Some(c) => c,
+        };
+        if let Err(e) = client.publish(topic.as_str(), QoS::AtLeastOnce, retain, payload).await {
+            warn!("mqtt 发布:{}失败:{:?}", topic, e);
+        }
+    }
+
+    async fn publish_discovery(&self, configs: Vec<DiscoveryConfig>) {
+        for config in configs {
+            let payload = match config.payload {
+                Value::Null => String::new(),
+                v => v.to_string(),
+            };
+            self.publish(config.topic, payload, true).await;
+        }
+    }
+
+    /// 发布配件的发现配置和全部状态
+    async fn publish_accessory(&self, aid: u64) {
+        let accessory = match self.accessories.get(&aid) {
+            None => return,
+            Some(a) => a.clone(),
+        };
+        if self.config.discovery {
+            let configs = discovery_configs(self.config.base_topic.as_str(), self.config.discovery_prefix.as_str(), &accessory);
+            self.publish_discovery(configs).await;
+        }
+        self.publish_states(&accessory, true).await;
+    }
+
+    /// 发布特征值, live 为true 时通过模型委托读取,否则读取特征当前值
+    /// 只发布变化的值
+    async fn publish_states(&self, accessory: &ExportAccessory, live: bool) {
+        let base = self.config.base_topic.as_str();
+        for svc in accessory.services.iter() {
+            for c in svc.chars.iter() {
+                let stag = svc.stag.as_str();
+                let value = if live {
+                    self.hap_manager.read_char_value(accessory.aid, stag, c.ctag.into()).await
+                } else {
+                    self.hap_manager.get_char_value(accessory.aid, stag, c.ctag.into()).await
+                };
+                let value = match value {
+                    Ok(Some(v)) => v,
+                    Ok(None) => continue,
+                    Err(e) => {
+                        debug!("读取特征:{}.{}失败:{:?}", stag, c.ctag, e);
+                        continue;
+                    }
+                };
+                let topic = state_topic(base, accessory.aid, stag, c.ctag);
+                if self.last_values.get(&topic).map(|v| *v == value).unwrap_or(false) {
+                    continue;
+                }
+                self.last_values.insert(topic.clone(), value.clone());
+                self.publish(topic, to_payload(&value), true).await;
+            }
+        }
+    }
+
+    /// 设备事件触发, 等待模型扩展更新特征后发布
+    fn schedule_publish(&self, aid: u64) {
+        if !self.pending.insert(aid) {
+            return;
+        }
+        let this = self.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(PUBLISH_DELAY).await;
+            this.pending.remove(&aid);
+            let accessory = match this.accessories.get(&aid) {
+                None => return,
+                Some(a) => a.clone(),
+            };
+            this.publish_states(&accessory, false).await;
+        });
+    }
+
+    /// 处理命令
+    async fn on_set(&self, topic: &str, payload: &[u8]) {
+        let (aid, stag, ctag) = match parse_set_topic(self.config.base_topic.as_str(), topic) {
+            None => return,
+            Some(v) => v,
+        };
+        let text = String::from_utf8_lossy(payload);
+        let value = serde_json::from_str(text.as_ref())
+            .unwrap_or(Value::String(text.to_string()));
+        info!("mqtt 命令:{}.{}.{}={}", aid, stag, ctag, value);
+        match self.hap_manager.write_char_value(aid, stag.as_str(), ctag.into(), value).await {
+            Ok(_) => self.schedule_publish(aid),
+            Err(e) => warn!("mqtt 命令:{}执行失败:{:?}", topic, e),
+        }
+    }
+
+    async fn on_connected(&self, client: AsyncClient) {
+        let base = self.config.base_topic.as_str();
+        if let Err(e) = client.subscribe(set_filter(base), QoS::AtLeastOnce).await {
+            error!("mqtt 订阅命令失败:{:?}", e);
+        }
+        self.publish(status_topic(base), "online".to_string(), true).await;
+        self.last_values.clear();
+        let aids: Vec<u64> = self.accessories.iter().map(|i| *i.key()).collect();
+        for aid in aids {
+            self.publish_accessory(aid).await;
+        }
+    }
+
+    /// 运行,断开后重连
+    pub async fn run(&self) {
+        loop {
+            let (client, mut event_loop) = AsyncClient::new(self.options(), 64);
+            self.client.write().await.replace(client.clone());
+            info!("mqtt 导出连接:{}:{}", self.config.host, self.config.port);
+            let err = loop {
+                match event_loop.poll().await {
+                    Ok(Event::Incoming(Incoming::ConnAck(_))) => {
+                        // 发布需要 event_loop 继续轮询,不能在这里等待
+                        let this = self.clone();
+                        let client = client.clone();
+                        tokio::spawn(async move {
+                            this.on_connected(client).await;
+                        });
+                    }
+                    Ok(Event::Incoming(Incoming::Publish(msg))) => {
+                        let this = self.clone();
+                        tokio::spawn(async move {
+                            this.on_set(msg.topic.as_str(), msg.payload.as_ref()).await;
+                        });
+                    }
+                    Ok(_) => {}
+                    Err(e) => break e,
+                }
+            };
+            self.client.write().await.take();
+            error!("mqtt 导出连接断开:{:?}, 5秒后重连", err);
+            tokio::time::sleep(Duration::from_secs(5)).await;
+        }
+    }
+}
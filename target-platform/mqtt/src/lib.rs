@@ -0,0 +1,6 @@
+/// mqtt 导出,把hap 配件的特征值发布到mqtt
+/// 并生成 home assistant 的自动发现配置
+pub mod config;
+pub mod discovery;
+pub mod exporter;
+mod topic;
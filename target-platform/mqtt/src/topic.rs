@@ -0,0 +1,62 @@
+use target_hap::hap_type_wrapper::HapTypeWrapper;
+
+/// 特征状态主题
+pub fn state_topic(base: &str, aid: u64, stag: &str, ctag: HapTypeWrapper) -> String {
+    format!("{}/{}/{}/{}", base, aid, stag, ctag)
+}
+
+/// 特征命令主题
+pub fn set_topic(base: &str, aid: u64, stag: &str, ctag: HapTypeWrapper) -> String {
+    format!("{}/set", state_topic(base, aid, stag, ctag))
+}
+
+/// 在线状态主题
+pub fn status_topic(base: &str) -> String {
+    format!("{}/status", base)
+}
+
+/// 订阅所有命令
+pub fn set_filter(base: &str) -> String {
+    format!("{}/+/+/+/set", base)
+}
+
+/// 解析命令主题, 返回 aid,stag,ctag
+pub fn parse_set_topic(base: &str, topic: &str) -> Option<(u64, String, HapTypeWrapper)> {
+    let rest = topic.strip_prefix(base)?
+        .strip_prefix('/')?
+        .strip_suffix("/set")?;
+    let mut parts = rest.split('/');
+    let aid = parts.next()?.parse().ok()?;
+    let stag = parts.next()?.to_string();
+    let ctag = parts.next()?.parse().ok()?;
+    if parts.next().is_some() {
+        return None;
+    }
+    Some((aid, stag, ctag))
+}
+
+/// home assistant 的 node_id/object_id 只允许字母数字下划线
+pub fn object_id(s: &str) -> String {
+    s.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '_' })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use target_hap::hap_type_wrapper::HapTypeWrapper;
+
+    use crate::topic::{object_id, parse_set_topic, set_topic};
+
+    #[test]
+    fn test_set_topic() {
+        let topic = set_topic("homelink", 12, "light", HapTypeWrapper::PowerState);
+        assert_eq!(topic, "homelink/12/light/PowerState/set");
+        assert_eq!(parse_set_topic("homelink", topic.as_str()),
+                   Some((12, "light".to_string(), HapTypeWrapper::PowerState)));
+        assert_eq!(parse_set_topic("homelink", "homelink/12/light/PowerState"), None);
+        assert_eq!(parse_set_topic("homelink", "homelink/x/light/PowerState/set"), None);
+        assert_eq!(parse_set_topic("homelink", "other/12/light/PowerState/set"), None);
+        assert_eq!(object_id("Light-1.PowerState"), "light_1_powerstate");
+    }
+}